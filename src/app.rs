@@ -1,12 +1,25 @@
 #![allow(dead_code)]
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::time::Instant;
 
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc;
 
-use crate::protocol::types::{CliMessage, ContentBlock};
+use crate::control::events::EventHub;
+use crate::control::{ControlReply, ControlRequest};
+use crate::hooks::runner::HookDef;
+use crate::permissions::rules::PermissionRule;
+use crate::protocol::types::{
+    CliMessage, ContentBlock, OutgoingControlRequest, OutgoingControlResponse,
+};
+use crate::server::auth::CliTokens;
+use crate::server::ws_server::Endpoint;
+use crate::ui::diff;
+use crate::ui::highlight::ColorDepth;
+use crate::ui::renderer::RenderState;
+use crate::ui::theme::{self, Theme};
 
 // ─── Search State ───────────────────────────────────────────────────────────
 
@@ -32,6 +45,11 @@ impl InputState {
         }
     }
 
+    /// Create an input pre-filled with `text`, cursor at the start.
+    pub fn with_text(text: String) -> Self {
+        Self { text, cursor: 0 }
+    }
+
     pub fn insert_char(&mut self, c: char) {
         self.text.insert(self.cursor, c);
         self.cursor += c.len_utf8();
@@ -181,10 +199,6 @@ impl InputState {
         }
         // Find the start of the next line
         let next_newline = self.text[self.cursor..].find('\n');
-        let current_line_start = self.text[..self.cursor]
-            .rfind('\n')
-            .map(|i| i + 1)
-            .unwrap_or(0);
         if let Some(nl_offset) = next_newline {
             let next_line_start = self.cursor + nl_offset + 1;
             let next_line_end = self.text[next_line_start..]
@@ -222,6 +236,10 @@ pub struct Layout {
     pub sidebar_visible: bool,
     pub sidebar_width: u16,
     pub task_panel_visible: bool,
+    /// Task panel rows, header included
+    pub task_panel_height: u16,
+    /// A second chat pane; None shows the active session alone
+    pub split: Option<Split>,
 }
 
 impl Default for Layout {
@@ -230,10 +248,101 @@ impl Default for Layout {
             sidebar_visible: true,
             sidebar_width: 22,
             task_panel_visible: false,
+            task_panel_height: 6,
+            split: None,
+        }
+    }
+}
+
+impl Layout {
+    /// Widen (or narrow, when negative) the sidebar
+    pub fn resize_sidebar(&mut self, delta: i16) {
+        self.sidebar_width = self.sidebar_width.saturating_add_signed(delta).clamp(12, 60);
+    }
+
+    pub fn resize_task_panel(&mut self, delta: i16) {
+        self.task_panel_height = self.task_panel_height.saturating_add_signed(delta).clamp(2, 20);
+    }
+
+    /// Move the split's divider right or down (left or up when negative)
+    pub fn move_divider(&mut self, delta: i16) {
+        if let Some(split) = &mut self.split {
+            split.ratio = split.ratio.saturating_add_signed(delta).clamp(20, 80);
+        }
+    }
+
+    /// Back to the default sizes, keeping the split itself
+    pub fn reset_sizes(&mut self) {
+        let default = Layout::default();
+        self.sidebar_width = default.sidebar_width;
+        self.task_panel_height = default.task_panel_height;
+        if let Some(split) = &mut self.split {
+            split.ratio = 50;
         }
     }
 }
 
+/// Two sessions' chat areas side by side or stacked. The active session is
+/// the focused pane: keys, the input line and the banners go to it.
+pub struct Split {
+    pub direction: SplitDirection,
+    /// Session in the other pane
+    pub other: String,
+    /// Whether the focused pane is the left (or top) one
+    pub focused_first: bool,
+    /// Share of the chat area taken by the left (or top) pane, in percent
+    pub ratio: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SplitDirection {
+    /// Side by side
+    Vertical,
+    /// One above the other
+    Horizontal,
+}
+
+// ─── Chat View ──────────────────────────────────────────────────────────────
+
+/// Geometry of the last rendered chat area, for keys that move through it
+/// and for mouse clicks
+#[derive(Debug, Default)]
+pub struct ChatView {
+    pub session_id: Option<String>,
+    pub total_lines: usize,
+    pub height: usize,
+    /// Top-left corner and width on screen
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    /// Index of the line on the top row
+    pub first_line: usize,
+    /// Collapsible items (threads, tool cards and thinking blocks) by id,
+    /// with their header line
+    pub folds: Vec<(String, usize)>,
+}
+
+impl ChatView {
+    /// Whether a screen cell is in the chat area
+    pub fn contains(&self, col: u16, row: u16) -> bool {
+        (self.x..self.x + self.width).contains(&col)
+            && (self.y..self.y + self.height as u16).contains(&row)
+    }
+}
+
+/// Clickable parts of the last frame outside the chat lines
+#[derive(Debug, Default)]
+pub struct ClickTargets {
+    /// Sidebar columns (border included); 0 when hidden
+    pub sidebar_w: u16,
+    /// Session shown on each sidebar row, from the top
+    pub sidebar_rows: Vec<String>,
+    /// Permission banner buttons: row, columns, and the key a click presses
+    pub buttons: Vec<(u16, std::ops::Range<u16>, char)>,
+    /// Chat area of the split's other pane
+    pub other_pane: Option<ChatView>,
+}
+
 // ─── Task Item ──────────────────────────────────────────────────────────────
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -283,6 +392,7 @@ pub enum Command {
     Rename { name: String },
     Ls,
     Env,
+    Theme { name: String },
     Model { name: String },
     PermMode { mode: String },
     Cd { path: String },
@@ -295,9 +405,16 @@ pub enum Command {
     Unarchive { index: Option<usize> },
     Clear,
     Go { partial_name: String },
+    Split { direction: SplitDirection, partial_name: String },
+    Only,
     Export { path: String },
     Pin,
     Unpin,
+    Allow { args: String },
+    Deny { args: String },
+    Rules,
+    Unrule { index: Option<usize> },
+    Inbox,
     Help,
     Quit,
     Unknown(String),
@@ -307,10 +424,10 @@ pub enum Command {
 
 /// All events flow through this channel to the event loop
 pub enum AppEvent {
-    /// A parsed NDJSON message from the CLI
+    /// A parsed NDJSON message from the CLI (boxed: it dwarfs the other variants)
     CliMessage {
         session_id: String,
-        message: CliMessage,
+        message: Box<CliMessage>,
     },
     /// CLI connected via WebSocket — provides a sender for outgoing messages
     CliConnected {
@@ -325,6 +442,20 @@ pub enum AppEvent {
     CliProcessExited {
         session_id: String,
     },
+    /// A scripting request (`send`, `approve`, ...) from the daemon socket
+    Control {
+        request: ControlRequest,
+        reply: ControlReply,
+    },
+    /// A daemon client attached; render for its terminal
+    ClientTerminal {
+        color_depth: ColorDepth,
+        hyperlinks: bool,
+        /// Theme asked for with `--attach --theme`
+        theme: Option<String>,
+    },
+    /// Stop the event loop (daemon received SIGTERM/SIGINT)
+    Shutdown,
 }
 
 // ─── Chat Messages ──────────────────────────────────────────────────────────
@@ -343,6 +474,93 @@ pub struct ChatMessage {
     pub content_blocks: Option<Vec<ContentBlock>>,
     pub model: Option<String>,
     pub timestamp: i64,
+    /// Task tool call this subagent message belongs to (None for the main agent)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_tool_use_id: Option<String>,
+}
+
+impl ChatMessage {
+    /// A CLI `user` message that only carries tool results; they are drawn
+    /// in the tool cards of the calls they answer
+    pub fn is_tool_results(&self) -> bool {
+        matches!(self.role, ChatRole::User)
+            && self.content.is_empty()
+            && self.content_blocks.is_some()
+    }
+
+    /// This message with long tool results cut down, for writing to disk and
+    /// sending to API clients. The full output only stays in memory, for the
+    /// tool card.
+    pub fn compact(&self) -> ChatMessage {
+        let mut msg = self.clone();
+        if !self.is_tool_results() {
+            return msg;
+        }
+        for block in msg.content_blocks.iter_mut().flatten() {
+            if let ContentBlock::ToolResult { content, .. } = block {
+                if let Some(short) = truncate_tool_result(content) {
+                    *content = serde_json::Value::String(short);
+                }
+            }
+        }
+        msg
+    }
+}
+
+/// Tool result lines (and bytes) kept by `ChatMessage::compact`
+const KEPT_RESULT_LINES: usize = 40;
+const KEPT_RESULT_BYTES: usize = 4096;
+
+/// The start of a long tool result as text, or None when it is short enough
+/// to keep. Non-text parts (e.g. images) are dropped.
+fn truncate_tool_result(content: &serde_json::Value) -> Option<String> {
+    let text = crate::protocol::types::extract_tool_result_text(content);
+    let total = text.lines().count();
+    if content.is_string() && total <= KEPT_RESULT_LINES && text.len() <= KEPT_RESULT_BYTES {
+        return None;
+    }
+    let mut kept = String::new();
+    let mut lines = 0;
+    for line in text.lines().take(KEPT_RESULT_LINES) {
+        let room = KEPT_RESULT_BYTES.saturating_sub(kept.len());
+        if line.len() >= room {
+            // Cut an overlong line at a char boundary
+            let end = line
+                .char_indices()
+                .map(|(i, c)| i + c.len_utf8())
+                .take_while(|end| *end < room)
+                .last()
+                .unwrap_or(0);
+            kept.push_str(&line[..end]);
+            kept.push_str("\u{2026}\n");
+            lines += 1;
+            break;
+        }
+        kept.push_str(line);
+        kept.push('\n');
+        lines += 1;
+    }
+    if lines < total {
+        kept.push_str(&format!("\u{2026} {} more lines not kept", total - lines));
+    }
+    Some(kept.trim_end().to_string())
+}
+
+const THREAD_FOLD_PREFIX: &str = "thread:";
+
+/// Fold id of the subagent thread under a Task call (the call's own id
+/// folds its tool card)
+pub fn thread_fold_id(task_id: &str) -> String {
+    format!("{}{}", THREAD_FOLD_PREFIX, task_id)
+}
+
+const THINKING_FOLD_PREFIX: &str = "thinking:";
+
+/// Fold id of a thinking block. Blocks carry no id, so it comes from the text.
+pub fn thinking_fold_id(thinking: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    thinking.hash(&mut hasher);
+    format!("{}{:016x}", THINKING_FOLD_PREFIX, hasher.finish())
 }
 
 // ─── Session Status ─────────────────────────────────────────────────────────
@@ -355,15 +573,56 @@ pub enum SessionStatus {
     Compacting,
 }
 
+// ─── Stream Blocks ──────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamBlockKind {
+    Text,
+    Thinking,
+    ToolUse { name: String },
+}
+
+/// A content block of the assistant message currently streaming
+#[derive(Debug, Clone)]
+pub struct StreamBlock {
+    /// Block index within the message (from content_block_start)
+    pub index: u64,
+    pub kind: StreamBlockKind,
+    /// Text, thinking, or the tool input JSON received so far
+    pub content: String,
+    /// content_block_stop received
+    pub done: bool,
+}
+
 // ─── Pending Permission ─────────────────────────────────────────────────────
 
 #[derive(Debug, Clone)]
 pub struct PendingPermission {
     pub request_id: String,
+    pub tool_use_id: String,
     pub tool_name: String,
     pub input: serde_json::Value,
     pub description: Option<String>,
     pub permission_suggestions: Option<Vec<serde_json::Value>>,
+    /// Subagent that issued the request (None for the main agent)
+    pub agent_id: Option<String>,
+}
+
+/// Inline prompt opened from the permission banner
+pub enum PermissionPrompt {
+    /// Editing the tool input (pretty-printed JSON) before approving
+    EditInput(InputState),
+    /// One-line reason sent to Claude as the deny message
+    DenyReason(InputState),
+    /// Confirming a bulk approve/deny of every queued request
+    ConfirmAll,
+}
+
+/// A control request sent to the CLI, awaiting its control_response
+#[derive(Debug, Clone)]
+pub enum PendingControl {
+    SetModel { model: String },
+    SetPermissionMode { mode: String },
 }
 
 // ─── Pending Question (AskUserQuestion) ─────────────────────────────────────
@@ -388,6 +647,31 @@ pub struct QuestionOption {
     pub description: String,
 }
 
+// ─── Inbox ──────────────────────────────────────────────────────────────────
+
+/// Something a session is blocked on, as listed in the cross-session inbox
+#[derive(Debug, Clone, PartialEq)]
+pub enum InboxItem {
+    /// Index into the session's permission queue
+    Permission { session_id: String, index: usize },
+    Question { session_id: String },
+}
+
+impl InboxItem {
+    pub fn session_id(&self) -> &str {
+        match self {
+            InboxItem::Permission { session_id, .. } | InboxItem::Question { session_id } => {
+                session_id
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct InboxState {
+    pub selected: usize,
+}
+
 // ─── Slash Command Menu ─────────────────────────────────────────────────────
 
 #[derive(Debug, Clone)]
@@ -446,6 +730,8 @@ pub struct PersistedSession {
     pub archived: bool,
     #[serde(default)]
     pub pinned: bool,
+    #[serde(default)]
+    pub permission_rules: Vec<PermissionRule>,
 }
 
 // ─── Session ────────────────────────────────────────────────────────────────
@@ -475,6 +761,9 @@ pub struct Session {
     pub context_used_percent: u32,
     /// Chat message history
     pub messages: Vec<ChatMessage>,
+    /// Bumped when `messages` is cleared, so their laid-out lines aren't
+    /// reused for the messages that follow
+    pub history_generation: u32,
     /// Accumulated streaming text (from content_block_delta events)
     pub streaming_text: String,
     /// Session status
@@ -483,8 +772,12 @@ pub struct Session {
     pub cli_connected: bool,
     /// Channel sender for outgoing messages to CLI
     pub cli_sender: Option<mpsc::UnboundedSender<String>>,
-    /// Pending permission request
-    pub pending_permission: Option<PendingPermission>,
+    /// Pending permission requests, answered front first
+    pub pending_permissions: VecDeque<PendingPermission>,
+    /// Inline prompt open on the pending permission (edit input / deny reason)
+    pub permission_prompt: Option<PermissionPrompt>,
+    /// Our control requests awaiting a reply (and when they were sent), by request_id
+    pub pending_controls: HashMap<String, (PendingControl, Instant)>,
     /// Whether an interrupt was sent (double Ctrl+C to quit)
     pub interrupt_sent: bool,
     /// Available tools (from system/init)
@@ -503,8 +796,12 @@ pub struct Session {
     pub dirty_persist: bool,
     /// Messages queued to send once CLI connects (for resume / pre-connect)
     pub queued_messages: Vec<String>,
-    /// Last assistant message ID seen (for deduplication)
-    pub last_assistant_msg_id: Option<String>,
+    /// Last assistant message ID seen per parent_tool_use_id (None for the
+    /// main agent), for deduplication
+    pub last_assistant_msg_ids: HashMap<Option<String>, String>,
+    /// File line each edit of an Edit/MultiEdit call starts at, by tool_use_id
+    /// (found when the call arrives, before it changes the file)
+    pub edit_start_lines: HashMap<String, Vec<Option<usize>>>,
     /// Git branch name
     pub git_branch: String,
     /// Whether this session is in a git worktree
@@ -527,20 +824,38 @@ pub struct Session {
     pub archived: bool,
     /// Previous permission mode (for plan mode toggle restore)
     pub previous_permission_mode: Option<String>,
+    /// Mode sent via :mode (and when), applied once the CLI acknowledges it
+    pub requested_permission_mode: Option<(String, Instant)>,
     /// Current active tool (from ToolProgress, cleared on result)
     pub current_tool: Option<(String, f64)>,
     /// Pending AskUserQuestion
     pub pending_question: Option<PendingQuestion>,
     /// Streaming start time (for elapsed calculation)
     pub stream_start: Option<std::time::Instant>,
+    /// Content blocks of the message being streamed, in arrival order
+    pub stream_blocks: Vec<StreamBlock>,
     /// Output tokens accumulated during current stream
     pub stream_output_tokens: u64,
+    /// `stream_output_tokens` is the CLI's count (message_delta), not an estimate
+    pub stream_tokens_exact: bool,
     /// Whether scroll is locked (user scrolled up, don't auto-scroll)
     pub scroll_locked: bool,
-    /// Whether tool results are collapsed
+    /// Whether tool cards are collapsed by default
     pub tool_results_collapsed: bool,
+    /// Current tool of each running subagent thread, by parent Task id
+    pub thread_progress: HashMap<String, (String, f64)>,
+    /// Latest elapsed seconds reported for each tool call, by tool_use id
+    pub tool_durations: HashMap<String, f64>,
+    /// Threads and tool cards toggled away from their default state
+    pub toggled_folds: HashSet<String>,
+    /// Thread or tool card header selected with `{`/`}`
+    pub selected_fold: Option<String>,
     /// Whether this session is pinned (sorts to top of sidebar)
     pub pinned: bool,
+    /// Session-scoped permission rules (checked before global rules)
+    pub permission_rules: Vec<PermissionRule>,
+    /// `send --wait` requests, answered with the next result message
+    pub turn_waiters: Vec<ControlReply>,
 }
 
 impl Session {
@@ -558,11 +873,14 @@ impl Session {
             num_turns: 0,
             context_used_percent: 0,
             messages: Vec::new(),
+            history_generation: 0,
             streaming_text: String::new(),
             status: SessionStatus::WaitingForCli,
             cli_connected: false,
             cli_sender: None,
-            pending_permission: None,
+            pending_permissions: VecDeque::new(),
+            permission_prompt: None,
+            pending_controls: HashMap::new(),
             interrupt_sent: false,
             tools: Vec::new(),
             scroll_offset: 0,
@@ -572,7 +890,8 @@ impl Session {
             cli_spawn_time: None,
             dirty_persist: false,
             queued_messages: Vec::new(),
-            last_assistant_msg_id: None,
+            last_assistant_msg_ids: HashMap::new(),
+            edit_start_lines: HashMap::new(),
             git_branch: String::new(),
             is_worktree: false,
             repo_root: String::new(),
@@ -584,13 +903,22 @@ impl Session {
             skills: Vec::new(),
             archived: false,
             previous_permission_mode: None,
+            requested_permission_mode: None,
             current_tool: None,
             pending_question: None,
             stream_start: None,
+            stream_blocks: Vec::new(),
             stream_output_tokens: 0,
+            stream_tokens_exact: false,
             scroll_locked: false,
             tool_results_collapsed: false,
+            thread_progress: HashMap::new(),
+            tool_durations: HashMap::new(),
+            toggled_folds: HashSet::new(),
+            selected_fold: None,
             pinned: false,
+            permission_rules: Vec::new(),
+            turn_waiters: Vec::new(),
         }
     }
 
@@ -603,6 +931,85 @@ impl Session {
         }
     }
 
+    /// Send a control request and remember it until the CLI replies
+    pub fn send_control(&mut self, req: OutgoingControlRequest, pending: PendingControl) {
+        self.send_to_cli(&req.to_ndjson());
+        self.pending_controls.insert(req.request_id, (pending, Instant::now()));
+    }
+
+    /// Ask the CLI to switch permission mode; `permission_mode` follows once
+    /// it acknowledges
+    pub fn request_permission_mode(&mut self, mode: &str) {
+        let req = OutgoingControlRequest::set_permission_mode(mode);
+        let pending = PendingControl::SetPermissionMode { mode: mode.to_string() };
+        self.send_control(req, pending);
+        self.requested_permission_mode = Some((mode.to_string(), Instant::now()));
+    }
+
+    /// Number of permission requests and questions waiting on the user
+    pub fn waiting_count(&self) -> usize {
+        self.pending_permissions.len() + usize::from(self.pending_question.is_some())
+    }
+
+    /// Answer the queued permission at `index`: approve it as requested, or
+    /// deny it with `deny_message`. Returns the answered request.
+    pub fn answer_permission(
+        &mut self,
+        index: usize,
+        deny_message: Option<&str>,
+    ) -> Option<PendingPermission> {
+        let perm = self.pending_permissions.remove(index)?;
+        if index == 0 {
+            self.permission_prompt = None;
+        }
+        let resp = match deny_message {
+            None => OutgoingControlResponse::allow(perm.request_id.clone(), perm.input.clone()),
+            Some(message) => OutgoingControlResponse::deny(perm.request_id.clone(), message),
+        };
+        self.send_to_cli(&resp.to_ndjson());
+        let verb = if deny_message.is_none() { "approved" } else { "denied" };
+        self.add_system_message(format!("[{}] {}", verb, perm.tool_name));
+        Some(perm)
+    }
+
+    /// Drop the partially streamed message (it arrived in full, or the turn
+    /// was reset)
+    pub fn clear_stream(&mut self) {
+        self.streaming_text.clear();
+        self.stream_blocks.clear();
+    }
+
+    /// Whether a fold is collapsed: threads and thinking blocks start
+    /// collapsed, tool cards follow `tool_results_collapsed`
+    pub fn is_fold_collapsed(&self, id: &str, default: bool) -> bool {
+        default != self.toggled_folds.contains(id)
+    }
+
+    /// `z`: collapse or expand every tool card, dropping per-card toggles
+    pub fn set_tool_results_collapsed(&mut self, collapsed: bool) {
+        self.tool_results_collapsed = collapsed;
+        self.toggled_folds.retain(|id| {
+            id.starts_with(THREAD_FOLD_PREFIX) || id.starts_with(THINKING_FOLD_PREFIX)
+        });
+    }
+
+    pub fn toggle_fold(&mut self, id: &str) {
+        if !self.toggled_folds.remove(id) {
+            self.toggled_folds.insert(id.to_string());
+        }
+    }
+
+    /// Note where the edits of a file-editing tool call start, the first
+    /// time the call is seen (the file may have been changed by it since)
+    pub fn locate_edits(&mut self, tool_use_id: &str, name: &str, input: &serde_json::Value) {
+        let known = self.edit_start_lines.contains_key(tool_use_id);
+        if known || !matches!(name, "Edit" | "MultiEdit") {
+            return;
+        }
+        let lines = diff::edit_start_lines(name, input, &self.cwd);
+        self.edit_start_lines.insert(tool_use_id.to_string(), lines);
+    }
+
     /// Add a system message to the chat
     pub fn add_system_message(&mut self, content: String) {
         self.messages.push(ChatMessage {
@@ -611,6 +1018,7 @@ impl Session {
             content_blocks: None,
             model: None,
             timestamp: chrono::Utc::now().timestamp(),
+            parent_tool_use_id: None,
         });
         self.scroll_offset = 0;
     }
@@ -628,12 +1036,13 @@ impl Session {
             total_cost_usd: self.total_cost_usd,
             num_turns: self.num_turns,
             context_used_percent: self.context_used_percent,
-            messages: self.messages.clone(),
+            messages: self.messages.iter().map(ChatMessage::compact).collect(),
             tools: self.tools.clone(),
             tasks: self.tasks.clone(),
             created_at: self.created_at,
             archived: self.archived,
             pinned: self.pinned,
+            permission_rules: self.permission_rules.clone(),
         }
     }
 
@@ -651,11 +1060,14 @@ impl Session {
             num_turns: p.num_turns,
             context_used_percent: p.context_used_percent,
             messages: p.messages,
+            history_generation: 0,
             streaming_text: String::new(),
             status: SessionStatus::WaitingForCli,
             cli_connected: false,
             cli_sender: None,
-            pending_permission: None,
+            pending_permissions: VecDeque::new(),
+            permission_prompt: None,
+            pending_controls: HashMap::new(),
             interrupt_sent: false,
             tools: p.tools,
             scroll_offset: 0,
@@ -665,7 +1077,8 @@ impl Session {
             cli_spawn_time: None,
             dirty_persist: false,
             queued_messages: Vec::new(),
-            last_assistant_msg_id: None,
+            last_assistant_msg_ids: HashMap::new(),
+            edit_start_lines: HashMap::new(),
             git_branch: String::new(),
             is_worktree: false,
             repo_root: String::new(),
@@ -678,12 +1091,21 @@ impl Session {
             archived: p.archived,
             pinned: p.pinned,
             previous_permission_mode: None,
+            requested_permission_mode: None,
             current_tool: None,
             pending_question: None,
             stream_start: None,
+            stream_blocks: Vec::new(),
             stream_output_tokens: 0,
+            stream_tokens_exact: false,
             scroll_locked: false,
             tool_results_collapsed: false,
+            thread_progress: HashMap::new(),
+            tool_durations: HashMap::new(),
+            toggled_folds: HashSet::new(),
+            selected_fold: None,
+            permission_rules: p.permission_rules,
+            turn_waiters: Vec::new(),
         }
     }
 
@@ -722,12 +1144,15 @@ pub struct App {
     pub should_quit: bool,
     pub dirty: bool,
     pub env_profiles: Vec<EnvProfile>,
-    pub ws_port: u16,
+    /// Where the CLI bridge listens (known once the server is bound)
+    pub endpoint: Endpoint,
     pub default_cwd: String,
     pub default_model: Option<String>,
     pub flash_message: Option<(String, Instant)>,
     /// For 'gg' double-key scroll to top in Normal mode
     pub gg_pending: bool,
+    /// Ctrl+W was pressed in Normal mode; the next key is a pane command
+    pub window_pending: bool,
     /// Session IDs that need a CLI process spawned
     pub pending_spawns: Vec<String>,
     /// Tick counter for spinner animation
@@ -750,10 +1175,43 @@ pub struct App {
     pub command_history_idx: Option<usize>,
     /// Draft text saved when entering command history browsing
     pub command_history_draft: String,
+    /// Global permission rules (~/.companion/permissions.json)
+    pub permission_rules: Vec<PermissionRule>,
+    /// Companion-side hooks (~/.companion/hooks/)
+    pub hooks: Vec<HookDef>,
+    /// Colors everything is drawn with
+    pub theme: Theme,
+    /// Custom themes (~/.companion/themes/)
+    pub themes: Vec<Theme>,
+    /// Cross-session inbox overlay (None when closed)
+    pub inbox: Option<InboxState>,
+    /// Chat area as last rendered
+    pub chat_view: ChatView,
+    /// Sidebar rows and banner buttons as last rendered
+    pub click_targets: ClickTargets,
+    /// Whether the terminal reports mouse events (off leaves text selection
+    /// to the terminal)
+    pub mouse_capture: bool,
+    /// Frame on screen and cached chat layout, kept between renders
+    pub render_state: RenderState,
+    /// Launch tokens the WebSocket server checks CLI handshakes against
+    pub cli_tokens: CliTokens,
+    /// Running as a headless daemon (quit detaches the client instead)
+    pub daemon_mode: bool,
+    /// Last size reported by a resize event (the attached client's terminal
+    /// in daemon mode); None means ask the local terminal
+    pub screen_size: Option<(u16, u16)>,
+    /// Colors of the terminal being drawn on (the attached client's in
+    /// daemon mode)
+    pub color_depth: ColorDepth,
+    /// Whether that terminal renders OSC 8 hyperlinks
+    pub hyperlinks: bool,
+    /// Change feed for `/ws/client` API subscribers
+    pub events: EventHub,
 }
 
 impl App {
-    pub fn new(ws_port: u16, cwd: String, model: Option<String>) -> Self {
+    pub fn new(endpoint: Endpoint, cwd: String, model: Option<String>) -> Self {
         Self {
             sessions: HashMap::new(),
             active_session_id: None,
@@ -765,11 +1223,12 @@ impl App {
             should_quit: false,
             dirty: true,
             env_profiles: Vec::new(),
-            ws_port,
+            endpoint,
             default_cwd: cwd,
             default_model: model,
             flash_message: None,
             gg_pending: false,
+            window_pending: false,
             pending_spawns: Vec::new(),
             tick: 0,
             show_thinking: false,
@@ -781,6 +1240,21 @@ impl App {
             command_history: Vec::new(),
             command_history_idx: None,
             command_history_draft: String::new(),
+            permission_rules: Vec::new(),
+            hooks: Vec::new(),
+            theme: Theme::dark(),
+            themes: Vec::new(),
+            inbox: None,
+            chat_view: ChatView::default(),
+            click_targets: ClickTargets::default(),
+            mouse_capture: true,
+            render_state: RenderState::default(),
+            cli_tokens: CliTokens::default(),
+            daemon_mode: false,
+            screen_size: None,
+            color_depth: ColorDepth::detect(),
+            hyperlinks: crate::ui::markdown::detect_hyperlinks(),
+            events: EventHub::new(),
         }
     }
 
@@ -814,13 +1288,35 @@ impl App {
         id
     }
 
+    /// Make `id` the active session. The split's other session takes the
+    /// focus from its pane instead, so the split stays on screen.
     pub fn switch_to_session(&mut self, id: &str) -> bool {
-        if self.sessions.contains_key(id) {
+        if !self.sessions.contains_key(id) {
+            return false;
+        }
+        if self.split_session().is_some_and(|s| s.id == id) {
+            self.focus_other_pane();
+        } else {
             self.active_session_id = Some(id.to_string());
+        }
+        self.dirty = true;
+        true
+    }
+
+    /// Kill the session's CLI process (if any) and queue a fresh spawn.
+    pub fn restart_cli(&mut self, id: &str) {
+        if let Some(session) = self.sessions.get_mut(id) {
+            if let Some(handle) = session.cli_process_handle.take() {
+                handle.abort();
+            }
+            session.cli_connected = false;
+            session.cli_sender = None;
+            session.pending_controls.clear();
+            session.requested_permission_mode = None;
+            session.turn_waiters.clear();
+            session.status = SessionStatus::WaitingForCli;
+            self.pending_spawns.push(id.to_string());
             self.dirty = true;
-            true
-        } else {
-            false
         }
     }
 
@@ -841,8 +1337,7 @@ impl App {
             if let Some(pos) = visible.iter().position(|id| id == active_id) {
                 let next = (pos + 1) % visible.len();
                 let id = visible[next].clone();
-                self.active_session_id = Some(id);
-                self.dirty = true;
+                self.switch_to_session(&id);
             }
         }
     }
@@ -860,10 +1355,60 @@ impl App {
                     pos - 1
                 };
                 let id = visible[prev].clone();
-                self.active_session_id = Some(id);
-                self.dirty = true;
+                self.switch_to_session(&id);
+            }
+        }
+    }
+
+    /// The session after the active one in the sidebar, if there is another
+    pub fn next_session_id(&self) -> Option<String> {
+        let visible = self.visible_session_order();
+        let pos = visible
+            .iter()
+            .position(|id| Some(id.as_str()) == self.active_session_id.as_deref())?;
+        (visible.len() > 1).then(|| visible[(pos + 1) % visible.len()].clone())
+    }
+
+    /// Session in the split's other pane, while the split is open and that
+    /// session isn't the active one
+    pub fn split_session(&self) -> Option<&Session> {
+        let split = self.layout.split.as_ref()?;
+        if self.active_session_id.as_deref() == Some(split.other.as_str()) {
+            return None;
+        }
+        self.sessions.get(&split.other)
+    }
+
+    /// Show `other` next to the active session, opening the split or
+    /// changing what it shows
+    pub fn open_split(&mut self, direction: SplitDirection, other: String) {
+        match &mut self.layout.split {
+            Some(split) => {
+                split.direction = direction;
+                split.other = other;
+            }
+            None => {
+                self.layout.split = Some(Split {
+                    direction,
+                    other,
+                    focused_first: true,
+                    ratio: 50,
+                })
             }
         }
+        self.dirty = true;
+    }
+
+    /// Move focus to the split's other pane; its session becomes active
+    pub fn focus_other_pane(&mut self) {
+        if self.split_session().is_none() {
+            return;
+        }
+        if let (Some(split), Some(active)) = (&mut self.layout.split, &mut self.active_session_id) {
+            std::mem::swap(&mut split.other, active);
+            split.focused_first = !split.focused_first;
+            self.dirty = true;
+        }
     }
 
     pub fn kill_active_session(&mut self) {
@@ -873,6 +1418,7 @@ impl App {
                     handle.abort();
                 }
                 session.delete_persisted();
+                self.cli_tokens.revoke(&id);
             }
             self.session_order.retain(|s| s != &id);
             // Switch to first non-archived session, or first session as fallback
@@ -933,6 +1479,55 @@ impl App {
         }
     }
 
+    pub fn load_permission_rules(&mut self) {
+        self.permission_rules = crate::permissions::rules::load_global_rules();
+    }
+
+    pub fn save_permission_rules(&self) {
+        if let Err(e) = crate::permissions::rules::save_global_rules(&self.permission_rules) {
+            tracing::error!("Failed to save permission rules: {}", e);
+        }
+    }
+
+    pub fn load_hooks(&mut self) {
+        self.hooks = crate::hooks::runner::load_hooks();
+    }
+
+    pub fn load_themes(&mut self) {
+        self.themes = theme::load_custom();
+    }
+
+    /// Names of every theme: built-in ones first, then custom ones not
+    /// replacing a built-in
+    pub fn theme_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = theme::BUILTIN.iter().map(|n| n.to_string()).collect();
+        for custom in &self.themes {
+            if !names.contains(&custom.name) {
+                names.push(custom.name.clone());
+            }
+        }
+        names
+    }
+
+    /// Switch to the theme called `name` (a custom theme of the same name
+    /// takes precedence over a built-in one); false when there is none
+    pub fn set_theme(&mut self, name: &str) -> bool {
+        let found = self
+            .themes
+            .iter()
+            .find(|t| t.name == name)
+            .cloned()
+            .or_else(|| Theme::builtin(name));
+        match found {
+            Some(theme) => {
+                self.theme = theme;
+                self.dirty = true;
+                true
+            }
+            None => false,
+        }
+    }
+
     pub fn load_persisted_sessions(&mut self) {
         let dir = dirs::home_dir()
             .unwrap_or_else(|| std::path::PathBuf::from("."))
@@ -1038,6 +1633,25 @@ impl App {
         visible
     }
 
+    /// Everything any session is waiting on, in sidebar order
+    pub fn inbox_items(&self) -> Vec<InboxItem> {
+        let mut items = Vec::new();
+        for id in self.visible_session_order() {
+            if let Some(session) = self.sessions.get(&id) {
+                for index in 0..session.pending_permissions.len() {
+                    items.push(InboxItem::Permission {
+                        session_id: id.clone(),
+                        index,
+                    });
+                }
+                if session.pending_question.is_some() {
+                    items.push(InboxItem::Question { session_id: id });
+                }
+            }
+        }
+        items
+    }
+
     pub fn get_env_vars(&self, profile_name: &str) -> HashMap<String, String> {
         self.env_profiles
             .iter()
@@ -1128,3 +1742,159 @@ pub fn gather_git_info(cwd: &str) -> GitInfo {
 
     info
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::ws_server::Endpoint;
+    use serde_json::{json, Value};
+
+    fn results(content: Value) -> ChatMessage {
+        ChatMessage {
+            role: ChatRole::User,
+            content: String::new(),
+            content_blocks: Some(vec![ContentBlock::ToolResult {
+                tool_use_id: "t1".to_string(),
+                content,
+                is_error: false,
+            }]),
+            model: None,
+            timestamp: 0,
+            parent_tool_use_id: None,
+        }
+    }
+
+    fn kept(msg: &ChatMessage) -> Value {
+        match &msg.compact().content_blocks.unwrap()[0] {
+            ContentBlock::ToolResult { content, .. } => content.clone(),
+            other => panic!("unexpected block {:?}", other),
+        }
+    }
+
+    fn numbered(n: usize) -> String {
+        (1..=n).map(|i| format!("line {}", i)).collect::<Vec<_>>().join("\n")
+    }
+
+    #[test]
+    fn short_results_are_kept_whole() {
+        let text = numbered(KEPT_RESULT_LINES);
+        assert_eq!(kept(&results(json!(text))), json!(text));
+    }
+
+    #[test]
+    fn only_tool_result_messages_are_compacted() {
+        let mut msg = results(json!(numbered(100)));
+        msg.content = "text".to_string();
+        assert_eq!(kept(&msg), json!(numbered(100)));
+    }
+
+    #[test]
+    fn long_results_keep_their_first_lines() {
+        let content = kept(&results(json!(numbered(100))));
+        let text = content.as_str().unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), KEPT_RESULT_LINES + 1);
+        assert_eq!(lines[0], "line 1");
+        assert_eq!(lines[KEPT_RESULT_LINES - 1], "line 40");
+        assert_eq!(lines[KEPT_RESULT_LINES], "\u{2026} 60 more lines not kept");
+    }
+
+    #[test]
+    fn wide_results_are_cut_at_a_char_boundary() {
+        let line = "é".repeat(3000);
+        let content = kept(&results(json!(format!("{}\nnext", line))));
+        let text = content.as_str().unwrap();
+        let (first, rest) = text.split_once('\n').unwrap();
+        assert!(first.len() <= KEPT_RESULT_BYTES + "\u{2026}".len());
+        assert!(first.ends_with("é\u{2026}"));
+        assert_eq!(rest, "\u{2026} 1 more lines not kept");
+    }
+
+    #[test]
+    fn array_results_become_text() {
+        let content = json!([
+            {"type": "text", "text": "first"},
+            {"type": "image", "source": {"type": "base64", "data": "AAAA"}},
+            {"type": "text", "text": "second"},
+        ]);
+        assert_eq!(kept(&results(content)), json!("first\nsecond"));
+    }
+
+    fn app_with_sessions(ids: &[&str]) -> App {
+        let mut app = App::new(Endpoint::Tcp(0), "/tmp".to_string(), None);
+        for id in ids {
+            let session = Session::new(id.to_string(), id.to_string(), "/tmp".to_string());
+            app.sessions.insert(id.to_string(), session);
+            app.session_order.push(id.to_string());
+        }
+        app.active_session_id = ids.first().map(|id| id.to_string());
+        app
+    }
+
+    #[test]
+    fn layout_sizes_stay_in_bounds() {
+        let mut layout = Layout::default();
+        layout.resize_sidebar(-100);
+        assert_eq!(layout.sidebar_width, 12);
+        layout.resize_sidebar(100);
+        assert_eq!(layout.sidebar_width, 60);
+        layout.resize_task_panel(-100);
+        assert_eq!(layout.task_panel_height, 2);
+        layout.resize_task_panel(100);
+        assert_eq!(layout.task_panel_height, 20);
+
+        // Without a split there is no divider to move
+        layout.move_divider(10);
+        assert!(layout.split.is_none());
+        layout.split = Some(Split {
+            direction: SplitDirection::Vertical,
+            other: "s2".to_string(),
+            focused_first: true,
+            ratio: 50,
+        });
+        layout.move_divider(-100);
+        assert_eq!(layout.split.as_ref().unwrap().ratio, 20);
+        layout.move_divider(100);
+        assert_eq!(layout.split.as_ref().unwrap().ratio, 80);
+
+        layout.reset_sizes();
+        assert_eq!(layout.sidebar_width, 22);
+        assert_eq!(layout.task_panel_height, 6);
+        assert_eq!(layout.split.as_ref().unwrap().ratio, 50);
+    }
+
+    #[test]
+    fn focus_moves_to_the_other_pane() {
+        let mut app = app_with_sessions(&["s1", "s2"]);
+        app.open_split(SplitDirection::Vertical, "s2".to_string());
+        app.focus_other_pane();
+        assert_eq!(app.active_session_id.as_deref(), Some("s2"));
+        let split = app.layout.split.as_ref().unwrap();
+        assert_eq!(split.other, "s1");
+        assert!(!split.focused_first);
+
+        app.focus_other_pane();
+        assert_eq!(app.active_session_id.as_deref(), Some("s1"));
+        assert!(app.layout.split.as_ref().unwrap().focused_first);
+    }
+
+    #[test]
+    fn switching_to_the_other_panes_session_keeps_the_split() {
+        let mut app = app_with_sessions(&["s1", "s2", "s3"]);
+        app.open_split(SplitDirection::Horizontal, "s2".to_string());
+        assert!(app.switch_to_session("s2"));
+        assert_eq!(app.active_session_id.as_deref(), Some("s2"));
+        assert_eq!(app.split_session().map(|s| s.id.as_str()), Some("s1"));
+
+        // Another session replaces the focused pane's
+        assert!(app.switch_to_session("s3"));
+        assert_eq!(app.split_session().map(|s| s.id.as_str()), Some("s1"));
+        // Cycling onto the other pane's session swaps too
+        app.prev_session();
+        assert_eq!(app.active_session_id.as_deref(), Some("s2"));
+        assert_eq!(app.split_session().map(|s| s.id.as_str()), Some("s1"));
+        app.prev_session();
+        assert_eq!(app.active_session_id.as_deref(), Some("s1"));
+        assert_eq!(app.split_session().map(|s| s.id.as_str()), Some("s2"));
+    }
+}
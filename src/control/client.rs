@@ -0,0 +1,183 @@
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::Value;
+use tokio::io::{AsyncRead, AsyncWrite};
+#[cfg(unix)]
+use tokio::net::UnixStream;
+use tokio::sync::oneshot;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+use crate::app::App;
+use crate::control::{self, ApiRequest, ControlRequest};
+#[cfg(unix)]
+use crate::daemon::{socket_path, ClientMessage, DaemonMessage};
+use crate::server::ws_server::Endpoint;
+
+/// Run one scripting request and print its JSON response on stdout.
+/// Returns whether the response was `ok`.
+///
+/// Tries the daemon socket first, then the `/ws/client` API of a companion
+/// running in a terminal (found through `~/.companion/api/`).
+pub async fn run(request: ControlRequest) -> anyhow::Result<bool> {
+    let response = match request_daemon(&request).await? {
+        Some(response) => response,
+        None => match request_api(&request).await? {
+            Some(response) => response,
+            None if request.is_read_only() => run_offline(request),
+            None => control::error_response(
+                "No companion running (start one, or run it in the background with --daemon)",
+            ),
+        },
+    };
+    println!("{}", serde_json::to_string_pretty(&response)?);
+    Ok(response.get("ok").and_then(|v| v.as_bool()).unwrap_or(false))
+}
+
+/// Ask the daemon, if one is listening on its socket
+#[cfg(unix)]
+async fn request_daemon(request: &ControlRequest) -> anyhow::Result<Option<Value>> {
+    let stream = match UnixStream::connect(socket_path()).await {
+        Ok(stream) => stream,
+        Err(_) => return Ok(None),
+    };
+    let (ws_stream, _) = tokio_tungstenite::client_async("ws://localhost/control", stream).await?;
+    let (mut ws_write, mut ws_read) = ws_stream.split();
+
+    let msg = ClientMessage::Request {
+        id: 1,
+        request: request.clone(),
+    };
+    ws_write.send(Message::text(serde_json::to_string(&msg)?)).await?;
+
+    while let Some(msg) = ws_read.next().await {
+        if let Message::Text(text) = msg? {
+            if let Ok(DaemonMessage::Response { id: 1, response }) = serde_json::from_str(&text) {
+                let _ = ws_write.close().await;
+                return Ok(Some(response));
+            }
+        }
+    }
+    anyhow::bail!("Daemon closed the connection without answering")
+}
+
+/// There is no daemon off Unix
+#[cfg(not(unix))]
+async fn request_daemon(_request: &ControlRequest) -> anyhow::Result<Option<Value>> {
+    Ok(None)
+}
+
+// ─── Control API ────────────────────────────────────────────────────────────
+
+/// Ask the newest companion that still answers on `/ws/client`. Returns None
+/// when none is reachable; discovery files left by crashed ones are skipped.
+async fn request_api(request: &ControlRequest) -> anyhow::Result<Option<Value>> {
+    let msg = ApiRequest {
+        id: 1,
+        request: request.clone(),
+    };
+    let msg = serde_json::to_string(&msg)?;
+    for (url, token) in discover_apis() {
+        let bearer: http::HeaderValue = format!("Bearer {}", token).parse()?;
+        // `ws+unix://<socket>:<path>`, see `Endpoint::url`
+        let unix = url.strip_prefix("ws+unix://").and_then(|rest| rest.rsplit_once(':'));
+        let err = match unix {
+            #[cfg(unix)]
+            Some((socket, path)) => {
+                let mut req = format!("ws://localhost{}", path).into_client_request()?;
+                req.headers_mut().insert(http::header::AUTHORIZATION, bearer);
+                match UnixStream::connect(socket).await {
+                    Ok(stream) => match tokio_tungstenite::client_async(req, stream).await {
+                        Ok((ws_stream, _)) => return exchange(ws_stream, msg).await.map(Some),
+                        Err(e) => e.to_string(),
+                    },
+                    Err(e) => e.to_string(),
+                }
+            }
+            #[cfg(not(unix))]
+            Some(_) => "Unix sockets are not supported on this platform".to_string(),
+            None => {
+                let mut req = url.as_str().into_client_request()?;
+                req.headers_mut().insert(http::header::AUTHORIZATION, bearer);
+                match tokio_tungstenite::connect_async(req).await {
+                    Ok((ws_stream, _)) => return exchange(ws_stream, msg).await.map(Some),
+                    Err(e) => e.to_string(),
+                }
+            }
+        };
+        tracing::debug!("Companion API {} unreachable: {}", url, err);
+    }
+    Ok(None)
+}
+
+/// Send one API request and wait for its response, skipping the events
+/// streamed on the same connection
+async fn exchange<S>(ws_stream: WebSocketStream<S>, msg: String) -> anyhow::Result<Value>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (mut ws_write, mut ws_read) = ws_stream.split();
+    ws_write.send(Message::text(msg)).await?;
+
+    while let Some(msg) = ws_read.next().await {
+        if let Message::Text(text) = msg? {
+            let value: Value = match serde_json::from_str(&text) {
+                Ok(v) => v,
+                Err(_) => continue,
+            };
+            if value.get("type").and_then(|t| t.as_str()) == Some("response")
+                && value.get("id").and_then(|i| i.as_u64()) == Some(1)
+            {
+                let _ = ws_write.close().await;
+                return Ok(value.get("response").cloned().unwrap_or(Value::Null));
+            }
+        }
+    }
+    anyhow::bail!("Companion closed the connection without answering")
+}
+
+/// `(url, token)` from `~/.companion/api/*.json`, newest first
+fn discover_apis() -> Vec<(String, String)> {
+    let dir = dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(".companion")
+        .join("api");
+    let mut found: Vec<(SystemTime, String, String)> = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.extension().map(|e| e == "json").unwrap_or(false))
+            .filter_map(|path| {
+                let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+                let data = std::fs::read_to_string(&path).ok()?;
+                let info: Value = serde_json::from_str(&data).ok()?;
+                let url = info.get("url")?.as_str()?.to_string();
+                let token = info.get("token")?.as_str()?.to_string();
+                Some((modified, url, token))
+            })
+            .collect(),
+        Err(_) => return Vec::new(),
+    };
+    found.sort_by_key(|(modified, _, _)| std::cmp::Reverse(*modified));
+    found.into_iter().map(|(_, url, token)| (url, token)).collect()
+}
+
+// ─── Offline ────────────────────────────────────────────────────────────────
+
+/// Answer a read-only request from `~/.companion/sessions/`
+fn run_offline(request: ControlRequest) -> Value {
+    let cwd = std::env::current_dir()
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut app = App::new(Endpoint::Tcp(0), cwd, None);
+    app.load_persisted_sessions();
+
+    let (reply_tx, mut reply_rx) = oneshot::channel();
+    control::handle(&mut app, request, reply_tx);
+    reply_rx
+        .try_recv()
+        .unwrap_or_else(|_| control::error_response("No response"))
+}
@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+use tokio::sync::broadcast;
+
+use crate::app::Session;
+use crate::control::{session_summary, status_name};
+
+/// Events buffered per `/ws/client` subscriber before it counts as lagging
+const EVENT_BUFFER: usize = 1024;
+
+/// What subscribers last heard about a session
+struct Seen {
+    status: &'static str,
+    connected: bool,
+    messages: usize,
+    permissions: Vec<String>,
+}
+
+/// Feeds the `/ws/client` event stream. Rather than hooking every place that
+/// touches a session, the event loop calls `publish_changes` after each
+/// iteration and the hub diffs sessions against what it last published.
+pub struct EventHub {
+    tx: broadcast::Sender<String>,
+    seen: HashMap<String, Seen>,
+}
+
+impl Default for EventHub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventHub {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(EVENT_BUFFER);
+        Self {
+            tx,
+            seen: HashMap::new(),
+        }
+    }
+
+    /// For the server to subscribe new API connections
+    pub fn sender(&self) -> broadcast::Sender<String> {
+        self.tx.clone()
+    }
+
+    /// Emit `session_added`/`session_removed`, `status`, `message`,
+    /// `messages_cleared` and `permission_request`/`permission_resolved`
+    /// events for whatever changed since the last call. Tracking continues
+    /// without subscribers so a new one doesn't get replayed history.
+    pub fn publish_changes(&mut self, sessions: &HashMap<String, Session>) {
+        let mut events = Vec::new();
+
+        self.seen.retain(|id, _| {
+            let keep = sessions.contains_key(id);
+            if !keep {
+                events.push(json!({ "event": "session_removed", "session": id }));
+            }
+            keep
+        });
+
+        for (id, session) in sessions {
+            let status = status_name(&session.status);
+            let seen = self.seen.entry(id.clone()).or_insert_with(|| {
+                events.push(json!({
+                    "event": "session_added",
+                    "session": session_summary(session, None),
+                }));
+                Seen {
+                    status,
+                    connected: session.cli_connected,
+                    messages: session.messages.len(),
+                    permissions: session
+                        .pending_permissions
+                        .iter()
+                        .map(|p| p.request_id.clone())
+                        .collect(),
+                }
+            });
+
+            if seen.status != status || seen.connected != session.cli_connected {
+                seen.status = status;
+                seen.connected = session.cli_connected;
+                events.push(json!({
+                    "event": "status",
+                    "session": id,
+                    "status": status,
+                    "connected": session.cli_connected,
+                }));
+            }
+
+            if session.messages.len() < seen.messages {
+                // :clear
+                seen.messages = 0;
+                events.push(json!({ "event": "messages_cleared", "session": id }));
+            }
+            for message in &session.messages[seen.messages..] {
+                let message = message.compact();
+                events.push(json!({ "event": "message", "session": id, "message": message }));
+            }
+            seen.messages = session.messages.len();
+
+            let permissions_changed = seen.permissions.len() != session.pending_permissions.len()
+                || seen
+                    .permissions
+                    .iter()
+                    .zip(&session.pending_permissions)
+                    .any(|(seen_id, perm)| seen_id != &perm.request_id);
+            if permissions_changed {
+                for perm in &session.pending_permissions {
+                    if !seen.permissions.contains(&perm.request_id) {
+                        events.push(json!({
+                            "event": "permission_request",
+                            "session": id,
+                            "request_id": perm.request_id,
+                            "tool": perm.tool_name,
+                            "input": perm.input,
+                            "description": perm.description,
+                            "agent_id": perm.agent_id,
+                        }));
+                    }
+                }
+                for request_id in &seen.permissions {
+                    if !session.pending_permissions.iter().any(|p| &p.request_id == request_id) {
+                        events.push(json!({
+                            "event": "permission_resolved",
+                            "session": id,
+                            "request_id": request_id,
+                        }));
+                    }
+                }
+                seen.permissions = session
+                    .pending_permissions
+                    .iter()
+                    .map(|p| p.request_id.clone())
+                    .collect();
+            }
+        }
+
+        if self.tx.receiver_count() == 0 {
+            return;
+        }
+        for mut event in events {
+            event["type"] = Value::String("event".to_string());
+            let _ = self.tx.send(event.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{PendingPermission, SessionStatus};
+
+    fn session(id: &str) -> Session {
+        Session::new(id.to_string(), "test".to_string(), "/tmp".to_string())
+    }
+
+    fn permission(request_id: &str) -> PendingPermission {
+        PendingPermission {
+            request_id: request_id.to_string(),
+            tool_use_id: format!("toolu_{}", request_id),
+            tool_name: "Bash".to_string(),
+            input: json!({ "command": "ls" }),
+            description: None,
+            permission_suggestions: None,
+            agent_id: None,
+        }
+    }
+
+    /// The `event` names published since the last call
+    fn drain(rx: &mut broadcast::Receiver<String>) -> Vec<String> {
+        let mut names = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            let event: Value = serde_json::from_str(&event).unwrap();
+            assert_eq!(event["type"], "event");
+            names.push(event["event"].as_str().unwrap().to_string());
+        }
+        names
+    }
+
+    #[test]
+    fn a_new_session_is_announced_without_replaying_its_messages() {
+        let mut hub = EventHub::new();
+        let mut rx = hub.sender().subscribe();
+        let mut sessions = HashMap::new();
+        let mut s = session("a");
+        s.add_system_message("before".to_string());
+        sessions.insert("a".to_string(), s);
+
+        hub.publish_changes(&sessions);
+        assert_eq!(drain(&mut rx), ["session_added"]);
+
+        sessions.get_mut("a").unwrap().add_system_message("after".to_string());
+        hub.publish_changes(&sessions);
+        assert_eq!(drain(&mut rx), ["message"]);
+
+        hub.publish_changes(&sessions);
+        assert!(drain(&mut rx).is_empty());
+    }
+
+    #[test]
+    fn status_and_connection_changes_are_published_once() {
+        let mut hub = EventHub::new();
+        let mut rx = hub.sender().subscribe();
+        let mut sessions = HashMap::from([("a".to_string(), session("a"))]);
+        hub.publish_changes(&sessions);
+        drain(&mut rx);
+
+        let s = sessions.get_mut("a").unwrap();
+        s.status = SessionStatus::Running;
+        s.cli_connected = true;
+        hub.publish_changes(&sessions);
+        hub.publish_changes(&sessions);
+        assert_eq!(drain(&mut rx), ["status"]);
+    }
+
+    #[test]
+    fn clearing_messages_is_reported_before_new_ones() {
+        let mut hub = EventHub::new();
+        let mut rx = hub.sender().subscribe();
+        let mut s = session("a");
+        s.add_system_message("one".to_string());
+        s.add_system_message("two".to_string());
+        let mut sessions = HashMap::from([("a".to_string(), s)]);
+        hub.publish_changes(&sessions);
+        drain(&mut rx);
+
+        let s = sessions.get_mut("a").unwrap();
+        s.messages.clear();
+        s.add_system_message("three".to_string());
+        hub.publish_changes(&sessions);
+        assert_eq!(drain(&mut rx), ["messages_cleared", "message"]);
+    }
+
+    #[test]
+    fn permission_queue_changes_become_requests_and_resolutions() {
+        let mut hub = EventHub::new();
+        let mut rx = hub.sender().subscribe();
+        let mut sessions = HashMap::from([("a".to_string(), session("a"))]);
+        hub.publish_changes(&sessions);
+        drain(&mut rx);
+
+        let s = sessions.get_mut("a").unwrap();
+        s.pending_permissions.push_back(permission("r1"));
+        s.pending_permissions.push_back(permission("r2"));
+        hub.publish_changes(&sessions);
+        assert_eq!(drain(&mut rx), ["permission_request", "permission_request"]);
+
+        // Answering the first and queueing a third in the same iteration
+        let s = sessions.get_mut("a").unwrap();
+        s.pending_permissions.pop_front();
+        s.pending_permissions.push_back(permission("r3"));
+        hub.publish_changes(&sessions);
+        assert_eq!(drain(&mut rx), ["permission_request", "permission_resolved"]);
+    }
+
+    #[test]
+    fn removed_sessions_are_announced() {
+        let mut hub = EventHub::new();
+        let mut rx = hub.sender().subscribe();
+        let mut sessions = HashMap::from([("a".to_string(), session("a"))]);
+        hub.publish_changes(&sessions);
+        drain(&mut rx);
+
+        sessions.clear();
+        hub.publish_changes(&sessions);
+        assert_eq!(drain(&mut rx), ["session_removed"]);
+    }
+
+    #[test]
+    fn a_late_subscriber_gets_no_history() {
+        let mut hub = EventHub::new();
+        let mut s = session("a");
+        s.pending_permissions.push_back(permission("r1"));
+        let mut sessions = HashMap::from([("a".to_string(), s)]);
+        hub.publish_changes(&sessions);
+
+        let mut rx = hub.sender().subscribe();
+        hub.publish_changes(&sessions);
+        assert!(drain(&mut rx).is_empty());
+
+        sessions.get_mut("a").unwrap().add_system_message("new".to_string());
+        hub.publish_changes(&sessions);
+        assert_eq!(drain(&mut rx), ["message"]);
+    }
+}
@@ -0,0 +1,285 @@
+//! Scripting requests (`ls`, `export`, `send`, `approve`, `interrupt`)
+//! answered with JSON.
+//!
+//! Requests reach a running daemon over its socket, or any companion over the
+//! authenticated `/ws/client` API, and are executed inside the event loop
+//! (`AppEvent::Control`), so they see exactly what the TUI sees. Without a
+//! daemon, `ls` and `export` run against the sessions persisted in
+//! `~/.companion/sessions/`.
+
+pub mod client;
+pub mod events;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::oneshot;
+
+use crate::app::{App, Session, SessionStatus};
+use crate::protocol::types::{format_tool_summary, OutgoingControlRequest};
+use crate::ui::event_loop;
+
+/// A scripting request. Sessions are named by id, unique id prefix, name,
+/// or sidebar number.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum ControlRequest {
+    /// All sessions with status and pending requests
+    Ls,
+    /// One session including its full message history
+    Export { session: String },
+    /// Send a prompt; with `wait`, reply once the turn's result arrives
+    Send {
+        session: String,
+        text: String,
+        #[serde(default)]
+        wait: bool,
+    },
+    /// Answer a pending permission request; `deny` carries the deny message
+    Approve {
+        request_id: String,
+        #[serde(default)]
+        deny: Option<String>,
+    },
+    /// Interrupt the session's running turn
+    Interrupt { session: String },
+}
+
+impl ControlRequest {
+    /// Whether the request can be answered from persisted sessions alone
+    pub fn is_read_only(&self) -> bool {
+        matches!(self, ControlRequest::Ls | ControlRequest::Export { .. })
+    }
+}
+
+/// Where the JSON response for a request goes
+pub type ControlReply = oneshot::Sender<Value>;
+
+/// A request on a `/ws/client` connection; `id` is echoed in the response
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiRequest {
+    pub id: u64,
+    #[serde(flatten)]
+    pub request: ControlRequest,
+}
+
+/// `{"ok": true, ...fields}`
+pub fn ok_response(mut fields: Value) -> Value {
+    if let Some(obj) = fields.as_object_mut() {
+        obj.insert("ok".to_string(), Value::Bool(true));
+    }
+    fields
+}
+
+/// `{"ok": false, "error": message}`
+pub fn error_response(message: impl Into<String>) -> Value {
+    json!({ "ok": false, "error": message.into() })
+}
+
+/// Execute a request against the app. Most requests reply immediately;
+/// `send --wait` parks the reply on the session until its turn finishes.
+pub fn handle(app: &mut App, request: ControlRequest, reply: ControlReply) {
+    let response = match request {
+        ControlRequest::Ls => {
+            let sessions: Vec<Value> = ordered_sessions(app)
+                .into_iter()
+                .map(|(number, session)| session_summary(session, number))
+                .collect();
+            ok_response(json!({ "sessions": sessions }))
+        }
+        ControlRequest::Export { session } => match find_session(app, &session) {
+            Ok(id) => {
+                let session = &app.sessions[&id];
+                match serde_json::to_value(session.to_persisted()) {
+                    Ok(value) => ok_response(json!({ "session": value })),
+                    Err(e) => error_response(format!("Failed to serialize session: {}", e)),
+                }
+            }
+            Err(e) => error_response(e),
+        },
+        ControlRequest::Send {
+            session,
+            text,
+            wait,
+        } => match find_session(app, &session) {
+            Ok(_) if text.trim().is_empty() => error_response("Empty prompt"),
+            Ok(id) => {
+                event_loop::send_text(app, &id, text);
+                if wait {
+                    if let Some(session) = app.sessions.get_mut(&id) {
+                        session.turn_waiters.push(reply);
+                    }
+                    return;
+                }
+                ok_response(json!({ "session": id }))
+            }
+            Err(e) => error_response(e),
+        },
+        ControlRequest::Approve { request_id, deny } => approve(app, &request_id, deny.as_deref()),
+        ControlRequest::Interrupt { session } => match find_session(app, &session) {
+            Ok(id) => interrupt(app, &id),
+            Err(e) => error_response(e),
+        },
+    };
+    let _ = reply.send(response);
+}
+
+/// Shortest request id prefix `approve` accepts. Shorter ones (and "")
+/// would grant whatever happens to be pending without naming it.
+const MIN_REQUEST_ID_PREFIX: usize = 4;
+
+fn approve(app: &mut App, request_id: &str, deny: Option<&str>) -> Value {
+    if request_id.is_empty() {
+        return error_response("Missing request id");
+    }
+    let mut matches = Vec::new();
+    for (id, session) in &app.sessions {
+        for (index, perm) in session.pending_permissions.iter().enumerate() {
+            let exact = perm.request_id == request_id;
+            if exact
+                || (request_id.len() >= MIN_REQUEST_ID_PREFIX
+                    && perm.request_id.starts_with(request_id))
+            {
+                matches.push((id.clone(), index, exact));
+            }
+        }
+    }
+    // An exact id wins over prefix matches
+    if let Some(exact) = matches.iter().position(|m| m.2) {
+        matches = vec![matches.swap_remove(exact)];
+    }
+    let (session_id, index) = match matches.as_slice() {
+        [] if request_id.len() < MIN_REQUEST_ID_PREFIX => {
+            return error_response(format!(
+                "No pending permission request {} (prefixes need at least {} characters)",
+                request_id, MIN_REQUEST_ID_PREFIX
+            ))
+        }
+        [] => return error_response(format!("No pending permission request {}", request_id)),
+        [(session_id, index, _)] => (session_id.clone(), *index),
+        _ => return error_response(format!("Ambiguous request id {}", request_id)),
+    };
+
+    let session = match app.sessions.get_mut(&session_id) {
+        Some(s) => s,
+        None => return error_response("Session vanished"),
+    };
+    match session.answer_permission(index, deny) {
+        Some(perm) => {
+            app.dirty = true;
+            ok_response(json!({
+                "session": session_id,
+                "request_id": perm.request_id,
+                "tool": perm.tool_name,
+                "approved": deny.is_none(),
+            }))
+        }
+        None => error_response(format!("No pending permission request {}", request_id)),
+    }
+}
+
+fn interrupt(app: &mut App, session_id: &str) -> Value {
+    let session = match app.sessions.get_mut(session_id) {
+        Some(s) => s,
+        None => return error_response("Session vanished"),
+    };
+    if session.status != SessionStatus::Running {
+        return error_response(format!("Session {} is not running", session.name));
+    }
+    if !session.send_to_cli(&OutgoingControlRequest::interrupt().to_ndjson()) {
+        return error_response(format!("Session {} has no connected CLI", session.name));
+    }
+    session.interrupt_sent = true;
+    session.add_system_message("Interrupt sent".to_string());
+    app.dirty = true;
+    ok_response(json!({ "session": session_id }))
+}
+
+/// Visible sessions in sidebar order (numbered from 1), then archived ones
+fn ordered_sessions(app: &App) -> Vec<(Option<usize>, &Session)> {
+    let visible = app.visible_session_order();
+    let mut out: Vec<(Option<usize>, &Session)> = visible
+        .iter()
+        .enumerate()
+        .filter_map(|(i, id)| app.sessions.get(id).map(|s| (Some(i + 1), s)))
+        .collect();
+    for id in &app.session_order {
+        if let Some(session) = app.sessions.get(id) {
+            if session.archived {
+                out.push((None, session));
+            }
+        }
+    }
+    out
+}
+
+/// Resolve a session reference: sidebar number, exact id or name, then a
+/// unique id or name prefix.
+pub fn find_session(app: &App, query: &str) -> Result<String, String> {
+    if let Ok(n) = query.parse::<usize>() {
+        if let Some(id) = app.visible_session_order().get(n.wrapping_sub(1)) {
+            return Ok(id.clone());
+        }
+    }
+    if app.sessions.contains_key(query) {
+        return Ok(query.to_string());
+    }
+    if let Some(session) = app.sessions.values().find(|s| s.name == query) {
+        return Ok(session.id.clone());
+    }
+    let prefixed: Vec<&Session> = app
+        .sessions
+        .values()
+        .filter(|s| s.id.starts_with(query) || s.name.starts_with(query))
+        .collect();
+    match prefixed.as_slice() {
+        [session] => Ok(session.id.clone()),
+        [] => Err(format!("No session matching '{}'", query)),
+        _ => Err(format!("Ambiguous session '{}' ({} matches)", query, prefixed.len())),
+    }
+}
+
+/// Status, counters and pending requests of a session (no message history)
+pub fn session_summary(session: &Session, number: Option<usize>) -> Value {
+    let permissions: Vec<Value> = session
+        .pending_permissions
+        .iter()
+        .map(|perm| {
+            json!({
+                "request_id": perm.request_id,
+                "tool": perm.tool_name,
+                "summary": format_tool_summary(&perm.tool_name, &perm.input),
+                "description": perm.description,
+                "agent_id": perm.agent_id,
+            })
+        })
+        .collect();
+    json!({
+        "id": session.id,
+        "number": number,
+        "name": session.name,
+        "cwd": session.cwd,
+        "model": session.model,
+        "permission_mode": session.permission_mode,
+        "status": status_name(&session.status),
+        "connected": session.cli_connected,
+        "archived": session.archived,
+        "pinned": session.pinned,
+        "git_branch": session.git_branch,
+        "total_cost_usd": session.total_cost_usd,
+        "num_turns": session.num_turns,
+        "context_used_percent": session.context_used_percent,
+        "message_count": session.messages.iter().filter(|m| !m.is_tool_results()).count(),
+        "created_at": session.created_at,
+        "pending_permissions": permissions,
+        "pending_question": session.pending_question.is_some(),
+    })
+}
+
+pub fn status_name(status: &SessionStatus) -> &'static str {
+    match status {
+        SessionStatus::WaitingForCli => "waiting_for_cli",
+        SessionStatus::Idle => "idle",
+        SessionStatus::Running => "running",
+        SessionStatus::Compacting => "compacting",
+    }
+}
@@ -0,0 +1,92 @@
+use std::io::Write;
+
+use crossterm::event::{
+    Event, EventStream, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseEventKind,
+};
+use crossterm::terminal;
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::UnixStream;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::daemon::{socket_path, ClientMessage, DaemonMessage};
+use crate::ui::highlight::ColorDepth;
+use crate::ui::markdown;
+use crate::ui::event_loop::TermGuard;
+
+/// Attach this terminal to the running daemon until detached.
+/// Ctrl+\ detaches locally; `:q` asks the daemon to detach us.
+pub async fn attach(theme: Option<String>) -> anyhow::Result<()> {
+    let path = socket_path();
+    let stream = UnixStream::connect(&path).await.map_err(|e| {
+        anyhow::anyhow!(
+            "No companion daemon at {} ({}). Start one with --daemon",
+            path.display(),
+            e
+        )
+    })?;
+    let (ws_stream, _) = tokio_tungstenite::client_async("ws://localhost/attach", stream).await?;
+    let (mut ws_write, mut ws_read) = ws_stream.split();
+
+    let guard = TermGuard::enter()?;
+    let mut stdout = std::io::stdout();
+
+    let terminal_info = ClientMessage::Terminal {
+        color_depth: ColorDepth::detect(),
+        hyperlinks: markdown::detect_hyperlinks(),
+        theme,
+    };
+    ws_write.send(Message::text(serde_json::to_string(&terminal_info)?)).await?;
+
+    // The first resize tells the daemon our size and triggers a full redraw
+    let (cols, rows) = terminal::size()?;
+    ws_write.send(event_message(Event::Resize(cols, rows))).await?;
+
+    let mut term_reader = EventStream::new();
+    let reason = loop {
+        tokio::select! {
+            maybe_event = term_reader.next() => match maybe_event {
+                Some(Ok(Event::Key(key))) if is_detach_key(&key) => break "detached".to_string(),
+                // The daemon only acts on clicks and the wheel
+                Some(Ok(Event::Mouse(mouse))) if mouse.kind == MouseEventKind::Moved => {}
+                Some(Ok(event)) => {
+                    if ws_write.send(event_message(event)).await.is_err() {
+                        break "connection lost".to_string();
+                    }
+                }
+                Some(Err(e)) => break format!("terminal error: {}", e),
+                None => break "terminal closed".to_string(),
+            },
+            maybe_msg = ws_read.next() => match maybe_msg {
+                Some(Ok(Message::Binary(frame))) => {
+                    stdout.write_all(&frame)?;
+                    stdout.flush()?;
+                }
+                Some(Ok(Message::Text(text))) => {
+                    if let Ok(DaemonMessage::Detach { reason }) = serde_json::from_str(&text) {
+                        break reason;
+                    }
+                }
+                Some(Ok(Message::Close(_))) | None => break "daemon closed the connection".to_string(),
+                Some(Err(e)) => break format!("connection error: {}", e),
+                Some(Ok(_)) => {}
+            },
+        }
+    };
+
+    let _ = ws_write.close().await;
+    drop(guard);
+    println!("[{}]", reason);
+    Ok(())
+}
+
+fn event_message(event: Event) -> Message {
+    let msg = ClientMessage::Event { event };
+    Message::text(serde_json::to_string(&msg).expect("serialization of client message cannot fail"))
+}
+
+/// Ctrl+\ (reported as Ctrl+4 by some terminals)
+fn is_detach_key(key: &KeyEvent) -> bool {
+    key.kind == KeyEventKind::Press
+        && key.modifiers.contains(KeyModifiers::CONTROL)
+        && matches!(key.code, KeyCode::Char('\\') | KeyCode::Char('4'))
+}
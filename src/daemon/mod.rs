@@ -0,0 +1,61 @@
+//! Headless daemon mode: the `App`, `WsServer` and CLI processes keep running
+//! in the background while TUI clients attach and detach over a local Unix
+//! socket (WebSocket framing, like the CLI bridge).
+//!
+//! The daemon renders frames itself and ships the raw terminal output to the
+//! attached client as binary messages; the client forwards its terminal
+//! events back as JSON text messages.
+//!
+//! TUI clients connect on `/attach`. Scripting clients connect on `/control`
+//! and exchange `Request`/`Response` messages without taking over the screen.
+
+pub mod client;
+pub mod server;
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::control::ControlRequest;
+use crate::ui::highlight::ColorDepth;
+
+/// Client → daemon
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// A terminal event (key, resize) from the client's terminal
+    Event { event: crossterm::event::Event },
+    /// What the client's terminal supports, sent once on attach
+    Terminal {
+        color_depth: ColorDepth,
+        #[serde(default)]
+        hyperlinks: bool,
+        /// Theme to draw with; None keeps the daemon's
+        #[serde(default)]
+        theme: Option<String>,
+    },
+    /// A scripting request (control connections only)
+    Request { id: u64, request: ControlRequest },
+}
+
+/// Daemon → client (terminal output is sent as binary messages)
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DaemonMessage {
+    /// The client should exit (quit requested, or another client attached)
+    Detach { reason: String },
+    /// The JSON answer to the `Request` with the same id
+    Response { id: u64, response: serde_json::Value },
+}
+
+/// Attach socket: `<runtime dir>/companion/daemon.sock`, falling back to
+/// `~/.companion/daemon.sock`.
+pub fn socket_path() -> PathBuf {
+    match dirs::runtime_dir() {
+        Some(dir) => dir.join("companion").join("daemon.sock"),
+        None => dirs::home_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(".companion")
+            .join("daemon.sock"),
+    }
+}
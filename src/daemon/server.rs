@@ -0,0 +1,353 @@
+use std::io::Write;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crossterm::event::Event;
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{mpsc, oneshot};
+use tokio_tungstenite::tungstenite::handshake::server::{Callback, ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+use crate::app::{App, AppEvent};
+use crate::control;
+use crate::daemon::{socket_path, ClientMessage, DaemonMessage};
+use crate::ui::event_loop::{self, Screen};
+
+// ─── Handshake ──────────────────────────────────────────────────────────────
+
+/// Accepts every upgrade, remembering the request path for routing
+struct RecordPath(Arc<Mutex<String>>);
+
+impl Callback for RecordPath {
+    fn on_request(self, req: &Request, resp: Response) -> Result<Response, ErrorResponse> {
+        *self.0.lock().unwrap() = req.uri().path().to_string();
+        Ok(resp)
+    }
+}
+
+// ─── Attached Client ────────────────────────────────────────────────────────
+
+struct AttachedClient {
+    id: u64,
+    tx: mpsc::UnboundedSender<Message>,
+}
+
+impl AttachedClient {
+    fn detach(&self, reason: &str) {
+        let msg = DaemonMessage::Detach {
+            reason: reason.to_string(),
+        };
+        let json = serde_json::to_string(&msg).expect("serialization of daemon message cannot fail");
+        let _ = self.tx.send(Message::text(json));
+    }
+}
+
+/// The one client allowed to draw and type at a time
+type CurrentClient = Arc<Mutex<Option<AttachedClient>>>;
+
+/// Frames for the attached client, buffered until flush
+struct ClientScreen {
+    buf: Vec<u8>,
+    client: CurrentClient,
+}
+
+impl Write for ClientScreen {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        let frame = std::mem::take(&mut self.buf);
+        if let Some(client) = self.client.lock().unwrap().as_ref() {
+            let _ = client.tx.send(Message::binary(frame));
+        }
+        Ok(())
+    }
+}
+
+impl Screen for ClientScreen {
+    fn attached(&self) -> bool {
+        self.client.lock().unwrap().is_some()
+    }
+
+    fn detach(&mut self) {
+        if let Some(client) = self.client.lock().unwrap().take() {
+            client.detach("detached");
+        }
+    }
+}
+
+// ─── Daemon ─────────────────────────────────────────────────────────────────
+
+/// Re-launch this binary as a detached daemon (`--foreground` added) in its
+/// own process group, so closing the terminal doesn't take it down.
+pub fn spawn_background() -> anyhow::Result<()> {
+    let path = socket_path();
+    if std::os::unix::net::UnixStream::connect(&path).is_ok() {
+        anyhow::bail!("A companion daemon is already running ({})", path.display());
+    }
+
+    let exe = std::env::current_exe()?;
+    let child = std::process::Command::new(exe)
+        .args(std::env::args().skip(1))
+        .arg("--foreground")
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .process_group(0)
+        .spawn()?;
+
+    println!(
+        "Companion daemon started (pid {}). Attach with: claude-code-companion --attach",
+        child.id()
+    );
+    Ok(())
+}
+
+/// Serve attach clients and run the event loop until SIGTERM/SIGINT.
+pub async fn run(
+    mut app: App,
+    event_rx: mpsc::UnboundedReceiver<AppEvent>,
+    event_tx: mpsc::UnboundedSender<AppEvent>,
+) -> anyhow::Result<()> {
+    let path = socket_path();
+    let listener = bind(&path).await?;
+    tracing::info!("Daemon listening for clients on {}", path.display());
+    app.daemon_mode = true;
+
+    let current: CurrentClient = Arc::new(Mutex::new(None));
+    let (input_tx, input_rx) = mpsc::unbounded_channel();
+    tokio::spawn(accept_clients(listener, current.clone(), input_tx, event_tx.clone()));
+    spawn_signal_handler(event_tx.clone());
+
+    let mut screen = ClientScreen {
+        buf: Vec::new(),
+        client: current.clone(),
+    };
+    let result = event_loop::run_headless(app, &mut screen, input_rx, event_rx, event_tx).await;
+
+    if let Some(client) = current.lock().unwrap().take() {
+        client.detach("daemon stopped");
+    }
+    let _ = std::fs::remove_file(&path);
+    tracing::info!("Daemon stopped");
+    result
+}
+
+/// Bind the attach socket (0600 in a 0700 dir), replacing a stale one.
+async fn bind(path: &Path) -> anyhow::Result<UnixListener> {
+    if let Some(dir) = path.parent() {
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)?;
+    }
+    if path.exists() {
+        if UnixStream::connect(path).await.is_ok() {
+            anyhow::bail!("A companion daemon is already running ({})", path.display());
+        }
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// Stop on SIGTERM/SIGINT; ignore SIGHUP so a closing terminal can't kill us.
+fn spawn_signal_handler(event_tx: mpsc::UnboundedSender<AppEvent>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    tokio::spawn(async move {
+        let (mut term, mut int, mut hup) = match (
+            signal(SignalKind::terminate()),
+            signal(SignalKind::interrupt()),
+            signal(SignalKind::hangup()),
+        ) {
+            (Ok(term), Ok(int), Ok(hup)) => (term, int, hup),
+            _ => {
+                tracing::error!("Failed to install daemon signal handlers");
+                return;
+            }
+        };
+        loop {
+            tokio::select! {
+                _ = term.recv() => break,
+                _ = int.recv() => break,
+                _ = hup.recv() => tracing::info!("Ignoring SIGHUP"),
+            }
+        }
+        let _ = event_tx.send(AppEvent::Shutdown);
+    });
+}
+
+async fn accept_clients(
+    listener: UnixListener,
+    current: CurrentClient,
+    input_tx: mpsc::UnboundedSender<Event>,
+    event_tx: mpsc::UnboundedSender<AppEvent>,
+) {
+    let mut next_id = 0u64;
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                tracing::error!("Daemon accept error: {}", e);
+                continue;
+            }
+        };
+        next_id += 1;
+        let id = next_id;
+        let current = current.clone();
+        let input_tx = input_tx.clone();
+        let event_tx = event_tx.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_client(stream, id, current, input_tx, event_tx).await {
+                tracing::error!("Client {} error: {}", id, e);
+            }
+        });
+    }
+}
+
+async fn handle_client(
+    stream: UnixStream,
+    id: u64,
+    current: CurrentClient,
+    input_tx: mpsc::UnboundedSender<Event>,
+    event_tx: mpsc::UnboundedSender<AppEvent>,
+) -> anyhow::Result<()> {
+    let path = Arc::new(Mutex::new(String::new()));
+    let callback = RecordPath(path.clone());
+    let ws_stream = match tokio_tungstenite::accept_hdr_async(stream, callback).await {
+        Ok(s) => s,
+        Err(e) => {
+            // e.g. a liveness probe from spawn_background
+            tracing::debug!("Client {} handshake failed: {}", id, e);
+            return Ok(());
+        }
+    };
+    if *path.lock().unwrap() == "/control" {
+        return handle_control(ws_stream, id, event_tx).await;
+    }
+    let (mut ws_write, mut ws_read) = ws_stream.split();
+
+    // Latest attach wins, tmux-style
+    let (tx, mut rx) = mpsc::unbounded_channel::<Message>();
+    if let Some(old) = current.lock().unwrap().replace(AttachedClient { id, tx }) {
+        old.detach("attached from another terminal");
+    }
+    tracing::info!("Client {} attached", id);
+
+    // Write task: frames and control messages → client. Ends once this
+    // client's sender is dropped (detached or replaced).
+    tokio::spawn(async move {
+        while let Some(msg) = rx.recv().await {
+            if ws_write.send(msg).await.is_err() {
+                break;
+            }
+        }
+        let _ = ws_write.close().await;
+    });
+
+    // Read task: client terminal events → event loop
+    while let Some(Ok(msg)) = ws_read.next().await {
+        match msg {
+            Message::Text(text) => match serde_json::from_str::<ClientMessage>(&text) {
+                Ok(ClientMessage::Request { .. }) => {
+                    tracing::warn!("Client {} sent a request on an attach connection", id);
+                }
+                Ok(ClientMessage::Event { event }) => {
+                    let is_current = current.lock().unwrap().as_ref().map(|c| c.id) == Some(id);
+                    if is_current {
+                        let _ = input_tx.send(event);
+                    }
+                }
+                Ok(ClientMessage::Terminal {
+                    color_depth,
+                    hyperlinks,
+                    theme,
+                }) => {
+                    let _ = event_tx.send(AppEvent::ClientTerminal {
+                        color_depth,
+                        hyperlinks,
+                        theme,
+                    });
+                }
+                Err(e) => tracing::warn!("Bad message from client {}: {}", id, e),
+            },
+            Message::Close(_) => break,
+            _ => {}
+        }
+    }
+
+    // Forget the client unless another one has attached since
+    let mut guard = current.lock().unwrap();
+    if guard.as_ref().map(|c| c.id) == Some(id) {
+        *guard = None;
+    }
+    tracing::info!("Client {} detached", id);
+    Ok(())
+}
+
+/// Scripting connection: each request runs in the event loop and its answer
+/// is sent back as a `Response`. Requests may complete out of order
+/// (`send --wait`).
+async fn handle_control(
+    ws_stream: WebSocketStream<UnixStream>,
+    id: u64,
+    event_tx: mpsc::UnboundedSender<AppEvent>,
+) -> anyhow::Result<()> {
+    tracing::debug!("Control client {} connected", id);
+    let (mut ws_write, mut ws_read) = ws_stream.split();
+
+    let (tx, mut rx) = mpsc::unbounded_channel::<DaemonMessage>();
+    let write_handle = tokio::spawn(async move {
+        while let Some(msg) = rx.recv().await {
+            let json = serde_json::to_string(&msg).expect("serialization of daemon message cannot fail");
+            if ws_write.send(Message::text(json)).await.is_err() {
+                break;
+            }
+        }
+        let _ = ws_write.close().await;
+    });
+
+    while let Some(Ok(msg)) = ws_read.next().await {
+        match msg {
+            Message::Text(text) => match serde_json::from_str::<ClientMessage>(&text) {
+                Ok(ClientMessage::Request { id: request_id, request }) => {
+                    let (reply_tx, reply_rx) = oneshot::channel();
+                    let _ = event_tx.send(AppEvent::Control {
+                        request,
+                        reply: reply_tx,
+                    });
+                    let tx = tx.clone();
+                    tokio::spawn(async move {
+                        // Dropped without an answer: the session went away
+                        let response = reply_rx.await.unwrap_or_else(|_| {
+                            control::error_response("No result (the CLI exited or the session was closed)")
+                        });
+                        let _ = tx.send(DaemonMessage::Response {
+                            id: request_id,
+                            response,
+                        });
+                    });
+                }
+                Ok(ClientMessage::Event { .. }) | Ok(ClientMessage::Terminal { .. }) => {
+                    tracing::warn!("Control client {} sent a terminal message", id);
+                }
+                Err(e) => tracing::warn!("Bad message from control client {}: {}", id, e),
+            },
+            Message::Close(_) => break,
+            _ => {}
+        }
+    }
+
+    // Answers still in flight have nowhere to go
+    write_handle.abort();
+    tracing::debug!("Control client {} disconnected", id);
+    Ok(())
+}
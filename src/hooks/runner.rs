@@ -0,0 +1,266 @@
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+
+use serde::Deserialize;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::mpsc;
+
+use crate::protocol::types::OutgoingControlResponse;
+
+const DEFAULT_TIMEOUT_SECS: u64 = 60;
+
+// ─── Hook Definition ────────────────────────────────────────────────────────
+
+/// A companion-side hook loaded from ~/.companion/hooks/<name>.json.
+/// Either runs `command` with the hook input on stdin and returns its JSON
+/// stdout, or returns the static `response`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct HookDef {
+    #[serde(skip)]
+    pub name: String,
+    /// Exact callback_id to match (any if omitted)
+    pub callback_id: Option<String>,
+    /// Hook event name to match, e.g. "PreToolUse" (any if omitted)
+    pub event: Option<String>,
+    /// Tool name to match for tool events (any if omitted)
+    pub tool: Option<String>,
+    /// Shell command to run
+    pub command: Option<String>,
+    /// Static response returned when there is no command
+    pub response: Option<serde_json::Value>,
+    pub timeout_secs: Option<u64>,
+}
+
+impl HookDef {
+    pub fn matches(&self, callback_id: &str, input: &serde_json::Value) -> bool {
+        let field = |key: &str| input.get(key).and_then(|v| v.as_str());
+        self.callback_id.as_deref().is_none_or(|id| id == callback_id)
+            && self
+                .event
+                .as_deref()
+                .is_none_or(|e| field("hook_event_name") == Some(e))
+            && self.tool.as_deref().is_none_or(|t| field("tool_name") == Some(t))
+    }
+}
+
+/// Load hook definitions from ~/.companion/hooks/*.json, sorted by file name
+pub fn load_hooks() -> Vec<HookDef> {
+    let dir = dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(".companion")
+        .join("hooks");
+    let mut paths: Vec<PathBuf> = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.extension().map(|e| e == "json").unwrap_or(false))
+            .collect(),
+        Err(_) => return Vec::new(),
+    };
+    paths.sort();
+
+    let mut hooks = Vec::new();
+    for path in paths {
+        let data = match std::fs::read_to_string(&path) {
+            Ok(d) => d,
+            Err(_) => continue,
+        };
+        match serde_json::from_str::<HookDef>(&data) {
+            Ok(mut hook) => {
+                hook.name = path
+                    .file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string();
+                hooks.push(hook);
+            }
+            Err(e) => {
+                tracing::warn!("Failed to parse hook {:?}: {}", path, e);
+            }
+        }
+    }
+    hooks
+}
+
+// ─── Hook Execution ─────────────────────────────────────────────────────────
+
+/// Answer a hook_callback control request. Runs in a spawned task so a slow
+/// script doesn't block the UI; any failure falls back to an empty (no-op)
+/// hook output so the CLI never hangs.
+pub fn respond(
+    hook: Option<HookDef>,
+    request_id: String,
+    input: serde_json::Value,
+    cwd: String,
+    sender: mpsc::UnboundedSender<String>,
+) {
+    tokio::spawn(async move {
+        let output = match hook {
+            Some(hook) => match run_hook(&hook, &input, &cwd).await {
+                Ok(output) => output,
+                Err(e) => {
+                    tracing::warn!("Hook '{}' failed: {}", hook.name, e);
+                    serde_json::json!({})
+                }
+            },
+            None => serde_json::json!({}),
+        };
+        let resp = OutgoingControlResponse::success(request_id, output);
+        let _ = sender.send(resp.to_ndjson());
+    });
+}
+
+async fn run_hook(
+    hook: &HookDef,
+    input: &serde_json::Value,
+    cwd: &str,
+) -> anyhow::Result<serde_json::Value> {
+    let command = match &hook.command {
+        Some(c) => c,
+        None => return Ok(hook.response.clone().unwrap_or_else(|| serde_json::json!({}))),
+    };
+
+    tracing::info!("Running hook '{}': {}", hook.name, command);
+    let mut child = Command::new("sh")
+        .args(["-c", command])
+        .current_dir(cwd)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    // Feed stdin alongside reading the output: a hook that never reads a
+    // payload bigger than the pipe buffer must not block us past the timeout
+    let payload = serde_json::to_vec(input)?;
+    let stdin = child.stdin.take();
+    let writer = tokio::spawn(async move {
+        if let Some(mut stdin) = stdin {
+            // Exiting without reading the input is fine; keep its output
+            if let Err(e) = stdin.write_all(&payload).await {
+                tracing::debug!("Hook stdin closed early: {}", e);
+            }
+        }
+    });
+
+    let timeout = Duration::from_secs(hook.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS));
+    let result = tokio::time::timeout(timeout, child.wait_with_output()).await;
+    writer.abort();
+    let output =
+        result.map_err(|_| anyhow::anyhow!("timed out after {}s", timeout.as_secs()))??;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stderr.trim().is_empty() {
+        tracing::debug!("[hook {} stderr] {}", hook.name, stderr.trim());
+    }
+    if !output.status.success() {
+        anyhow::bail!("exited with {}", output.status);
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if stdout.trim().is_empty() {
+        return Ok(serde_json::json!({}));
+    }
+    Ok(serde_json::from_str(stdout.trim())?)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn hook(def: serde_json::Value) -> HookDef {
+        let mut hook: HookDef = serde_json::from_value(def).unwrap();
+        hook.name = "test".to_string();
+        hook
+    }
+
+    fn pre_tool_use(tool: &str) -> serde_json::Value {
+        json!({"hook_event_name": "PreToolUse", "tool_name": tool})
+    }
+
+    /// Answer a hook_callback and return the `response` the CLI would get
+    async fn answer(hook: Option<HookDef>, input: serde_json::Value) -> serde_json::Value {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let cwd = std::env::temp_dir().to_string_lossy().into_owned();
+        respond(hook, "req-1".to_string(), input, cwd, tx);
+        let line = rx.recv().await.expect("no control_response sent");
+        let msg: serde_json::Value = serde_json::from_str(line.trim()).unwrap();
+        assert_eq!(msg["type"], "control_response");
+        assert_eq!(msg["response"]["request_id"], "req-1");
+        assert_eq!(msg["response"]["subtype"], "success");
+        msg["response"]["response"].clone()
+    }
+
+    #[test]
+    fn empty_hook_matches_everything() {
+        let h = hook(json!({}));
+        assert!(h.matches("cb-1", &pre_tool_use("Bash")));
+        assert!(h.matches("cb-2", &json!({})));
+    }
+
+    #[test]
+    fn every_given_field_must_match() {
+        let h = hook(json!({"callback_id": "cb-1", "event": "PreToolUse", "tool": "Bash"}));
+        assert!(h.matches("cb-1", &pre_tool_use("Bash")));
+        assert!(!h.matches("cb-2", &pre_tool_use("Bash")));
+        assert!(!h.matches("cb-1", &pre_tool_use("Write")));
+        assert!(!h.matches(
+            "cb-1",
+            &json!({"hook_event_name": "PostToolUse", "tool_name": "Bash"})
+        ));
+        // A tool filter never matches an event without a tool
+        let h = hook(json!({"tool": "Bash"}));
+        assert!(!h.matches("cb-1", &json!({"hook_event_name": "Stop"})));
+    }
+
+    #[tokio::test]
+    async fn no_hook_answers_empty_output() {
+        assert_eq!(answer(None, pre_tool_use("Bash")).await, json!({}));
+    }
+
+    #[tokio::test]
+    async fn static_response_is_returned() {
+        let h = hook(json!({"response": {"decision": "block", "reason": "no"}}));
+        assert_eq!(
+            answer(Some(h), pre_tool_use("Bash")).await,
+            json!({"decision": "block", "reason": "no"})
+        );
+        assert_eq!(answer(Some(hook(json!({}))), json!({})).await, json!({}));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn command_gets_input_and_returns_its_output() {
+        let h = hook(json!({
+            "command": "grep -q '\"tool_name\":\"Bash\"' && echo '{\"continue\": false}'"
+        }));
+        assert_eq!(
+            answer(Some(h), pre_tool_use("Bash")).await,
+            json!({"continue": false})
+        );
+        // No output is an empty (no-op) answer
+        let h = hook(json!({"command": "cat > /dev/null"}));
+        assert_eq!(answer(Some(h), pre_tool_use("Bash")).await, json!({}));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn failing_commands_fall_back_to_empty_output() {
+        for command in ["exit 1", "echo 'not json'", "sleep 5"] {
+            let h = hook(json!({"command": command, "timeout_secs": 1}));
+            assert_eq!(answer(Some(h), pre_tool_use("Bash")).await, json!({}), "{}", command);
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn command_ignoring_a_large_input_still_answers() {
+        let h = hook(json!({"command": "echo '{\"ok\": true}'", "timeout_secs": 5}));
+        let input = json!({"tool_input": {"content": "x".repeat(1 << 20)}});
+        assert_eq!(answer(Some(h), input).await, json!({"ok": true}));
+    }
+}
@@ -1,10 +1,15 @@
 mod app;
+mod control;
+#[cfg(unix)]
+mod daemon;
+mod hooks;
+mod permissions;
 mod process;
 mod protocol;
 mod server;
 mod ui;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use tokio::sync::mpsc;
 use tracing_subscriber::EnvFilter;
 
@@ -15,9 +20,14 @@ use tracing_subscriber::EnvFilter;
     version
 )]
 struct Args {
-    /// Port for the WebSocket server
-    #[arg(long, default_value = "8765")]
-    port: u16,
+    /// Port for the WebSocket server (default: pick a free port)
+    #[arg(long)]
+    port: Option<u16>,
+
+    /// Serve the CLI bridge on a Unix domain socket (0600, in the runtime dir)
+    /// instead of TCP
+    #[arg(long, conflicts_with = "port")]
+    socket: bool,
 
     /// Working directory for Claude Code
     #[arg(long)]
@@ -30,6 +40,82 @@ struct Args {
     /// Don't spawn a CLI process (connect to existing)
     #[arg(long)]
     connect: bool,
+
+    /// Run headless in the background; attach TUI clients with --attach
+    #[arg(long, conflicts_with = "attach")]
+    daemon: bool,
+
+    /// With --daemon, stay in the foreground (e.g. under a service manager)
+    #[arg(long, requires = "daemon")]
+    foreground: bool,
+
+    /// Attach this terminal to a running daemon (Ctrl+\ detaches)
+    #[arg(long)]
+    attach: bool,
+
+    /// Color theme: dark, light, high-contrast or one in ~/.companion/themes/
+    #[arg(long)]
+    theme: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Cmd>,
+}
+
+/// Scripting subcommands. Output is JSON on stdout; the exit status is 1
+/// when the response isn't `ok`.
+#[derive(Subcommand, Debug)]
+enum Cmd {
+    /// List sessions (from the daemon, or ~/.companion/sessions/)
+    Ls,
+    /// Print a session with its message history
+    Export {
+        /// Session number, id (or prefix) or name
+        session: String,
+    },
+    /// Send a prompt to a session (needs a running companion: daemon or TUI)
+    Send {
+        /// Session number, id (or prefix) or name
+        session: String,
+        prompt: String,
+        /// Wait for the turn to finish and print its result
+        #[arg(long)]
+        wait: bool,
+    },
+    /// Approve a pending permission request (needs a running companion: daemon or TUI)
+    Approve {
+        /// Request id (or a unique prefix of at least 4 characters), as listed by `ls`
+        request_id: String,
+        /// Deny instead, with an optional message for Claude
+        #[arg(long, num_args = 0..=1, default_missing_value = "Denied by user")]
+        deny: Option<String>,
+    },
+    /// Interrupt a session's running turn (needs a running companion: daemon or TUI)
+    Interrupt {
+        /// Session number, id (or prefix) or name
+        session: String,
+    },
+}
+
+impl Cmd {
+    fn into_request(self) -> control::ControlRequest {
+        match self {
+            Cmd::Ls => control::ControlRequest::Ls,
+            Cmd::Export { session } => control::ControlRequest::Export { session },
+            Cmd::Send {
+                session,
+                prompt,
+                wait,
+            } => control::ControlRequest::Send {
+                session,
+                text: prompt,
+                wait,
+            },
+            Cmd::Approve { request_id, deny } => {
+                control::ControlRequest::Approve { request_id, deny }
+            }
+            Cmd::Interrupt { session } => control::ControlRequest::Interrupt { session },
+        }
+    }
 }
 
 #[tokio::main]
@@ -51,7 +137,28 @@ async fn main() -> anyhow::Result<()> {
         .with_ansi(false)
         .init();
 
-    tracing::info!("Starting companion-tui on port {}", args.port);
+    // ── Scripting subcommands ────────────────────────────────────────────
+    if let Some(cmd) = args.command {
+        let ok = control::client::run(cmd.into_request()).await?;
+        std::process::exit(if ok { 0 } else { 1 });
+    }
+
+    // ── Daemon client / launcher ─────────────────────────────────────────
+    #[cfg(unix)]
+    {
+        if args.attach {
+            return daemon::client::attach(args.theme).await;
+        }
+        if args.daemon && !args.foreground {
+            return daemon::server::spawn_background();
+        }
+    }
+    #[cfg(not(unix))]
+    if args.daemon || args.attach {
+        anyhow::bail!("--daemon and --attach need a Unix platform (the daemon listens on a Unix socket)");
+    }
+
+    tracing::info!("Starting companion-tui");
 
     // ── Event channel ────────────────────────────────────────────────────
     let (event_tx, event_rx) = mpsc::unbounded_channel();
@@ -67,7 +174,12 @@ async fn main() -> anyhow::Result<()> {
     tracing::info!("Working directory: {}", cwd);
 
     // ── App state ────────────────────────────────────────────────────────
-    let mut app = app::App::new(args.port, cwd.clone(), args.model.clone());
+    let port = args.port.unwrap_or(0);
+    let mut app = app::App::new(
+        server::ws_server::Endpoint::Tcp(port),
+        cwd.clone(),
+        args.model.clone(),
+    );
 
     // Load environment profiles from ~/.companion/envs/
     app.load_env_profiles();
@@ -75,6 +187,33 @@ async fn main() -> anyhow::Result<()> {
         tracing::info!("Loaded {} environment profiles", app.env_profiles.len());
     }
 
+    // Load global permission rules from ~/.companion/permissions.json
+    app.load_permission_rules();
+    if !app.permission_rules.is_empty() {
+        tracing::info!("Loaded {} permission rules", app.permission_rules.len());
+    }
+
+    // Load companion-side hooks from ~/.companion/hooks/
+    app.load_hooks();
+    if !app.hooks.is_empty() {
+        tracing::info!("Loaded {} hooks", app.hooks.len());
+    }
+
+    // Load custom themes from ~/.companion/themes/
+    app.load_themes();
+    if !app.themes.is_empty() {
+        tracing::info!("Loaded {} custom themes", app.themes.len());
+    }
+    if let Some(name) = &args.theme {
+        if !app.set_theme(name) {
+            anyhow::bail!(
+                "Unknown theme '{}' (available: {})",
+                name,
+                app.theme_names().join(", ")
+            );
+        }
+    }
+
     // Load persisted sessions from ~/.companion/sessions/
     app.load_persisted_sessions();
     if !app.session_order.is_empty() {
@@ -85,9 +224,29 @@ async fn main() -> anyhow::Result<()> {
     }
 
     // ── WebSocket server ─────────────────────────────────────────────────
-    let ws_server = server::ws_server::WsServer::bind(args.port, event_tx.clone())
-        .await
-        .map_err(|e| anyhow::anyhow!("Failed to bind WebSocket port {}: {} (is another companion running?)", args.port, e))?;
+    let ws_server = if args.socket {
+        let path = server::ws_server::default_socket_path();
+        server::ws_server::WsServer::bind_unix(path.clone(), event_tx.clone(), app.cli_tokens.clone())
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to bind socket {}: {}", path.display(), e))?
+    } else {
+        server::ws_server::WsServer::bind(port, event_tx.clone(), app.cli_tokens.clone())
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to bind WebSocket port {}: {} (is another companion running?)", port, e))?
+    };
+    // Launched CLIs connect to the real address (port 0 resolves here)
+    app.endpoint = ws_server.endpoint().clone();
+
+    // Control API for other local tools, found via ~/.companion/api/<pid>.json
+    let api_token = server::auth::generate_token();
+    let ws_server = ws_server.with_api(server::api::ApiConfig {
+        token: api_token.clone(),
+        events: app.events.sender(),
+    });
+    match server::api::write_discovery(&app.endpoint, &api_token) {
+        Ok(path) => tracing::info!("API discovery file: {}", path.display()),
+        Err(e) => tracing::warn!("Failed to write API discovery file: {}", e),
+    }
     tokio::spawn(async move {
         if let Err(e) = ws_server.run().await {
             tracing::error!("WebSocket server error: {}", e);
@@ -112,8 +271,40 @@ async fn main() -> anyhow::Result<()> {
         }
     }
 
+    // ── --connect: accept external CLIs on any session id ───────────────
+    if args.connect {
+        // Connecting on the active session's id binds to it; any other id
+        // is adopted as a new session.
+        let token = server::auth::generate_token();
+        app.cli_tokens.set_connect_token(&token);
+        if let Some(id) = app.active_session_id.clone() {
+            let url = app.endpoint.sdk_url(&id, &token);
+            if let Some(session) = app.active_session_mut() {
+                session.add_system_message(format!(
+                    "Waiting for CLI. Start it with:\n  claude --sdk-url '{}' --output-format stream-json --input-format stream-json --verbose\nCLIs connecting on other session ids are added as new sessions.",
+                    url
+                ));
+            }
+        }
+    }
+
     // ── Run the TUI event loop (blocks until quit) ───────────────────────
-    ui::event_loop::run(app, event_rx, event_tx).await?;
+    #[cfg(unix)]
+    let endpoint = app.endpoint.clone();
+    #[cfg(unix)]
+    let result = if args.daemon {
+        daemon::server::run(app, event_rx, event_tx).await
+    } else {
+        ui::event_loop::run(app, event_rx, event_tx).await
+    };
+    #[cfg(not(unix))]
+    let result = ui::event_loop::run(app, event_rx, event_tx).await;
+    #[cfg(unix)]
+    if let server::ws_server::Endpoint::Unix(path) = &endpoint {
+        server::ws_server::remove_socket(path);
+    }
+    server::api::remove_discovery();
+    result?;
 
     tracing::info!("companion-tui exiting");
     Ok(())
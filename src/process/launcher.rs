@@ -2,27 +2,39 @@ use std::collections::HashMap;
 use std::process::Stdio;
 use tokio::process::Command;
 
+use crate::server::auth;
+use crate::server::ws_server::Endpoint;
+
 pub struct CliLauncher {
-    port: u16,
+    endpoint: Endpoint,
     session_id: String,
     cwd: String,
     model: Option<String>,
     env_vars: HashMap<String, String>,
     resume_session_id: Option<String>,
+    /// Secret the CLI presents in its --sdk-url to authenticate the handshake
+    token: String,
 }
 
 impl CliLauncher {
-    pub fn new(port: u16, session_id: String, cwd: String, model: Option<String>) -> Self {
+    pub fn new(endpoint: Endpoint, session_id: String, cwd: String, model: Option<String>) -> Self {
         Self {
-            port,
+            endpoint,
             session_id,
             cwd,
             model,
             env_vars: HashMap::new(),
             resume_session_id: None,
+            token: auth::generate_token(),
         }
     }
 
+    /// Token for this launch; register it with the server's `CliTokens`
+    /// before spawning.
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
     pub fn with_env_vars(mut self, vars: HashMap<String, String>) -> Self {
         self.env_vars = vars;
         self
@@ -37,10 +49,7 @@ impl CliLauncher {
     /// This function awaits process exit — run it in a spawned task.
     pub async fn spawn(self) -> anyhow::Result<std::process::ExitStatus> {
         let binary = find_claude_binary()?;
-        let sdk_url = format!(
-            "ws://127.0.0.1:{}/ws/cli/{}",
-            self.port, self.session_id
-        );
+        let sdk_url = self.endpoint.sdk_url(&self.session_id, &self.token);
 
         let mut args = vec![
             "--sdk-url".to_string(),
@@ -68,7 +77,7 @@ impl CliLauncher {
         tracing::info!(
             "Spawning Claude CLI: {} {}",
             binary,
-            args.join(" ")
+            args.join(" ").replace(&self.token, "***")
         );
 
         let mut cmd = Command::new(&binary);
@@ -77,7 +86,9 @@ impl CliLauncher {
             .env("CLAUDECODE", "1")
             .stdin(Stdio::null())
             .stdout(Stdio::piped())
-            .stderr(Stdio::piped());
+            .stderr(Stdio::piped())
+            // Aborting the task running spawn() (restart, kill) ends the CLI too
+            .kill_on_drop(true);
 
         // Inject environment variables from profile
         for (k, v) in &self.env_vars {
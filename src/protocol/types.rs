@@ -12,12 +12,16 @@ pub enum CliMessage {
     System(SystemMessage),
     #[serde(rename = "assistant")]
     Assistant(AssistantMessage),
+    #[serde(rename = "user")]
+    User(UserMessage),
     #[serde(rename = "result")]
     Result(ResultMessage),
     #[serde(rename = "stream_event")]
     StreamEvent(StreamEventMessage),
     #[serde(rename = "control_request")]
     ControlRequest(ControlRequestMessage),
+    #[serde(rename = "control_response")]
+    ControlResponse(ControlResponseMessage),
     #[serde(rename = "tool_progress")]
     ToolProgress(ToolProgressMessage),
     #[serde(rename = "tool_use_summary")]
@@ -86,6 +90,24 @@ pub struct AssistantMessageBody {
     pub usage: Option<Usage>,
 }
 
+// ─── User Message ───────────────────────────────────────────────────────────
+
+/// A user turn echoed by the CLI; in practice the results of tool calls
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserMessage {
+    pub message: UserMessageBody,
+    pub parent_tool_use_id: Option<String>,
+    pub uuid: Option<String>,
+    pub session_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UserMessageBody {
+    pub role: Option<String>,
+    /// A string or an array of content blocks
+    pub content: serde_json::Value,
+}
+
 // ─── Content Blocks ─────────────────────────────────────────────────────────
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -204,6 +226,23 @@ pub enum ControlRequestPayload {
     Unknown,
 }
 
+// ─── Control Response (CLI → Server) ────────────────────────────────────────
+
+/// Reply to a control request we sent (e.g. set_model)
+#[derive(Debug, Clone, Deserialize)]
+pub struct ControlResponseMessage {
+    pub response: IncomingControlResponse,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct IncomingControlResponse {
+    /// "success" or "error"
+    pub subtype: String,
+    pub request_id: String,
+    pub response: Option<serde_json::Value>,
+    pub error: Option<String>,
+}
+
 // ─── Tool Progress ──────────────────────────────────────────────────────────
 
 #[derive(Debug, Clone, Deserialize)]
@@ -349,6 +388,39 @@ impl OutgoingControlResponse {
         }
     }
 
+    /// Allow and apply permission updates (from the request's
+    /// `permission_suggestions`) to the CLI's own settings.
+    pub fn allow_with_permissions(
+        request_id: String,
+        updated_input: serde_json::Value,
+        updated_permissions: Vec<serde_json::Value>,
+    ) -> Self {
+        Self {
+            msg_type: "control_response",
+            response: ControlResponseBody {
+                subtype: "success",
+                request_id,
+                response: Some(serde_json::json!({
+                    "behavior": "allow",
+                    "updatedInput": updated_input,
+                    "updatedPermissions": updated_permissions,
+                })),
+            },
+        }
+    }
+
+    /// Generic success response (e.g. hook callback output)
+    pub fn success(request_id: String, response: serde_json::Value) -> Self {
+        Self {
+            msg_type: "control_response",
+            response: ControlResponseBody {
+                subtype: "success",
+                request_id,
+                response: Some(response),
+            },
+        }
+    }
+
     pub fn deny(request_id: String, message: &str) -> Self {
         Self {
             msg_type: "control_response",
@@ -386,6 +458,22 @@ impl OutgoingControlRequest {
         }
     }
 
+    pub fn set_model(model: &str) -> Self {
+        Self {
+            msg_type: "control_request",
+            request_id: uuid::Uuid::new_v4().to_string(),
+            request: serde_json::json!({ "subtype": "set_model", "model": model }),
+        }
+    }
+
+    pub fn set_permission_mode(mode: &str) -> Self {
+        Self {
+            msg_type: "control_request",
+            request_id: uuid::Uuid::new_v4().to_string(),
+            request: serde_json::json!({ "subtype": "set_permission_mode", "mode": mode }),
+        }
+    }
+
     pub fn to_ndjson(&self) -> String {
         serde_json::to_string(self).expect("serialization of protocol message cannot fail")
     }
@@ -409,28 +497,8 @@ pub struct HistoryEntry {
 
 // ─── Server → CLI Messages (outgoing NDJSON) ────────────────────────────────
 
-/// Set permission mode message sent to the CLI
-#[derive(Debug, Serialize)]
-pub struct OutgoingSetPermissionMode {
-    #[serde(rename = "type")]
-    pub msg_type: &'static str,
-    pub mode: String,
-    pub session_id: String,
-}
-
-impl OutgoingSetPermissionMode {
-    pub fn new(mode: String, session_id: String) -> Self {
-        Self {
-            msg_type: "set_permission_mode",
-            mode,
-            session_id,
-        }
-    }
-
-    pub fn to_ndjson(&self) -> String {
-        serde_json::to_string(self).expect("serialization of protocol message cannot fail")
-    }
-}
+/// Permission modes accepted by the CLI
+pub const PERMISSION_MODES: &[&str] = &["default", "acceptEdits", "bypassPermissions", "plan"];
 
 // ─── Helper functions ───────────────────────────────────────────────────────
 
@@ -460,7 +528,7 @@ pub fn format_tool_summary(name: &str, input: &serde_json::Value) -> String {
             .and_then(|v| v.as_str())
             .unwrap_or("")
             .to_string(),
-        "Edit" => input
+        "Edit" | "MultiEdit" => input
             .get("file_path")
             .and_then(|v| v.as_str())
             .unwrap_or("")
@@ -487,6 +555,66 @@ pub fn format_tool_summary(name: &str, input: &serde_json::Value) -> String {
     }
 }
 
+/// Format a `permission_suggestions` entry (a CLI PermissionUpdate) for display
+pub fn format_permission_suggestion(suggestion: &serde_json::Value) -> String {
+    let kind = suggestion.get("type").and_then(|v| v.as_str()).unwrap_or("");
+    let destination = suggestion
+        .get("destination")
+        .and_then(|v| v.as_str())
+        .unwrap_or("session");
+    let text = match kind {
+        "addRules" | "replaceRules" | "removeRules" => {
+            let behavior = suggestion
+                .get("behavior")
+                .and_then(|v| v.as_str())
+                .unwrap_or("allow");
+            let rules = suggestion
+                .get("rules")
+                .and_then(|v| v.as_array())
+                .map(|rules| {
+                    rules
+                        .iter()
+                        .map(|r| {
+                            let tool = r.get("toolName").and_then(|v| v.as_str()).unwrap_or("?");
+                            match r.get("ruleContent").and_then(|v| v.as_str()) {
+                                Some(content) => format!("{}({})", tool, content),
+                                None => tool.to_string(),
+                            }
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                })
+                .unwrap_or_default();
+            let verb = match kind {
+                "removeRules" => "Remove",
+                "replaceRules" => "Replace with",
+                _ => "Always",
+            };
+            format!("{} {} {}", verb, behavior, rules)
+        }
+        "setMode" => format!(
+            "Switch mode to {}",
+            suggestion.get("mode").and_then(|v| v.as_str()).unwrap_or("?")
+        ),
+        "addDirectories" | "removeDirectories" => {
+            let dirs = suggestion
+                .get("directories")
+                .and_then(|v| v.as_array())
+                .map(|d| {
+                    d.iter()
+                        .filter_map(|v| v.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                })
+                .unwrap_or_default();
+            let verb = if kind == "addDirectories" { "Add" } else { "Remove" };
+            format!("{} directories {}", verb, dirs)
+        }
+        _ => truncate_str(&serde_json::to_string(suggestion).unwrap_or_default(), 80),
+    };
+    format!("{} ({})", text, destination)
+}
+
 /// Truncate a string to at most `max_chars` characters (UTF-8 safe)
 fn truncate_str(s: &str, max_chars: usize) -> String {
     let boundary = s
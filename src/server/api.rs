@@ -0,0 +1,318 @@
+//! `/ws/client`: the control API for editor integrations and dashboards.
+//!
+//! Clients authenticate with the per-launch API token (`?token=` or a Bearer
+//! header), published with the URL in `~/.companion/api/<pid>.json` (0600).
+//! They send `{"id": N, "command": ...}` requests (the scripting commands:
+//! `ls`, `export`, `send`, `approve`, `interrupt`) and receive
+//! `{"type": "response", "id": N, "response": {...}}` replies interleaved with
+//! `{"type": "event", "event": ...}` updates.
+
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::PathBuf;
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::json;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+use crate::app::AppEvent;
+use crate::control::{self, ApiRequest};
+use crate::server::ws_server::Endpoint;
+
+/// Credentials and change feed for `/ws/client`
+#[derive(Clone)]
+pub struct ApiConfig {
+    pub token: String,
+    pub events: broadcast::Sender<String>,
+}
+
+/// Discovery file for this process: `~/.companion/api/<pid>.json`
+pub fn discovery_path() -> PathBuf {
+    dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(".companion")
+        .join("api")
+        .join(format!("{}.json", std::process::id()))
+}
+
+/// Publish the API URL and token so local tools can find this companion.
+pub fn write_discovery(endpoint: &Endpoint, token: &str) -> anyhow::Result<PathBuf> {
+    let path = discovery_path();
+    if let Some(dir) = path.parent() {
+        let mut builder = std::fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        builder.mode(0o700);
+        builder.create(dir)?;
+    }
+    let info = json!({
+        "pid": std::process::id(),
+        "url": endpoint.url("/ws/client"),
+        "token": token,
+    });
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    // Owner-only on Unix; elsewhere the home dir's ACLs apply
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(&path)?;
+    file.write_all(serde_json::to_string_pretty(&info)?.as_bytes())?;
+    Ok(path)
+}
+
+pub fn remove_discovery() {
+    let _ = std::fs::remove_file(discovery_path());
+}
+
+/// Serve one authenticated API connection until it closes.
+pub async fn handle_connection<S>(
+    ws_stream: WebSocketStream<S>,
+    event_tx: mpsc::UnboundedSender<AppEvent>,
+    mut events: broadcast::Receiver<String>,
+) -> anyhow::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    tracing::info!("API client connected");
+    let (mut ws_write, mut ws_read) = ws_stream.split();
+    // Responses finish out of order (`send` with `wait`)
+    let (resp_tx, mut resp_rx) = mpsc::unbounded_channel::<String>();
+
+    loop {
+        tokio::select! {
+            maybe_msg = ws_read.next() => match maybe_msg {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str::<ApiRequest>(&text) {
+                    Ok(ApiRequest { id, request }) => {
+                        let (reply_tx, reply_rx) = oneshot::channel();
+                        let _ = event_tx.send(AppEvent::Control {
+                            request,
+                            reply: reply_tx,
+                        });
+                        let resp_tx = resp_tx.clone();
+                        tokio::spawn(async move {
+                            let response = reply_rx.await.unwrap_or_else(|_| {
+                                control::error_response("No result (the CLI exited or the session was closed)")
+                            });
+                            let msg = json!({ "type": "response", "id": id, "response": response });
+                            let _ = resp_tx.send(msg.to_string());
+                        });
+                    }
+                    Err(e) => {
+                        let msg = json!({ "type": "error", "error": format!("Bad request: {}", e) });
+                        ws_write.send(Message::text(msg.to_string())).await?;
+                    }
+                },
+                Some(Ok(Message::Close(_))) | None => break,
+                Some(Ok(_)) => {}
+                Some(Err(e)) => {
+                    tracing::debug!("API client read error: {}", e);
+                    break;
+                }
+            },
+            Some(response) = resp_rx.recv() => {
+                ws_write.send(Message::text(response)).await?;
+            }
+            event = events.recv() => match event {
+                Ok(event) => ws_write.send(Message::text(event)).await?,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    // Too slow to keep up: tell the client to resync with `ls`
+                    let msg = json!({ "type": "lagged", "skipped": skipped });
+                    ws_write.send(Message::text(msg.to_string())).await?;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+        }
+    }
+
+    tracing::info!("API client disconnected");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use tokio::io::DuplexStream;
+    use tokio_tungstenite::tungstenite::protocol::Role;
+
+    use crate::app::{App, PendingPermission, Session};
+
+    /// An API connection served over an in-memory stream
+    struct Harness {
+        client: WebSocketStream<DuplexStream>,
+        event_rx: mpsc::UnboundedReceiver<AppEvent>,
+        events: broadcast::Sender<String>,
+    }
+
+    async fn connect() -> Harness {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let server = WebSocketStream::from_raw_socket(server, Role::Server, None).await;
+        let client = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+        let (events, events_rx) = broadcast::channel(16);
+        tokio::spawn(handle_connection(server, event_tx, events_rx));
+        Harness {
+            client,
+            event_rx,
+            events,
+        }
+    }
+
+    impl Harness {
+        async fn send(&mut self, text: &str) {
+            self.client.send(Message::text(text)).await.unwrap();
+        }
+
+        async fn recv(&mut self) -> Value {
+            loop {
+                if let Message::Text(text) = self.client.next().await.unwrap().unwrap() {
+                    return serde_json::from_str(&text).unwrap();
+                }
+            }
+        }
+
+        /// Play the event loop for the next control request
+        async fn serve(&mut self, app: &mut App) {
+            match self.event_rx.recv().await {
+                Some(AppEvent::Control { request, reply }) => control::handle(app, request, reply),
+                _ => panic!("expected a control request"),
+            }
+        }
+    }
+
+    /// An app with one session waiting on two permission requests; the
+    /// receiver gets what is sent to its CLI
+    fn app_with_permissions() -> (App, mpsc::UnboundedReceiver<String>) {
+        let mut app = App::new(Endpoint::Tcp(0), "/tmp".to_string(), None);
+        let mut session = Session::new("s1".to_string(), "alpha".to_string(), "/tmp".to_string());
+        let (cli_tx, cli_rx) = mpsc::unbounded_channel();
+        session.cli_sender = Some(cli_tx);
+        session.cli_connected = true;
+        for request_id in ["req-1a", "req-1b"] {
+            session.pending_permissions.push_back(PendingPermission {
+                request_id: request_id.to_string(),
+                tool_use_id: format!("toolu_{}", request_id),
+                tool_name: "Bash".to_string(),
+                input: json!({ "command": "ls" }),
+                description: None,
+                permission_suggestions: None,
+                agent_id: None,
+            });
+        }
+        app.sessions.insert("s1".to_string(), session);
+        app.session_order.push("s1".to_string());
+        (app, cli_rx)
+    }
+
+    #[tokio::test]
+    async fn responses_echo_the_request_id() {
+        let (mut app, _cli_rx) = app_with_permissions();
+        let mut h = connect().await;
+        h.send(r#"{"id": 7, "command": "ls"}"#).await;
+        h.serve(&mut app).await;
+
+        let msg = h.recv().await;
+        assert_eq!(msg["type"], "response");
+        assert_eq!(msg["id"], 7);
+        assert_eq!(msg["response"]["ok"], true);
+        let sessions = msg["response"]["sessions"].as_array().unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0]["name"], "alpha");
+        assert_eq!(sessions[0]["pending_permissions"].as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn malformed_requests_get_an_error_and_keep_the_connection() {
+        let (mut app, _cli_rx) = app_with_permissions();
+        let mut h = connect().await;
+        h.send("not json").await;
+        let msg = h.recv().await;
+        assert_eq!(msg["type"], "error");
+        assert!(msg["error"].as_str().unwrap().starts_with("Bad request"));
+
+        h.send(r#"{"id": 1, "command": "reboot"}"#).await;
+        assert_eq!(h.recv().await["type"], "error");
+
+        h.send(r#"{"id": 2, "command": "ls"}"#).await;
+        h.serve(&mut app).await;
+        assert_eq!(h.recv().await["id"], 2);
+    }
+
+    #[tokio::test]
+    async fn approve_answers_the_cli_and_rejects_ambiguous_ids() {
+        let (mut app, mut cli_rx) = app_with_permissions();
+        let mut h = connect().await;
+
+        h.send(r#"{"id": 1, "command": "approve", "request_id": "req-1"}"#).await;
+        h.serve(&mut app).await;
+        let msg = h.recv().await;
+        assert_eq!(msg["response"]["ok"], false);
+        assert!(msg["response"]["error"].as_str().unwrap().contains("Ambiguous"));
+        assert!(cli_rx.try_recv().is_err());
+
+        let deny = r#"{"id": 2, "command": "approve", "request_id": "req-1b", "deny": "no"}"#;
+        h.send(deny).await;
+        h.serve(&mut app).await;
+        let msg = h.recv().await;
+        assert_eq!(msg["response"]["ok"], true);
+        assert_eq!(msg["response"]["approved"], false);
+        let sent: Value = serde_json::from_str(&cli_rx.try_recv().unwrap()).unwrap();
+        assert_eq!(sent["response"]["request_id"], "req-1b");
+        assert_eq!(sent["response"]["response"]["behavior"], "deny");
+
+        // Only one request left, so its prefix is unique now
+        h.send(r#"{"id": 3, "command": "approve", "request_id": "req-"}"#).await;
+        h.serve(&mut app).await;
+        assert_eq!(h.recv().await["response"]["request_id"], "req-1a");
+        assert!(app.sessions["s1"].pending_permissions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn approve_needs_the_request_named() {
+        let (mut app, mut cli_rx) = app_with_permissions();
+        app.sessions.get_mut("s1").unwrap().pending_permissions.pop_back();
+        let mut h = connect().await;
+
+        // Too short to name the one pending request
+        for (id, request_id) in [(1, ""), (2, "r"), (3, "re"), (4, "req")] {
+            let req = json!({"id": id, "command": "approve", "request_id": request_id});
+            h.send(&req.to_string()).await;
+            h.serve(&mut app).await;
+            let msg = h.recv().await;
+            assert_eq!(msg["response"]["ok"], false, "{:?}", request_id);
+        }
+        assert!(cli_rx.try_recv().is_err());
+        assert_eq!(app.sessions["s1"].pending_permissions.len(), 1);
+
+        h.send(r#"{"id": 5, "command": "approve", "request_id": "req-1a"}"#).await;
+        h.serve(&mut app).await;
+        assert_eq!(h.recv().await["response"]["ok"], true);
+        assert!(app.sessions["s1"].pending_permissions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_dropped_reply_is_reported_as_an_error() {
+        let mut h = connect().await;
+        h.send(r#"{"id": 4, "command": "send", "session": "x", "text": "hi"}"#).await;
+        match h.event_rx.recv().await {
+            Some(AppEvent::Control { reply, .. }) => drop(reply),
+            _ => panic!("expected a control request"),
+        }
+        let msg = h.recv().await;
+        assert_eq!(msg["id"], 4);
+        assert_eq!(msg["response"]["ok"], false);
+    }
+
+    #[tokio::test]
+    async fn published_events_are_forwarded() {
+        let mut h = connect().await;
+        // The connection subscribed before being spawned
+        let event = json!({ "type": "event", "event": "session_removed", "session": "s1" });
+        h.events.send(event.to_string()).unwrap();
+        assert_eq!(h.recv().await, event);
+    }
+}
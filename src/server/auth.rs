@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Per-launch secrets that CLI connections must present on `/ws/cli/{id}`.
+///
+/// Shared between the event loop (which registers a token whenever it
+/// launches a CLI) and the WebSocket server (which checks it during the
+/// handshake).
+#[derive(Clone, Default)]
+pub struct CliTokens {
+    tokens: Arc<Mutex<HashMap<String, String>>>,
+    /// `--connect` mode: token accepted for session ids we didn't launch a
+    /// CLI for, so externally started CLIs can attach (and get adopted as
+    /// new sessions)
+    connect_token: Arc<Mutex<Option<String>>>,
+}
+
+impl CliTokens {
+    /// Register the token for a session, replacing any previous launch's token.
+    pub fn insert(&self, session_id: &str, token: &str) {
+        self.tokens
+            .lock()
+            .unwrap()
+            .insert(session_id.to_string(), token.to_string());
+    }
+
+    pub fn revoke(&self, session_id: &str) {
+        self.tokens.lock().unwrap().remove(session_id);
+    }
+
+    pub fn set_connect_token(&self, token: &str) {
+        *self.connect_token.lock().unwrap() = Some(token.to_string());
+    }
+
+    /// Check a handshake. Missing token → 401, unknown session or wrong
+    /// token → 403. The connect token (if set) is only valid for sessions
+    /// without a launch token, so it can't take over CLIs we started.
+    pub fn verify(&self, session_id: &str, token: Option<&str>) -> Result<(), http::StatusCode> {
+        let token = token.ok_or(http::StatusCode::UNAUTHORIZED)?;
+        let tokens = self.tokens.lock().unwrap();
+        match tokens.get(session_id) {
+            Some(expected) if constant_time_eq(expected.as_bytes(), token.as_bytes()) => Ok(()),
+            Some(_) => Err(http::StatusCode::FORBIDDEN),
+            None => {
+                let connect_ok = self
+                    .connect_token
+                    .lock()
+                    .unwrap()
+                    .as_deref()
+                    .is_some_and(|t| constant_time_eq(t.as_bytes(), token.as_bytes()));
+                if connect_ok {
+                    Ok(())
+                } else {
+                    Err(http::StatusCode::FORBIDDEN)
+                }
+            }
+        }
+    }
+}
+
+/// Generate a fresh launch token (256 bits from two v4 UUIDs).
+pub fn generate_token() -> String {
+    format!(
+        "{}{}",
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    )
+}
+
+/// Extract the token from `?token=` or an `Authorization: Bearer` header.
+pub fn token_from_request(req: &http::Request<()>) -> Option<String> {
+    let from_query = req.uri().query().and_then(|q| {
+        q.split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(k, _)| *k == "token")
+            .map(|(_, v)| v.to_string())
+    });
+    from_query.or_else(|| {
+        req.headers()
+            .get(http::header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(|v| v.trim().to_string())
+    })
+}
+
+/// Compare secrets without leaking where they differ through timing
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
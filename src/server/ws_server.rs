@@ -1,78 +1,318 @@
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+#[cfg(unix)]
+use std::path::Path;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
 use futures_util::{SinkExt, StreamExt};
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
 use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::handshake::server::{Callback, ErrorResponse, Request, Response};
 use tokio_tungstenite::tungstenite::Message;
 
 use crate::app::AppEvent;
 use crate::protocol::types::CliMessage;
+use crate::server::api::{self, ApiConfig};
+use crate::server::auth::{self, CliTokens};
+
+// ─── Endpoint ───────────────────────────────────────────────────────────────
+
+/// Where the CLI bridge listens
+#[derive(Debug, Clone, PartialEq)]
+pub enum Endpoint {
+    Tcp(u16),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl Endpoint {
+    /// WebSocket URL for a path on this endpoint
+    pub fn url(&self, path: &str) -> String {
+        match self {
+            Endpoint::Tcp(port) => format!("ws://127.0.0.1:{}{}", port, path),
+            #[cfg(unix)]
+            Endpoint::Unix(socket) => format!("ws+unix://{}:{}", socket.display(), path),
+        }
+    }
+
+    /// `--sdk-url` for a CLI connecting to this endpoint
+    pub fn sdk_url(&self, session_id: &str, token: &str) -> String {
+        self.url(&format!("/ws/cli/{}?token={}", session_id, token))
+    }
+}
+
+impl std::fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Endpoint::Tcp(port) => write!(f, "127.0.0.1:{}", port),
+            #[cfg(unix)]
+            Endpoint::Unix(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Socket path for this process: `<runtime dir>/companion-<pid>/cli.sock`.
+/// Falls back to the temp dir when there is no runtime dir.
+pub fn default_socket_path() -> PathBuf {
+    dirs::runtime_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join(format!("companion-{}", std::process::id()))
+        .join("cli.sock")
+}
+
+/// Create the socket's directory with mode 0700. Without a runtime dir its
+/// name is predictable, so an existing one is only reused if it is ours and
+/// private; otherwise another user could have made it to swap the socket.
+#[cfg(unix)]
+fn create_private_dir(dir: &Path) -> anyhow::Result<()> {
+    match std::fs::DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            let meta = std::fs::symlink_metadata(dir)?;
+            // SAFETY: geteuid has no preconditions and cannot fail
+            let uid = unsafe { libc::geteuid() };
+            if !meta.is_dir() || meta.uid() != uid || meta.mode() & 0o777 != 0o700 {
+                anyhow::bail!(
+                    "{} exists but is not a private directory owned by this user",
+                    dir.display()
+                );
+            }
+            Ok(())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Remove a socket created by `WsServer::bind_unix` and its private dir.
+#[cfg(unix)]
+pub fn remove_socket(path: &Path) {
+    let _ = std::fs::remove_file(path);
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::remove_dir(dir);
+    }
+}
+
+// ─── Server ─────────────────────────────────────────────────────────────────
+
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
 
 pub struct WsServer {
-    listener: TcpListener,
+    listener: Listener,
+    endpoint: Endpoint,
     event_tx: mpsc::UnboundedSender<AppEvent>,
+    tokens: CliTokens,
+    /// `/ws/client` is only served when configured
+    api: Option<ApiConfig>,
 }
 
 impl WsServer {
-    /// Bind the WebSocket server to the given port. Returns an error immediately
-    /// if the port is already in use (e.g. another companion instance).
-    pub async fn bind(port: u16, event_tx: mpsc::UnboundedSender<AppEvent>) -> anyhow::Result<Self> {
+    /// Bind the WebSocket server to the given port (0 picks a free one).
+    /// Returns an error immediately if the port is already in use (e.g.
+    /// another companion instance).
+    pub async fn bind(
+        port: u16,
+        event_tx: mpsc::UnboundedSender<AppEvent>,
+        tokens: CliTokens,
+    ) -> anyhow::Result<Self> {
         let listener = TcpListener::bind(format!("127.0.0.1:{}", port)).await?;
+        let port = listener.local_addr()?.port();
         tracing::info!("WebSocket server listening on 127.0.0.1:{}", port);
-        Ok(Self { listener, event_tx })
+        Ok(Self {
+            listener: Listener::Tcp(listener),
+            endpoint: Endpoint::Tcp(port),
+            event_tx,
+            tokens,
+            api: None,
+        })
+    }
+
+    /// Bind the WebSocket server to a Unix domain socket. The socket lives in
+    /// a fresh 0700 directory and is itself 0600, so only this user can
+    /// reach it.
+    #[cfg(unix)]
+    pub async fn bind_unix(
+        path: PathBuf,
+        event_tx: mpsc::UnboundedSender<AppEvent>,
+        tokens: CliTokens,
+    ) -> anyhow::Result<Self> {
+        if let Some(dir) = path.parent() {
+            create_private_dir(dir)?;
+        }
+        // A leftover socket from a crashed instance with the same pid
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path)?;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+        tracing::info!("WebSocket server listening on {}", path.display());
+        Ok(Self {
+            listener: Listener::Unix(listener),
+            endpoint: Endpoint::Unix(path),
+            event_tx,
+            tokens,
+            api: None,
+        })
+    }
+
+    /// Unix domain sockets need a Unix platform
+    #[cfg(not(unix))]
+    pub async fn bind_unix(
+        _path: PathBuf,
+        _event_tx: mpsc::UnboundedSender<AppEvent>,
+        _tokens: CliTokens,
+    ) -> anyhow::Result<Self> {
+        anyhow::bail!("Unix domain sockets are not supported on this platform; use --port")
+    }
+
+    /// Also serve the control API on `/ws/client`
+    pub fn with_api(mut self, api: ApiConfig) -> Self {
+        self.api = Some(api);
+        self
+    }
+
+    /// The address actually bound (with the real port for `bind(0, ..)`)
+    pub fn endpoint(&self) -> &Endpoint {
+        &self.endpoint
     }
 
     pub async fn run(self) -> anyhow::Result<()> {
         loop {
-            let (stream, addr) = self.listener.accept().await?;
-            tracing::debug!("TCP connection from {}", addr);
-            let event_tx = self.event_tx.clone();
-            tokio::spawn(async move {
-                if let Err(e) = handle_connection(stream, event_tx).await {
-                    tracing::error!("WebSocket connection error: {}", e);
+            match &self.listener {
+                Listener::Tcp(listener) => {
+                    let (stream, addr) = listener.accept().await?;
+                    tracing::debug!("TCP connection from {}", addr);
+                    self.spawn_connection(stream);
+                }
+                #[cfg(unix)]
+                Listener::Unix(listener) => {
+                    let (stream, _) = listener.accept().await?;
+                    tracing::debug!("Unix socket connection");
+                    self.spawn_connection(stream);
                 }
-            });
+            }
         }
     }
+
+    fn spawn_connection<S>(&self, stream: S)
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let event_tx = self.event_tx.clone();
+        let tokens = self.tokens.clone();
+        let api = self.api.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, event_tx, tokens, api).await {
+                tracing::error!("WebSocket connection error: {}", e);
+            }
+        });
+    }
 }
 
-async fn handle_connection(
-    stream: tokio::net::TcpStream,
-    event_tx: mpsc::UnboundedSender<AppEvent>,
-) -> anyhow::Result<()> {
-    // Extract session ID from the URL path during WebSocket handshake
-    let session_id_cell = Arc::new(Mutex::new(None::<String>));
-    let sid_clone = session_id_cell.clone();
-
-    let callback =
-        move |req: &http::Request<()>,
-              resp: http::Response<()>|
-              -> Result<http::Response<()>, http::Response<Option<String>>> {
-            let path = req.uri().path();
-            tracing::debug!("WebSocket upgrade request for path: {}", path);
-
-            if let Some(id) = path.strip_prefix("/ws/cli/") {
-                let id = id.trim_end_matches('/');
-                if !id.is_empty() {
-                    *sid_clone.lock().unwrap() = Some(id.to_string());
-                    return Ok(resp);
+/// What a handshake was accepted for
+enum Route {
+    Cli(String),
+    Api,
+}
+
+/// Checks the upgrade request's path and token, recording the route it was
+/// accepted for
+struct Handshake {
+    tokens: CliTokens,
+    api_token: Option<String>,
+    route: Arc<Mutex<Option<Route>>>,
+}
+
+impl Callback for Handshake {
+    fn on_request(self, req: &Request, resp: Response) -> Result<Response, ErrorResponse> {
+        let path = req.uri().path();
+        tracing::debug!("WebSocket upgrade request for path: {}", path);
+
+        let reject = |status: http::StatusCode, body: &str| {
+            http::Response::builder()
+                .status(status)
+                .body(Some(body.to_string()))
+                .unwrap()
+        };
+
+        if let Some(id) = path.strip_prefix("/ws/cli/") {
+            let id = id.trim_end_matches('/');
+            if !id.is_empty() {
+                // Only CLIs we launched (or were told about) may connect
+                let token = auth::token_from_request(req);
+                if let Err(status) = self.tokens.verify(id, token.as_deref()) {
+                    tracing::warn!("Rejected CLI connection for session {}: {}", id, status);
+                    return Err(reject(status, "Invalid session or token"));
                 }
+                *self.route.lock().unwrap() = Some(Route::Cli(id.to_string()));
+                return Ok(resp);
             }
+        }
 
-            // Reject connections that don't match the expected path
-            let err_resp = http::Response::builder()
-                .status(http::StatusCode::NOT_FOUND)
-                .body(Some("Expected path: /ws/cli/{session_id}".to_string()))
-                .unwrap();
-            Err(err_resp)
-        };
+        if path.trim_end_matches('/') == "/ws/client" {
+            if let Some(expected) = &self.api_token {
+                let token = auth::token_from_request(req);
+                let status = match token {
+                    None => Some(http::StatusCode::UNAUTHORIZED),
+                    Some(t) if !auth::constant_time_eq(t.as_bytes(), expected.as_bytes()) => {
+                        Some(http::StatusCode::FORBIDDEN)
+                    }
+                    Some(_) => None,
+                };
+                if let Some(status) = status {
+                    tracing::warn!("Rejected API connection: {}", status);
+                    return Err(reject(status, "Invalid API token"));
+                }
+                *self.route.lock().unwrap() = Some(Route::Api);
+                return Ok(resp);
+            }
+        }
+
+        // Reject connections that don't match the expected path
+        Err(reject(
+            http::StatusCode::NOT_FOUND,
+            "Expected path: /ws/cli/{session_id} or /ws/client",
+        ))
+    }
+}
+
+async fn handle_connection<S>(
+    stream: S,
+    event_tx: mpsc::UnboundedSender<AppEvent>,
+    tokens: CliTokens,
+    api: Option<ApiConfig>,
+) -> anyhow::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    // Route (and CLI session ID) from the URL path during the handshake
+    let route_cell = Arc::new(Mutex::new(None::<Route>));
+    let callback = Handshake {
+        tokens,
+        api_token: api.as_ref().map(|a| a.token.clone()),
+        route: route_cell.clone(),
+    };
 
     let ws_stream = tokio_tungstenite::accept_hdr_async(stream, callback).await?;
-    let session_id = session_id_cell
+    let route = route_cell
         .lock()
         .unwrap()
         .take()
-        .ok_or_else(|| anyhow::anyhow!("No session ID extracted from WebSocket path"))?;
+        .ok_or_else(|| anyhow::anyhow!("No route extracted from WebSocket path"))?;
+    let session_id = match route {
+        Route::Cli(id) => id,
+        Route::Api => {
+            let events = api
+                .map(|a| a.events.subscribe())
+                .ok_or_else(|| anyhow::anyhow!("API route without API config"))?;
+            return api::handle_connection(ws_stream, event_tx, events).await;
+        }
+    };
 
     tracing::info!("CLI WebSocket connected for session {}", session_id);
 
@@ -100,7 +340,7 @@ async fn handle_connection(
                             Ok(cli_msg) => {
                                 let _ = etx_read.send(AppEvent::CliMessage {
                                     session_id: sid_read.clone(),
-                                    message: cli_msg,
+                                    message: Box::new(cli_msg),
                                 });
                             }
                             Err(e) => {
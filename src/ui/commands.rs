@@ -1,4 +1,7 @@
-use crate::app::{App, Command, Mode, SessionStatus};
+use crate::app::{App, Command, Mode, PendingControl, SessionStatus, SplitDirection};
+use crate::permissions::rules::{PermissionRule, RuleAction};
+use crate::protocol::types::{OutgoingControlRequest, PERMISSION_MODES};
+use crate::ui::keybindings;
 
 pub fn parse_command(input: &str) -> Command {
     let trimmed = input.trim();
@@ -22,6 +25,9 @@ pub fn parse_command(input: &str) -> Command {
         },
         "ls" | "sessions" => Command::Ls,
         "env" | "envs" => Command::Env,
+        "theme" | "themes" => Command::Theme {
+            name: arg.unwrap_or_default(),
+        },
         "model" => Command::Model {
             name: arg.unwrap_or_default(),
         },
@@ -50,11 +56,31 @@ pub fn parse_command(input: &str) -> Command {
         "go" => Command::Go {
             partial_name: arg.unwrap_or_default(),
         },
+        "split" | "sp" => Command::Split {
+            direction: SplitDirection::Horizontal,
+            partial_name: arg.unwrap_or_default(),
+        },
+        "vsplit" | "vs" => Command::Split {
+            direction: SplitDirection::Vertical,
+            partial_name: arg.unwrap_or_default(),
+        },
+        "only" => Command::Only,
         "export" => Command::Export {
             path: arg.unwrap_or_default(),
         },
         "pin" => Command::Pin,
         "unpin" => Command::Unpin,
+        "allow" => Command::Allow {
+            args: arg.unwrap_or_default(),
+        },
+        "deny" => Command::Deny {
+            args: arg.unwrap_or_default(),
+        },
+        "rules" => Command::Rules,
+        "unrule" => Command::Unrule {
+            index: arg.and_then(|s| s.parse::<usize>().ok()),
+        },
+        "inbox" => Command::Inbox,
         "help" | "h" | "?" => Command::Help,
         "q" | "quit" | "exit" => Command::Quit,
         other => Command::Unknown(other.to_string()),
@@ -160,23 +186,68 @@ pub fn execute_command(cmd: Command, app: &mut App) -> CommandResult {
             app.dirty = true;
             CommandResult::Ok
         }
+        Command::Theme { name } => {
+            // Re-read the files so edits to a custom theme show up
+            app.load_themes();
+            if name.is_empty() {
+                let lines: Vec<String> = app
+                    .theme_names()
+                    .into_iter()
+                    .map(|n| {
+                        let marker = if n == app.theme.name { ">" } else { " " };
+                        format!("{} {}", marker, n)
+                    })
+                    .collect();
+                if let Some(session) = app.active_session_mut() {
+                    session.add_system_message(format!(
+                        "Themes (custom ones go in ~/.companion/themes/):\n{}",
+                        lines.join("\n")
+                    ));
+                }
+            } else if app.set_theme(&name) {
+                app.flash(format!("Theme: {}", name));
+            } else {
+                app.flash(format!("Unknown theme: {} (see :theme)", name));
+            }
+            app.dirty = true;
+            CommandResult::Ok
+        }
         Command::Model { name } => {
             if name.is_empty() {
                 app.flash("Usage: :model <name>".to_string());
             } else if let Some(session) = app.active_session_mut() {
-                session.add_system_message(format!("Model changed to: {}", name));
-                session.model = name;
-                session.dirty_persist = true;
+                if session.cli_connected && session.cli_sender.is_some() {
+                    // Switch the live CLI; session.model is updated once it replies
+                    let req = OutgoingControlRequest::set_model(&name);
+                    session.send_control(req, PendingControl::SetModel { model: name.clone() });
+                    session.add_system_message(format!("Switching model to {}...", name));
+                } else {
+                    // Picked up by the next spawn (--model)
+                    session.add_system_message(format!("Model changed to: {}", name));
+                    session.model = name;
+                    session.dirty_persist = true;
+                }
             }
             app.dirty = true;
             CommandResult::Ok
         }
         Command::PermMode { mode } => {
-            if mode.is_empty() {
-                app.flash("Usage: :mode <permission_mode>".to_string());
-            } else if let Some(session) = app.active_session_mut() {
-                session.add_system_message(format!("Permission mode: {}", mode));
-                session.permission_mode = mode;
+            let canonical = PERMISSION_MODES
+                .iter()
+                .find(|m| m.eq_ignore_ascii_case(&mode))
+                .map(|m| m.to_string());
+            match (canonical, app.active_session_mut()) {
+                (None, _) => {
+                    app.flash(format!("Usage: :mode <{}>", PERMISSION_MODES.join("|")));
+                }
+                (Some(_), None) => {}
+                (Some(_), Some(session)) if !session.cli_connected => {
+                    app.flash("CLI not connected".to_string());
+                }
+                (Some(mode), Some(session)) => {
+                    session.request_permission_mode(&mode);
+                    session.add_system_message(format!("Requesting permission mode: {}", mode));
+                }
             }
             app.dirty = true;
             CommandResult::Ok
@@ -293,16 +364,9 @@ pub fn execute_command(cmd: Command, app: &mut App) -> CommandResult {
         Command::Reconnect => {
             if let Some(id) = app.active_session_id.clone() {
                 if let Some(session) = app.sessions.get_mut(&id) {
-                    // Kill existing process handle if any
-                    if let Some(handle) = session.cli_process_handle.take() {
-                        handle.abort();
-                    }
-                    session.cli_connected = false;
-                    session.cli_sender = None;
-                    session.status = SessionStatus::WaitingForCli;
                     session.add_system_message("Reconnecting...".to_string());
                 }
-                app.pending_spawns.push(id);
+                app.restart_cli(&id);
             }
             app.dirty = true;
             CommandResult::Ok
@@ -527,8 +591,14 @@ pub fn execute_command(cmd: Command, app: &mut App) -> CommandResult {
         Command::Clear => {
             if let Some(session) = app.active_session_mut() {
                 session.messages.clear();
-                session.streaming_text.clear();
+                session.history_generation += 1;
+                session.clear_stream();
                 session.scroll_offset = 0;
+                // Keep the edits still waiting for approval in the banner
+                let pending = &session.pending_permissions;
+                session
+                    .edit_start_lines
+                    .retain(|id, _| pending.iter().any(|p| &p.tool_use_id == id));
             }
             app.dirty = true;
             CommandResult::Ok
@@ -537,18 +607,9 @@ pub fn execute_command(cmd: Command, app: &mut App) -> CommandResult {
             if partial_name.is_empty() {
                 app.flash("Usage: :go <name>".to_string());
             } else {
-                let query = partial_name.to_lowercase();
-                let visible = app.visible_session_order();
-                let found = visible.iter().find(|id| {
-                    app.sessions
-                        .get(id.as_str())
-                        .map(|s| s.name.to_lowercase().contains(&query))
-                        .unwrap_or(false)
-                });
-                if let Some(id) = found {
-                    let id = id.clone();
+                if let Some(id) = find_session(app, &partial_name) {
                     app.switch_to_session(&id);
-                    app.flash(format!("Switched to session"));
+                    app.flash("Switched to session".to_string());
                 } else {
                     app.flash(format!("No session matching '{}'", partial_name));
                 }
@@ -556,6 +617,33 @@ pub fn execute_command(cmd: Command, app: &mut App) -> CommandResult {
             app.dirty = true;
             CommandResult::Ok
         }
+        Command::Split {
+            direction,
+            partial_name,
+        } => {
+            let other = if partial_name.is_empty() {
+                app.next_session_id()
+            } else {
+                find_session(app, &partial_name)
+            };
+            match other {
+                Some(id) if app.active_session_id.as_deref() == Some(id.as_str()) => {
+                    app.flash("Already in the focused pane".to_string());
+                }
+                Some(id) => app.open_split(direction, id),
+                None if partial_name.is_empty() => {
+                    app.flash("No other session to split with".to_string());
+                }
+                None => app.flash(format!("No session matching '{}'", partial_name)),
+            }
+            app.dirty = true;
+            CommandResult::Ok
+        }
+        Command::Only => {
+            app.layout.split = None;
+            app.dirty = true;
+            CommandResult::Ok
+        }
         Command::Export { path } => {
             if path.is_empty() {
                 app.flash("Usage: :export <path>".to_string());
@@ -569,7 +657,7 @@ pub fn execute_command(cmd: Command, app: &mut App) -> CommandResult {
                 };
                 if let Some(session) = app.active_session() {
                     let mut md = String::new();
-                    for msg in &session.messages {
+                    for msg in session.messages.iter().filter(|m| !m.is_tool_results()) {
                         match msg.role {
                             crate::app::ChatRole::User => {
                                 md.push_str("## You\n\n");
@@ -621,6 +709,70 @@ pub fn execute_command(cmd: Command, app: &mut App) -> CommandResult {
             app.dirty = true;
             CommandResult::Ok
         }
+        Command::Allow { args } => {
+            add_permission_rule(app, RuleAction::Allow, &args);
+            CommandResult::Ok
+        }
+        Command::Deny { args } => {
+            add_permission_rule(app, RuleAction::Deny, &args);
+            CommandResult::Ok
+        }
+        Command::Rules => {
+            let session_rules = app
+                .active_session()
+                .map(|s| s.permission_rules.clone())
+                .unwrap_or_default();
+            let mut lines = vec!["Permission rules:".to_string()];
+            for (i, rule) in session_rules.iter().enumerate() {
+                lines.push(format!("  {}. {} {} [session]", i + 1, rule.action, rule));
+            }
+            for (i, rule) in app.permission_rules.iter().enumerate() {
+                lines.push(format!(
+                    "  {}. {} {} [global]",
+                    session_rules.len() + i + 1,
+                    rule.action,
+                    rule
+                ));
+            }
+            if lines.len() == 1 {
+                lines.push("  (none)".to_string());
+            }
+            if let Some(session) = app.active_session_mut() {
+                session.add_system_message(lines.join("\n"));
+            }
+            app.dirty = true;
+            CommandResult::Ok
+        }
+        Command::Unrule { index } => {
+            let session_count = app
+                .active_session()
+                .map(|s| s.permission_rules.len())
+                .unwrap_or(0);
+            // :rules numbers from 1, so 0 is as invalid as no number
+            match index.and_then(|i| i.checked_sub(1)) {
+                Some(i) if i < session_count => {
+                    if let Some(session) = app.active_session_mut() {
+                        let rule = session.permission_rules.remove(i);
+                        session.dirty_persist = true;
+                        let _ = session.persist();
+                        app.flash(format!("Removed rule: {} {}", rule.action, rule));
+                    }
+                }
+                Some(i) if i - session_count < app.permission_rules.len() => {
+                    let rule = app.permission_rules.remove(i - session_count);
+                    app.save_permission_rules();
+                    app.flash(format!("Removed rule: {} {}", rule.action, rule));
+                }
+                Some(_) => app.flash("Invalid rule index (see :rules)".to_string()),
+                None => app.flash("Usage: :unrule <n>".to_string()),
+            }
+            app.dirty = true;
+            CommandResult::Ok
+        }
+        Command::Inbox => {
+            keybindings::open_inbox(app);
+            CommandResult::Ok
+        }
         Command::Help => {
             let help = [
                 "Commands:",
@@ -637,13 +789,21 @@ pub fn execute_command(cmd: Command, app: &mut App) -> CommandResult {
                 "  :reconnect/:rc    Respawn CLI for session",
                 "  :ls              List all sessions",
                 "  :model <name>    Change model",
-                "  :mode <mode>     Change permission mode",
+                "  :mode <mode>     Change permission mode (Tab completes)",
                 "  :env             List environment profiles",
+                "  :theme [name]    Switch or list color themes (Tab completes)",
                 "  :clear           Clear chat history",
                 "  :go <name>       Switch to session by name (fuzzy)",
+                "  :vsplit [name]   Show another session side by side (:split stacks)",
+                "  :only            Close the split",
                 "  :export <path>   Export conversation as markdown",
                 "  :pin             Pin current session to top",
                 "  :unpin           Unpin current session",
+                "  :allow [-g] <tool> [pattern]  Always allow (session, -g global)",
+                "  :deny [-g] <tool> [pattern]   Always deny (session, -g global)",
+                "  :rules           List permission rules",
+                "  :unrule <n>      Remove permission rule by index",
+                "  :inbox           Pending requests across sessions",
                 "  :q               Quit",
                 "",
                 "Keys (Normal mode):",
@@ -655,8 +815,16 @@ pub fn execute_command(cmd: Command, app: &mut App) -> CommandResult {
                 "  y        Yank response   z        Collapse tools",
                 "  p        Toggle plan     T        Toggle thinking",
                 "  t        Toggle tasks    Tab      Toggle sidebar",
+                "  I        Inbox (all)     M        Toggle mouse",
                 "  Ctrl+N   New session     Ctrl+C   Interrupt/quit",
                 "",
+                "Keys (Normal mode, after Ctrl+W):",
+                "  v/s      Split side/stack w        Switch pane",
+                "  x        Swap panes      r        Rotate split",
+                "  h/l      Move divider    o        Close split",
+                "  </>      Sidebar width   +/-      Task panel height",
+                "  =        Reset sizes",
+                "",
                 "Keys (Insert mode):",
                 "  Enter    Send message    Esc      Normal mode",
                 "  Ctrl+J   Insert newline  Up/Down  History / line nav",
@@ -683,3 +851,103 @@ pub fn execute_command(cmd: Command, app: &mut App) -> CommandResult {
         }
     }
 }
+
+/// First visible session whose name contains `query`, ignoring case
+fn find_session(app: &App, query: &str) -> Option<String> {
+    let query = query.to_lowercase();
+    app.visible_session_order().into_iter().find(|id| {
+        app.sessions
+            .get(id.as_str())
+            .map(|s| s.name.to_lowercase().contains(&query))
+            .unwrap_or(false)
+    })
+}
+
+/// Parse `[-g] <tool> [pattern]` and add the rule to the session or global set.
+fn add_permission_rule(app: &mut App, action: RuleAction, args: &str) {
+    let (global, rest) = match args.strip_prefix("-g") {
+        Some(rest) if rest.is_empty() || rest.starts_with(' ') => (true, rest.trim()),
+        _ => (false, args.trim()),
+    };
+    let mut parts = rest.splitn(2, ' ');
+    let tool = parts.next().unwrap_or("").to_string();
+    if tool.is_empty() {
+        app.flash(format!("Usage: :{} [-g] <tool> [pattern]", action));
+        return;
+    }
+    let pattern = parts
+        .next()
+        .map(|p| p.trim().to_string())
+        .filter(|p| !p.is_empty());
+    let rule = PermissionRule {
+        tool,
+        pattern,
+        action,
+    };
+    let scope = if global { "global" } else { "session" };
+    app.flash(format!("Added rule: {} {} ({})", action, rule, scope));
+    if global {
+        app.permission_rules.push(rule);
+        app.save_permission_rules();
+    } else if let Some(session) = app.active_session_mut() {
+        session.permission_rules.push(rule);
+        session.dirty_persist = true;
+        let _ = session.persist();
+    }
+    app.dirty = true;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::Session;
+    use crate::server::ws_server::Endpoint;
+
+    fn app_with_sessions(names: &[&str]) -> App {
+        let mut app = App::new(Endpoint::Tcp(0), "/tmp".to_string(), None);
+        for (i, name) in names.iter().enumerate() {
+            let id = format!("s{}", i + 1);
+            let session = Session::new(id.clone(), name.to_string(), "/tmp".to_string());
+            app.sessions.insert(id.clone(), session);
+            app.session_order.push(id);
+        }
+        app.active_session_id = Some("s1".to_string());
+        app
+    }
+
+    fn run(app: &mut App, input: &str) -> Option<String> {
+        app.flash_message = None;
+        execute_command(parse_command(input), app);
+        app.flash_message.as_ref().map(|(message, _)| message.clone())
+    }
+
+    #[test]
+    fn vsplit_opens_the_named_session_beside_the_active_one() {
+        let mut app = app_with_sessions(&["alpha", "beta", "gamma"]);
+        assert_eq!(run(&mut app, "vsplit gam"), None);
+        let split = app.layout.split.as_ref().unwrap();
+        assert_eq!(split.direction, SplitDirection::Vertical);
+        assert_eq!(split.other, "s3");
+
+        // Without a name, the next session in the sidebar
+        assert_eq!(run(&mut app, "sp"), None);
+        let split = app.layout.split.as_ref().unwrap();
+        assert_eq!(split.direction, SplitDirection::Horizontal);
+        assert_eq!(split.other, "s2");
+    }
+
+    #[test]
+    fn splitting_with_the_focused_session_is_refused() {
+        let mut app = app_with_sessions(&["alpha", "beta"]);
+        assert_eq!(run(&mut app, "vsplit alp").as_deref(), Some("Already in the focused pane"));
+        assert!(app.layout.split.is_none());
+        assert_eq!(
+            run(&mut app, "vsplit delta").as_deref(),
+            Some("No session matching 'delta'")
+        );
+
+        let mut app = app_with_sessions(&["alpha"]);
+        assert_eq!(run(&mut app, "vs").as_deref(), Some("No other session to split with"));
+        assert!(app.layout.split.is_none());
+    }
+}
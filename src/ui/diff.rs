@@ -0,0 +1,452 @@
+//! Line diffs of file-editing tool calls (`Edit`, `MultiEdit`, `Write`) for
+//! the chat and the permission banner.
+//!
+//! Line numbers of an edit come from where its `old_string` sits in the
+//! file, found with [`edit_start_lines`] before the edit runs. Where that
+//! isn't known the diff has no numbers and no hunk headers (`Write` content
+//! is the whole file, numbered from 1).
+
+use std::io::Read;
+use std::path::Path;
+
+use serde_json::Value;
+
+/// Unchanged lines kept around each change
+const CONTEXT: usize = 3;
+
+/// Past this many cells the LCS table isn't worth it; show a full rewrite
+const MAX_LCS_CELLS: usize = 250_000;
+
+/// Larger files aren't read to locate edits (this runs on the event loop)
+const MAX_LOCATE_BYTES: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffLine {
+    /// `@@ -a,b +c,d @@`
+    Hunk(String),
+    Context { old: Option<usize>, new: Option<usize>, text: String },
+    Removed { old: Option<usize>, text: String },
+    Added { new: Option<usize>, text: String },
+    /// Unchanged lines left out between hunks
+    Skipped(usize),
+}
+
+impl DiffLine {
+    /// Line number shown in the gutter
+    pub fn number(&self) -> Option<usize> {
+        match self {
+            DiffLine::Context { new, .. } | DiffLine::Added { new, .. } => *new,
+            DiffLine::Removed { old, .. } => *old,
+            DiffLine::Hunk(_) | DiffLine::Skipped(_) => None,
+        }
+    }
+}
+
+/// The diff a file-editing tool call makes, or None for other tools.
+/// `start_lines` are the file lines the edits start at, from
+/// [`edit_start_lines`].
+pub fn tool_diff(
+    name: &str,
+    input: &Value,
+    start_lines: &[Option<usize>],
+) -> Option<Vec<DiffLine>> {
+    let str_field = |v: &Value, key: &str| v.get(key).and_then(|s| s.as_str()).map(String::from);
+    let start = |i: usize| start_lines.get(i).copied().flatten();
+    match name {
+        "Edit" => {
+            let old = str_field(input, "old_string")?;
+            let new = str_field(input, "new_string")?;
+            Some(unified(&old, &new, start(0)))
+        }
+        "MultiEdit" => {
+            let edits = input.get("edits")?.as_array()?;
+            let mut lines = Vec::new();
+            for (i, edit) in edits.iter().enumerate() {
+                if let (Some(old), Some(new)) =
+                    (str_field(edit, "old_string"), str_field(edit, "new_string"))
+                {
+                    lines.extend(unified(&old, &new, start(i)));
+                }
+            }
+            Some(lines)
+        }
+        "Write" => {
+            // The whole file, so no hunk header
+            let content = str_field(input, "content")?;
+            let lines = split_lines(&content)
+                .into_iter()
+                .enumerate()
+                .map(|(i, text)| DiffLine::Added {
+                    new: Some(i + 1),
+                    text: text.to_string(),
+                })
+                .collect();
+            Some(lines)
+        }
+        _ => None,
+    }
+}
+
+/// The 1-based file line each edit of an `Edit` or `MultiEdit` call starts
+/// at, read from the file (relative paths against `cwd`) before the call
+/// runs. None for an edit whose `old_string` isn't in the file, or when the
+/// file is missing or larger than `MAX_LOCATE_BYTES`.
+pub fn edit_start_lines(name: &str, input: &Value, cwd: &str) -> Vec<Option<usize>> {
+    let edits: Vec<&Value> = match name {
+        "Edit" => vec![input],
+        "MultiEdit" => match input.get("edits").and_then(|e| e.as_array()) {
+            Some(edits) => edits.iter().collect(),
+            None => return Vec::new(),
+        },
+        _ => return Vec::new(),
+    };
+    let content = input
+        .get("file_path")
+        .and_then(|p| p.as_str())
+        .and_then(|path| read_small_file(&Path::new(cwd).join(path)));
+    match content {
+        Some(content) => locate_edits(content, &edits),
+        None => vec![None; edits.len()],
+    }
+}
+
+/// A regular file's text, if it is at most `MAX_LOCATE_BYTES` long
+fn read_small_file(path: &Path) -> Option<String> {
+    let meta = std::fs::metadata(path).ok()?;
+    if !meta.is_file() || meta.len() > MAX_LOCATE_BYTES {
+        return None;
+    }
+    // The file may grow between the check and the read
+    let mut content = String::new();
+    std::fs::File::open(path)
+        .ok()?
+        .take(MAX_LOCATE_BYTES + 1)
+        .read_to_string(&mut content)
+        .ok()?;
+    (content.len() as u64 <= MAX_LOCATE_BYTES).then_some(content)
+}
+
+/// Find each edit's `old_string`, applying the edits in order as the tool
+/// does. Once one is missing the file no longer matches, so the rest are
+/// unknown too.
+fn locate_edits(mut content: String, edits: &[&Value]) -> Vec<Option<usize>> {
+    let mut starts = Vec::new();
+    for edit in edits {
+        let field = |key: &str| edit.get(key).and_then(|s| s.as_str());
+        let found = match (field("old_string"), field("new_string")) {
+            (Some(old), Some(new)) if !old.is_empty() => {
+                content.find(old).map(|at| (at, old, new))
+            }
+            _ => None,
+        };
+        let (at, old, new) = match found {
+            Some(found) => found,
+            None => break,
+        };
+        starts.push(Some(content[..at].matches('\n').count() + 1));
+        content = if edit.get("replace_all").and_then(|r| r.as_bool()).unwrap_or(false) {
+            content.replace(old, new)
+        } else {
+            content.replacen(old, new, 1)
+        };
+    }
+    starts.resize(edits.len(), None);
+    starts
+}
+
+/// Lines added and removed
+pub fn diff_stats(lines: &[DiffLine]) -> (usize, usize) {
+    lines.iter().fold((0, 0), |(added, removed), line| match line {
+        DiffLine::Added { .. } => (added + 1, removed),
+        DiffLine::Removed { .. } => (added, removed + 1),
+        _ => (added, removed),
+    })
+}
+
+/// Unified diff of two texts: hunks with `CONTEXT` lines around changes.
+/// Both texts are numbered from `first_line` of the file; without it the
+/// lines have no numbers and the hunks no headers.
+pub fn unified(old: &str, new: &str, first_line: Option<usize>) -> Vec<DiffLine> {
+    let old_lines = split_lines(old);
+    let new_lines = split_lines(new);
+    let ops = diff_ops(&old_lines, &new_lines);
+
+    // Indices of ops to show: every change plus its context
+    let mut shown = vec![false; ops.len()];
+    for (i, op) in ops.iter().enumerate() {
+        if !matches!(op, Op::Same(..)) {
+            let from = i.saturating_sub(CONTEXT);
+            let to = (i + CONTEXT + 1).min(ops.len());
+            shown[from..to].iter_mut().for_each(|s| *s = true);
+        }
+    }
+
+    let mut lines = Vec::new();
+    let mut i = 0;
+    while i < ops.len() {
+        if !shown[i] {
+            let start = i;
+            while i < ops.len() && !shown[i] {
+                i += 1;
+            }
+            // Leading and trailing context is dropped quietly, like `diff -u`
+            if start > 0 && i < ops.len() {
+                lines.push(DiffLine::Skipped(i - start));
+            }
+            continue;
+        }
+        let start = i;
+        while i < ops.len() && shown[i] {
+            i += 1;
+        }
+        let hunk = &ops[start..i];
+        // 0-based position in a text to its line in the file
+        let number = |pos: usize| first_line.map(|first| first + pos);
+        if let Some(first) = first_line {
+            let (old_start, new_start) = hunk[0].position();
+            let old_count = hunk.iter().filter(|op| !matches!(op, Op::Added(..))).count();
+            let new_count = hunk.iter().filter(|op| !matches!(op, Op::Removed(..))).count();
+            lines.push(DiffLine::Hunk(format!(
+                "@@ -{},{} +{},{} @@",
+                first + old_start - 1,
+                old_count,
+                first + new_start - 1,
+                new_count
+            )));
+        }
+        for op in hunk {
+            lines.push(match op {
+                Op::Same(old, new) => DiffLine::Context {
+                    old: number(*old),
+                    new: number(*new),
+                    text: old_lines[*old].to_string(),
+                },
+                Op::Removed(old, _) => DiffLine::Removed {
+                    old: number(*old),
+                    text: old_lines[*old].to_string(),
+                },
+                Op::Added(_, new) => DiffLine::Added {
+                    new: number(*new),
+                    text: new_lines[*new].to_string(),
+                },
+            });
+        }
+    }
+    lines
+}
+
+fn split_lines(text: &str) -> Vec<&str> {
+    if text.is_empty() {
+        Vec::new()
+    } else {
+        text.strip_suffix('\n').unwrap_or(text).split('\n').collect()
+    }
+}
+
+/// One step of the edit script, with the 0-based positions in both texts
+enum Op {
+    Same(usize, usize),
+    Removed(usize, usize),
+    Added(usize, usize),
+}
+
+impl Op {
+    /// 1-based start of a hunk beginning with this op
+    fn position(&self) -> (usize, usize) {
+        match self {
+            Op::Same(old, new) | Op::Removed(old, new) | Op::Added(old, new) => (old + 1, new + 1),
+        }
+    }
+}
+
+/// Shortest edit script via LCS, after trimming the common prefix and suffix
+/// (most edits touch a few lines in the middle)
+fn diff_ops(old: &[&str], new: &[&str]) -> Vec<Op> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (a, b) = (&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix]);
+
+    let mut ops: Vec<Op> = (0..prefix).map(|i| Op::Same(i, i)).collect();
+    if a.len() * b.len() > MAX_LCS_CELLS {
+        ops.extend((0..a.len()).map(|i| Op::Removed(prefix + i, prefix)));
+        ops.extend((0..b.len()).map(|j| Op::Added(prefix + a.len(), prefix + j)));
+    } else {
+        // lcs[i][j]: LCS length of a[i..] and b[j..]
+        let mut lcs = vec![vec![0u32; b.len() + 1]; a.len() + 1];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lcs[i][j] = if a[i] == b[j] {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            if i < a.len() && j < b.len() && a[i] == b[j] {
+                ops.push(Op::Same(prefix + i, prefix + j));
+                i += 1;
+                j += 1;
+            } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+                ops.push(Op::Removed(prefix + i, prefix + j));
+                i += 1;
+            } else {
+                ops.push(Op::Added(prefix + i, prefix + j));
+                j += 1;
+            }
+        }
+    }
+    let (old_end, new_end) = (old.len() - suffix, new.len() - suffix);
+    ops.extend((0..suffix).map(|k| Op::Same(old_end + k, new_end + k)));
+    ops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn texts(lines: &[DiffLine]) -> Vec<String> {
+        lines
+            .iter()
+            .map(|line| match line {
+                DiffLine::Hunk(header) => header.clone(),
+                DiffLine::Context { text, .. } => format!(" {}", text),
+                DiffLine::Removed { text, .. } => format!("-{}", text),
+                DiffLine::Added { text, .. } => format!("+{}", text),
+                DiffLine::Skipped(n) => format!("~{}", n),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn diff_ops_trims_common_prefix_and_suffix() {
+        let old = ["a", "b", "c", "d"];
+        let new = ["a", "x", "c", "d"];
+        let ops: Vec<(char, usize, usize)> = diff_ops(&old, &new)
+            .into_iter()
+            .map(|op| match op {
+                Op::Same(o, n) => ('=', o, n),
+                Op::Removed(o, n) => ('-', o, n),
+                Op::Added(o, n) => ('+', o, n),
+            })
+            .collect();
+        assert_eq!(
+            ops,
+            [('=', 0, 0), ('-', 1, 1), ('+', 2, 1), ('=', 2, 2), ('=', 3, 3)]
+        );
+    }
+
+    #[test]
+    fn diff_ops_over_the_lcs_cap_rewrites_the_middle() {
+        let old: Vec<String> = (0..600).map(|i| format!("old {}", i)).collect();
+        let new: Vec<String> = (0..600).map(|i| format!("new {}", i)).collect();
+        let mut old: Vec<&str> = old.iter().map(String::as_str).collect();
+        let mut new: Vec<&str> = new.iter().map(String::as_str).collect();
+        old.insert(0, "head");
+        new.insert(0, "head");
+        old.push("tail");
+        new.push("tail");
+        const { assert!(600 * 600 > MAX_LCS_CELLS) };
+
+        let ops = diff_ops(&old, &new);
+        assert_eq!(ops.len(), 2 + 600 + 600);
+        assert!(matches!(ops[0], Op::Same(0, 0)));
+        assert!(ops[1..601].iter().all(|op| matches!(op, Op::Removed(..))));
+        assert!(ops[601..1201].iter().all(|op| matches!(op, Op::Added(..))));
+        assert!(matches!(ops[1201], Op::Same(601, 601)));
+    }
+
+    #[test]
+    fn unified_numbers_from_the_first_line() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nJ\n";
+        let lines = unified(old, new, Some(100));
+        assert_eq!(
+            texts(&lines),
+            [
+                "@@ -100,5 +100,5 @@", " a", "-b", "+B", " c", " d", " e",
+                "~1",
+                "@@ -106,4 +106,4 @@", " g", " h", " i", "-j", "+J",
+            ]
+        );
+        assert_eq!(lines[2].number(), Some(101));
+        assert_eq!(lines[3].number(), Some(101));
+        assert_eq!(lines[13].number(), Some(109));
+        assert_eq!(diff_stats(&lines), (2, 2));
+    }
+
+    #[test]
+    fn unified_without_a_position_has_no_numbers_or_headers() {
+        let lines = unified("a\nb\n", "a\nc\n", None);
+        assert_eq!(texts(&lines), [" a", "-b", "+c"]);
+        assert!(lines.iter().all(|line| line.number().is_none()));
+    }
+
+    #[test]
+    fn edits_are_located_in_the_file() {
+        let content = "fn main() {\n    one();\n    two();\n}\n".to_string();
+        let edit = json!({ "old_string": "    two();", "new_string": "    three();" });
+        assert_eq!(locate_edits(content.clone(), &[&edit]), [Some(3)]);
+
+        // Later edits see the file as the earlier ones left it
+        let first = json!({ "old_string": "one();\n", "new_string": "a();\nb();\nc();\n" });
+        let second = json!({ "old_string": "two", "new_string": "2" });
+        assert_eq!(locate_edits(content.clone(), &[&first, &second]), [Some(2), Some(5)]);
+
+        // A missing edit makes the rest unknown
+        let missing = json!({ "old_string": "nope", "new_string": "x" });
+        assert_eq!(locate_edits(content, &[&missing, &second]), [None, None]);
+    }
+
+    #[test]
+    fn tool_diff_uses_the_located_lines() {
+        let input = json!({
+            "file_path": "src/main.rs",
+            "old_string": "x\ny",
+            "new_string": "x\nz",
+        });
+        let lines = tool_diff("Edit", &input, &[Some(40)]).unwrap();
+        assert_eq!(texts(&lines), ["@@ -40,2 +40,2 @@", " x", "-y", "+z"]);
+        assert_eq!(lines[3].number(), Some(41));
+
+        let lines = tool_diff("Edit", &input, &[]).unwrap();
+        assert_eq!(texts(&lines), [" x", "-y", "+z"]);
+        assert!(tool_diff("Bash", &json!({ "command": "ls" }), &[]).is_none());
+    }
+
+    #[test]
+    fn edit_start_lines_reads_relative_to_cwd() {
+        let dir = std::env::temp_dir().join(format!("companion-diff-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("f.txt"), "one\ntwo\nthree\n").unwrap();
+        let cwd = dir.to_string_lossy();
+        let input = json!({ "file_path": "f.txt", "old_string": "three", "new_string": "3" });
+        assert_eq!(edit_start_lines("Edit", &input, &cwd), [Some(3)]);
+        let input = json!({ "file_path": "gone.txt", "old_string": "three", "new_string": "3" });
+        assert_eq!(edit_start_lines("Edit", &input, &cwd), [None]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn large_files_are_not_read() {
+        let dir = std::env::temp_dir().join(format!("companion-diff-big-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut content = "x\n".repeat(MAX_LOCATE_BYTES as usize / 2);
+        content.push_str("three\n");
+        std::fs::write(dir.join("big.txt"), content).unwrap();
+        let cwd = dir.to_string_lossy();
+        let input = json!({ "file_path": "big.txt", "old_string": "three", "new_string": "3" });
+        assert_eq!(edit_start_lines("Edit", &input, &cwd), [None]);
+        // Nor anything that isn't a regular file
+        let input = json!({ "file_path": ".", "old_string": "three", "new_string": "3" });
+        assert_eq!(edit_start_lines("Edit", &input, &cwd), [None]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,33 +1,37 @@
 use std::collections::HashMap;
+use std::io::Write;
 use std::time::Duration;
 
 use crossterm::{
     cursor, execute,
-    event::{Event, EventStream, KeyEvent},
+    event::{DisableMouseCapture, Event, EventStream, KeyEvent},
     terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use futures_util::StreamExt;
 use tokio::sync::mpsc;
 
 use crate::app::{
-    App, AppEvent, ChatMessage, ChatRole, Mode, PendingPermission, SessionStatus, TaskItem,
-    TaskStatus,
+    App, AppEvent, ChatMessage, ChatRole, Mode, PendingControl, PendingPermission, SessionStatus,
+    StreamBlock, StreamBlockKind, TaskItem, TaskStatus,
 };
+use crate::hooks;
+use crate::permissions::rules::{self, RuleAction};
 use crate::process::launcher::CliLauncher;
 use crate::protocol::types::{
-    self, CliMessage, ControlRequestPayload, OutgoingUserMessage,
+    self, CliMessage, ControlRequestPayload, OutgoingControlResponse, OutgoingUserMessage,
 };
 use crate::ui::commands;
 use crate::ui::keybindings;
 use crate::ui::renderer;
 
 /// Guard that restores the terminal on drop (including panics)
-struct TermGuard;
+pub struct TermGuard;
 
 impl Drop for TermGuard {
     fn drop(&mut self) {
         let _ = execute!(
             std::io::stdout(),
+            DisableMouseCapture,
             cursor::Show,
             LeaveAlternateScreen
         );
@@ -35,6 +39,28 @@ impl Drop for TermGuard {
     }
 }
 
+impl TermGuard {
+    /// Enter alternate screen + raw mode; restored when the guard drops.
+    pub fn enter() -> anyhow::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(std::io::stdout(), EnterAlternateScreen, cursor::Hide)?;
+        Ok(Self)
+    }
+}
+
+/// Where frames are drawn. The local terminal is always attached; a daemon
+/// draws for whichever client is attached, if any.
+pub trait Screen: Write {
+    fn attached(&self) -> bool {
+        true
+    }
+
+    /// Quit was requested while running as a daemon: drop the client instead.
+    fn detach(&mut self) {}
+}
+
+impl Screen for std::io::Stdout {}
+
 /// Run the TUI event loop. Takes ownership of the terminal.
 pub async fn run(
     mut app: App,
@@ -43,14 +69,12 @@ pub async fn run(
 ) -> anyhow::Result<()> {
     let mut stdout = std::io::stdout();
 
-    // Enter alternate screen + raw mode
-    terminal::enable_raw_mode()?;
-    execute!(stdout, EnterAlternateScreen, cursor::Hide)?;
-
     // Guard ensures terminal is restored even on panic
-    let _guard = TermGuard;
+    let _guard = TermGuard::enter()?;
 
-    let result = run_inner(&mut app, &mut stdout, &mut event_rx, &event_tx).await;
+    let mut term_reader = EventStream::new().filter_map(|e| futures_util::future::ready(e.ok()));
+    let result =
+        run_inner(&mut app, &mut stdout, &mut term_reader, &mut event_rx, &event_tx).await;
 
     // Persist all sessions before exiting
     app.persist_all_sessions();
@@ -60,35 +84,53 @@ pub async fn run(
     result
 }
 
+/// Run the event loop headless: terminal events come from `input` and frames
+/// go to `screen` (see `daemon::server`).
+#[cfg(unix)]
+pub async fn run_headless(
+    mut app: App,
+    screen: &mut impl Screen,
+    mut input: mpsc::UnboundedReceiver<Event>,
+    mut event_rx: mpsc::UnboundedReceiver<AppEvent>,
+    event_tx: mpsc::UnboundedSender<AppEvent>,
+) -> anyhow::Result<()> {
+    let mut term_reader = futures_util::stream::poll_fn(move |cx| input.poll_recv(cx));
+    let result = run_inner(&mut app, screen, &mut term_reader, &mut event_rx, &event_tx).await;
+    app.persist_all_sessions();
+    result
+}
+
 async fn run_inner(
     app: &mut App,
-    stdout: &mut std::io::Stdout,
+    screen: &mut impl Screen,
+    term_reader: &mut (impl futures_util::Stream<Item = Event> + Unpin),
     event_rx: &mut mpsc::UnboundedReceiver<AppEvent>,
     event_tx: &mpsc::UnboundedSender<AppEvent>,
 ) -> anyhow::Result<()> {
-    let mut term_reader = EventStream::new();
     let mut tick = tokio::time::interval(Duration::from_millis(100));
     tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
 
     // Initial render
-    renderer::render(app, stdout)?;
-    app.dirty = false;
+    if screen.attached() {
+        renderer::render(app, screen)?;
+        app.dirty = false;
+    }
 
     loop {
         tokio::select! {
             // Terminal events (keyboard, resize)
             maybe_event = term_reader.next() => {
-                if let Some(Ok(event)) = maybe_event {
+                if let Some(event) = maybe_event {
                     handle_terminal_event(event, app, event_tx);
                 }
             }
             // App events (WebSocket messages, connection changes)
             maybe_event = event_rx.recv() => {
-                if let Some(event) = maybe_event {
-                    handle_app_event(event, app);
-                } else {
+                match maybe_event {
+                    Some(AppEvent::Shutdown) => break,
+                    Some(event) => handle_app_event(event, app),
                     // Channel closed — all senders dropped
-                    break;
+                    None => break,
                 }
             }
             // Tick timer for periodic redraws
@@ -101,6 +143,7 @@ async fn run_inner(
                         app.dirty = true;
                     }
                 }
+                expire_pending_controls(app);
                 // Force redraw when any session is running (for spinner)
                 if app.sessions.values().any(|s| s.status == SessionStatus::Running || s.status == SessionStatus::Compacting) {
                     app.dirty = true;
@@ -128,14 +171,22 @@ async fn run_inner(
         // Process pending CLI spawns
         process_pending_spawns(app, event_tx);
 
-        // Redraw if dirty
-        if app.dirty {
-            renderer::render(app, stdout)?;
-            app.dirty = false;
-        }
+        // Tell API subscribers what changed
+        app.events.publish_changes(&app.sessions);
 
         if app.should_quit {
-            break;
+            if !app.daemon_mode {
+                break;
+            }
+            // A daemon outlives its clients
+            app.should_quit = false;
+            screen.detach();
+        }
+
+        // Redraw if dirty (nothing to draw on while detached)
+        if app.dirty && screen.attached() {
+            renderer::render(app, screen)?;
+            app.dirty = false;
         }
     }
 
@@ -157,7 +208,7 @@ fn spawn_cli_for_session(
     event_tx: &mpsc::UnboundedSender<AppEvent>,
 ) {
     // Gather data (immutable borrow)
-    let (port, cwd, model, resume_id, env_vars) = {
+    let (endpoint, cwd, model, resume_id, env_vars) = {
         let session = match app.sessions.get(session_id) {
             Some(s) => s,
             None => return,
@@ -179,7 +230,7 @@ fn spawn_cli_for_session(
             Some(session.model.clone())
         };
         (
-            app.ws_port,
+            app.endpoint.clone(),
             session.cwd.clone(),
             model,
             session.cli_session_id.clone(),
@@ -190,9 +241,11 @@ fn spawn_cli_for_session(
     let sid = session_id.to_string();
     let etx = event_tx.clone();
 
-    let launcher = CliLauncher::new(port, sid.clone(), cwd, model)
+    let launcher = CliLauncher::new(endpoint, sid.clone(), cwd, model)
         .with_env_vars(env_vars)
         .with_resume_session_id(resume_id);
+    // Replaces the previous launch's token, so a stale process can't reconnect
+    app.cli_tokens.insert(&sid, launcher.token());
 
     let handle = tokio::spawn(async move {
         match launcher.spawn().await {
@@ -224,7 +277,10 @@ fn handle_terminal_event(
 ) {
     match event {
         Event::Key(key) => handle_key_event(key, app, event_tx),
-        Event::Resize(_, _) => {
+        Event::Mouse(mouse) => keybindings::handle_mouse(mouse, app),
+        Event::Resize(width, height) => {
+            app.screen_size = Some((width, height));
+            app.render_state.force_redraw();
             app.dirty = true;
         }
         _ => {}
@@ -236,15 +292,19 @@ fn handle_key_event(
     app: &mut App,
     event_tx: &mpsc::UnboundedSender<AppEvent>,
 ) {
+    // Inbox overlay intercepts all keys while open
+    if app.inbox.is_some() && keybindings::handle_inbox_keys(key, app) {
+        return;
+    }
+
     // Permission overlay intercepts all keys when pending
     if app
         .active_session()
-        .map(|s| s.pending_permission.is_some())
+        .map(|s| !s.pending_permissions.is_empty())
         .unwrap_or(false)
+        && keybindings::handle_permission_keys(key, app)
     {
-        if keybindings::handle_permission_keys(key, app) {
-            return;
-        }
+        return;
     }
 
     // Question overlay intercepts all keys when pending
@@ -252,10 +312,9 @@ fn handle_key_event(
         .active_session()
         .map(|s| s.pending_question.is_some())
         .unwrap_or(false)
+        && keybindings::handle_question_keys(key, app)
     {
-        if keybindings::handle_question_keys(key, app) {
-            return;
-        }
+        return;
     }
 
     match app.mode {
@@ -291,14 +350,24 @@ fn send_user_message(app: &mut App, _event_tx: &mpsc::UnboundedSender<AppEvent>)
     app.input_history.push(text.clone());
     app.input_history_idx = None;
 
+    if let Some(sid) = app.active_session_id.clone() {
+        send_text(app, &sid, text);
+    }
+}
+
+/// Send a prompt to a session: straight to its CLI when connected, otherwise
+/// queued for delivery once a (re)spawned CLI connects.
+pub fn send_text(app: &mut App, session_id: &str, text: String) {
     // Check the CLI connection state
     let (cli_connected, has_cli_session, has_sender) = app
-        .active_session()
+        .sessions
+        .get(session_id)
         .map(|s| (s.cli_connected, s.cli_session_id.is_some(), s.cli_sender.is_some()))
         .unwrap_or((false, false, false));
 
     tracing::info!(
-        "send_user_message: text={:?}, cli_connected={}, has_cli_session={}, has_sender={}",
+        "send_text: session={}, text={:?}, cli_connected={}, has_cli_session={}, has_sender={}",
+        session_id,
         &text,
         cli_connected,
         has_cli_session,
@@ -306,13 +375,14 @@ fn send_user_message(app: &mut App, _event_tx: &mpsc::UnboundedSender<AppEvent>)
     );
 
     // Add to chat history first (always)
-    if let Some(session) = app.active_session_mut() {
+    if let Some(session) = app.sessions.get_mut(session_id) {
         session.messages.push(ChatMessage {
             role: ChatRole::User,
             content: text.clone(),
             content_blocks: None,
             model: None,
             timestamp: chrono::Utc::now().timestamp(),
+            parent_tool_use_id: None,
         });
         session.scroll_locked = false;
         session.scroll_offset = 0;
@@ -320,19 +390,19 @@ fn send_user_message(app: &mut App, _event_tx: &mpsc::UnboundedSender<AppEvent>)
 
     if has_sender {
         // Try to send directly — CLI appears connected
-        let sent = if let Some(session) = app.active_session_mut() {
-            let session_id = session
+        let sent = if let Some(session) = app.sessions.get_mut(session_id) {
+            let cli_session_id = session
                 .cli_session_id
                 .clone()
                 .unwrap_or_else(|| session.id.clone());
-            let msg = OutgoingUserMessage::new(text.clone(), session_id.clone());
+            let msg = OutgoingUserMessage::new(text.clone(), cli_session_id.clone());
             let ndjson = msg.to_ndjson();
-            tracing::info!("Sending to CLI (session_id={}): {}", session_id, &ndjson);
+            tracing::info!("Sending to CLI (session_id={}): {}", cli_session_id, &ndjson);
             let ok = session.send_to_cli(&ndjson);
             tracing::info!("send_to_cli returned: {}", ok);
             if ok {
                 session.status = SessionStatus::Running;
-                session.streaming_text.clear();
+                session.clear_stream();
                 session.scroll_locked = false;
                 session.scroll_offset = 0;
             }
@@ -351,31 +421,57 @@ fn send_user_message(app: &mut App, _event_tx: &mpsc::UnboundedSender<AppEvent>)
     }
 
     // CLI is not connected (or send failed) — queue message for delivery after init
-    if let Some(session) = app.active_session_mut() {
+    if let Some(session) = app.sessions.get_mut(session_id) {
         session.queued_messages.push(text);
         session.status = SessionStatus::Running; // show "thinking" state
     }
 
     if !cli_connected || !has_sender {
         // Need to spawn/respawn CLI
-        if let Some(sid) = app.active_session_id.clone() {
-            if has_cli_session {
-                if let Some(session) = app.active_session_mut() {
-                    session.add_system_message("Resuming session...".to_string());
-                }
-            }
-            // Check if CLI process is already running (pending spawn or starting up)
-            let already_spawning = app.pending_spawns.contains(&sid)
-                || app.sessions.get(&sid).map(|s| s.cli_process_handle.is_some()).unwrap_or(false);
-            if !already_spawning {
-                app.pending_spawns.push(sid);
+        if has_cli_session {
+            if let Some(session) = app.sessions.get_mut(session_id) {
+                session.add_system_message("Resuming session...".to_string());
             }
         }
+        // Check if CLI process is already running (pending spawn or starting up)
+        let already_spawning = app.pending_spawns.iter().any(|id| id == session_id)
+            || app
+                .sessions
+                .get(session_id)
+                .map(|s| s.cli_process_handle.is_some())
+                .unwrap_or(false);
+        if !already_spawning {
+            app.pending_spawns.push(session_id.to_string());
+        }
     }
 
     app.dirty = true;
 }
 
+/// Create a session for a CLI that connected on an id we didn't launch
+/// (only possible with the `--connect` token). cwd, model etc. arrive with
+/// its system/init.
+fn adopt_external_cli(app: &mut App, session_id: &str) {
+    let name = crate::app::generate_session_name();
+    tracing::info!("Adopting external CLI {} as session {}", session_id, name);
+    let mut session =
+        crate::app::Session::new(session_id.to_string(), name.clone(), app.default_cwd.clone());
+    session.add_system_message("Attached to external CLI".to_string());
+    app.sessions.insert(session_id.to_string(), session);
+    app.session_order.push(session_id.to_string());
+
+    // Take focus from a session that is still waiting for its CLI
+    let active_waiting = app
+        .active_session()
+        .map(|s| !s.cli_connected)
+        .unwrap_or(true);
+    if active_waiting {
+        app.active_session_id = Some(session_id.to_string());
+    } else {
+        app.flash(format!("External CLI attached as {}", name));
+    }
+}
+
 // ─── App Event Handling ─────────────────────────────────────────────────────
 
 fn handle_app_event(event: AppEvent, app: &mut App) {
@@ -385,6 +481,9 @@ fn handle_app_event(event: AppEvent, app: &mut App) {
             sender,
         } => {
             tracing::info!("CLI connected for session {}", session_id);
+            if !app.sessions.contains_key(&session_id) {
+                adopt_external_cli(app, &session_id);
+            }
             if let Some(session) = app.sessions.get_mut(&session_id) {
                 session.cli_connected = true;
                 session.cli_sender = Some(sender);
@@ -399,6 +498,10 @@ fn handle_app_event(event: AppEvent, app: &mut App) {
                     tracing::info!("CLI disconnected for session {}", session_id);
                     session.cli_connected = false;
                     session.cli_sender = None;
+                    session.pending_controls.clear();
+                    session.requested_permission_mode = None;
+                    // The turn they wait for won't finish
+                    session.turn_waiters.clear();
                     session.status = SessionStatus::WaitingForCli;
                     session.add_system_message("Claude CLI disconnected".to_string());
                     // Persist on disconnect
@@ -411,12 +514,34 @@ fn handle_app_event(event: AppEvent, app: &mut App) {
             session_id,
             message,
         } => {
-            handle_cli_message(message, &session_id, app);
+            handle_cli_message(*message, &session_id, app);
+        }
+        AppEvent::Control { request, reply } => {
+            crate::control::handle(app, request, reply);
+        }
+        AppEvent::ClientTerminal {
+            color_depth,
+            hyperlinks,
+            theme,
+        } => {
+            app.color_depth = color_depth;
+            app.hyperlinks = hyperlinks;
+            if let Some(name) = theme {
+                if !app.set_theme(&name) {
+                    app.flash(format!("Unknown theme: {}", name));
+                }
+            }
+            // A newly attached client starts from a blank screen
+            app.render_state.force_redraw();
+            app.dirty = true;
         }
+        // Handled by the event loop itself
+        AppEvent::Shutdown => {}
         AppEvent::CliProcessExited { session_id } => {
             tracing::info!("CLI process exited for session {}", session_id);
             if let Some(session) = app.sessions.get_mut(&session_id) {
                 session.cli_process_handle = None;
+                session.turn_waiters.clear();
                 // If CLI never connected (CliDisconnected guard won't fire),
                 // reset state here to avoid being stuck in Running forever.
                 if !session.cli_connected {
@@ -440,15 +565,24 @@ fn handle_cli_message(msg: CliMessage, session_id: &str, app: &mut App) {
     match msg {
         CliMessage::System(sys) => handle_system_message(sys, session_id, app),
         CliMessage::Assistant(asst) => handle_assistant_message(asst, session_id, app),
+        CliMessage::User(user) => handle_user_message(user, session_id, app),
         CliMessage::Result(result) => handle_result_message(result, session_id, app),
         CliMessage::StreamEvent(stream) => handle_stream_event(stream, session_id, app),
         CliMessage::ControlRequest(ctrl) => handle_control_request(ctrl, session_id, app),
+        CliMessage::ControlResponse(ctrl) => handle_control_response(ctrl, session_id, app),
         CliMessage::ToolProgress(prog) => {
             if let Some(session) = app.sessions.get_mut(session_id) {
-                session.current_tool = Some((
+                let progress = (
                     prog.tool_name.clone(),
                     prog.elapsed_time_seconds.unwrap_or(0.0),
-                ));
+                );
+                session
+                    .tool_durations
+                    .insert(prog.tool_use_id.clone(), progress.1);
+                if let Some(parent) = prog.parent_tool_use_id {
+                    session.thread_progress.insert(parent, progress.clone());
+                }
+                session.current_tool = Some(progress);
             }
         }
         CliMessage::ToolUseSummary(summary) => {
@@ -459,6 +593,7 @@ fn handle_cli_message(msg: CliMessage, session_id: &str, app: &mut App) {
                     content_blocks: None,
                     model: None,
                     timestamp: chrono::Utc::now().timestamp(),
+                    parent_tool_use_id: None,
                 });
             }
         }
@@ -502,6 +637,7 @@ fn handle_cli_message(msg: CliMessage, session_id: &str, app: &mut App) {
                             content_blocks: None,
                             model: entry.model.clone(),
                             timestamp: chrono::Utc::now().timestamp(),
+                            parent_tool_use_id: None,
                         });
                     }
                 }
@@ -538,7 +674,7 @@ fn handle_system_message(msg: types::SystemMessage, session_id: &str, app: &mut
                 session.cwd = cwd.clone();
             }
             if let Some(mode) = &msg.permission_mode {
-                session.permission_mode = mode.clone();
+                apply_permission_mode(session, mode);
             }
             if let Some(version) = &msg.claude_code_version {
                 session.version = version.clone();
@@ -583,7 +719,7 @@ fn handle_system_message(msg: types::SystemMessage, session_id: &str, app: &mut
                     let msg = OutgoingUserMessage::new(last_msg.clone(), cli_session_id);
                     session.send_to_cli(&msg.to_ndjson());
                     session.status = SessionStatus::Running;
-                    session.streaming_text.clear();
+                    session.clear_stream();
                     if !session.scroll_locked {
                         session.scroll_offset = 0;
                     }
@@ -591,6 +727,9 @@ fn handle_system_message(msg: types::SystemMessage, session_id: &str, app: &mut
             }
         }
         "status" => {
+            if let Some(mode) = &msg.permission_mode {
+                apply_permission_mode(session, mode);
+            }
             if let Some(status) = &msg.status {
                 if status.as_str() == Some("compacting") {
                     session.status = SessionStatus::Compacting;
@@ -608,44 +747,114 @@ fn handle_system_message(msg: types::SystemMessage, session_id: &str, app: &mut
     }
 }
 
+/// Take a permission mode reported by the CLI, confirming a pending :mode
+fn apply_permission_mode(session: &mut crate::app::Session, mode: &str) {
+    if session.requested_permission_mode.as_ref().map(|(m, _)| m.as_str()) == Some(mode) {
+        session.requested_permission_mode = None;
+        session.add_system_message(format!("Permission mode: {}", mode));
+    }
+    if mode != "plan" {
+        session.previous_permission_mode = None;
+    } else if session.permission_mode != "plan" {
+        // Remember what `p` goes back to
+        session.previous_permission_mode = Some(session.permission_mode.clone());
+    }
+    session.permission_mode = mode.to_string();
+}
+
 fn handle_assistant_message(msg: types::AssistantMessage, session_id: &str, app: &mut App) {
     let session = match app.sessions.get_mut(session_id) {
         Some(s) => s,
         None => return,
     };
 
-    // Deduplicate: the CLI often sends the same assistant message twice
+    // Deduplicate: the CLI often sends the same assistant message twice,
+    // possibly with a subagent's messages in between
     let msg_id = &msg.message.id;
-    if session.last_assistant_msg_id.as_deref() == Some(msg_id) {
+    let last_id = session.last_assistant_msg_ids.get(&msg.parent_tool_use_id);
+    if last_id == Some(msg_id) {
         tracing::debug!("Skipping duplicate assistant message: {}", msg_id);
-        session.streaming_text.clear();
+        if msg.parent_tool_use_id.is_none() {
+            session.clear_stream();
+        }
         return;
     }
-    session.last_assistant_msg_id = Some(msg_id.clone());
+    session
+        .last_assistant_msg_ids
+        .insert(msg.parent_tool_use_id.clone(), msg_id.clone());
 
-    // Extract tasks from tool_use blocks
-    extract_tasks_from_blocks(&msg.message.content, session);
+    // Subagent todo lists aren't the session's task list
+    if msg.parent_tool_use_id.is_none() {
+        extract_tasks_from_blocks(&msg.message.content, session);
+    }
 
     // Extract AskUserQuestion if present
     extract_question_from_blocks(&msg.message.content, session);
 
+    // Edits are located before they run, for the line numbers of their diffs
+    for block in &msg.message.content {
+        if let types::ContentBlock::ToolUse { id, name, input } = block {
+            session.locate_edits(id, name, input);
+        }
+    }
+
     let text = types::extract_text_from_blocks(&msg.message.content);
 
+    let is_subagent = msg.parent_tool_use_id.is_some();
     session.messages.push(ChatMessage {
         role: ChatRole::Assistant,
         content: text,
         content_blocks: Some(msg.message.content),
         model: msg.message.model,
         timestamp: chrono::Utc::now().timestamp(),
+        parent_tool_use_id: msg.parent_tool_use_id,
     });
 
-    session.streaming_text.clear();
+    // The main agent's partial message is still streaming
+    if is_subagent {
+        return;
+    }
+    session.clear_stream();
     if !session.scroll_locked {
         session.scroll_offset = 0;
     }
     session.status = SessionStatus::Running;
 }
 
+/// Tool results come back as `user` messages; keep them so the renderer can
+/// pair each one with its call. Plain text (an echoed prompt) is already in
+/// the transcript.
+fn handle_user_message(msg: types::UserMessage, session_id: &str, app: &mut App) {
+    use types::ContentBlock;
+
+    let session = match app.sessions.get_mut(session_id) {
+        Some(s) => s,
+        None => return,
+    };
+    let blocks: Vec<ContentBlock> = match serde_json::from_value(msg.message.content) {
+        Ok(blocks) => blocks,
+        Err(_) => return,
+    };
+    let results: Vec<ContentBlock> = blocks
+        .into_iter()
+        .filter(|b| matches!(b, ContentBlock::ToolResult { .. }))
+        .collect();
+    if results.is_empty() {
+        return;
+    }
+    session.messages.push(ChatMessage {
+        role: ChatRole::User,
+        content: String::new(),
+        content_blocks: Some(results),
+        model: None,
+        timestamp: chrono::Utc::now().timestamp(),
+        parent_tool_use_id: msg.parent_tool_use_id,
+    });
+    if !session.scroll_locked {
+        session.scroll_offset = 0;
+    }
+}
+
 fn handle_result_message(msg: types::ResultMessage, session_id: &str, app: &mut App) {
     let session = match app.sessions.get_mut(session_id) {
         Some(s) => s,
@@ -687,11 +896,24 @@ fn handle_result_message(msg: types::ResultMessage, session_id: &str, app: &mut
         }
     }
 
-    session.streaming_text.clear();
+    session.clear_stream();
     session.current_tool = None;
+    session.thread_progress.clear();
     session.stream_start = None;
     session.status = SessionStatus::Idle;
 
+    // Answer `send --wait` requests with this turn's result
+    for waiter in session.turn_waiters.drain(..) {
+        let _ = waiter.send(serde_json::json!({
+            "ok": !msg.is_error,
+            "session": session_id,
+            "result": msg.result,
+            "errors": msg.errors,
+            "total_cost_usd": session.total_cost_usd,
+            "num_turns": session.num_turns,
+        }));
+    }
+
     // Desktop notification: bell + macOS notification
     print!("\x07"); // Terminal bell
     let session_name = session.name.clone();
@@ -720,27 +942,106 @@ fn handle_stream_event(msg: types::StreamEventMessage, session_id: &str, app: &m
         None => return,
     };
 
-    let event = &msg.event;
-
-    if event.get("type").and_then(|v| v.as_str()) == Some("message_start") {
-        session.status = SessionStatus::Running;
-        session.stream_start = Some(std::time::Instant::now());
-        session.stream_output_tokens = 0;
+    // Subagent output shows up in its thread once each message completes
+    if msg.parent_tool_use_id.is_some() {
         return;
     }
 
-    if event.get("type").and_then(|v| v.as_str()) == Some("content_block_delta") {
-        if let Some(delta) = event.get("delta") {
-            if delta.get("type").and_then(|v| v.as_str()) == Some("text_delta") {
-                if let Some(text) = delta.get("text").and_then(|v| v.as_str()) {
-                    session.streaming_text.push_str(text);
-                    // Approximate token count (rough: ~4 chars per token)
-                    session.stream_output_tokens += (text.len() as u64 + 3) / 4;
-                    app.dirty = true;
+    let event = &msg.event;
+    let index = event.get("index").and_then(|v| v.as_u64()).unwrap_or(0);
+
+    match event.get("type").and_then(|v| v.as_str()).unwrap_or("") {
+        "message_start" => {
+            session.status = SessionStatus::Running;
+            session.stream_start = Some(std::time::Instant::now());
+            session.stream_output_tokens = 0;
+            session.stream_tokens_exact = false;
+            session.stream_blocks.clear();
+            return;
+        }
+        "content_block_start" => {
+            let block = event.get("content_block");
+            let kind = match block.and_then(|b| b.get("type")).and_then(|v| v.as_str()) {
+                Some("text") => StreamBlockKind::Text,
+                Some("thinking") | Some("redacted_thinking") => StreamBlockKind::Thinking,
+                Some("tool_use") | Some("server_tool_use") => StreamBlockKind::ToolUse {
+                    name: block
+                        .and_then(|b| b.get("name"))
+                        .and_then(|v| v.as_str())
+                        .unwrap_or("tool")
+                        .to_string(),
+                },
+                other => {
+                    tracing::debug!("Unhandled content block type: {:?}", other);
+                    return;
                 }
+            };
+            session.stream_blocks.retain(|b| b.index != index);
+            session.stream_blocks.push(StreamBlock {
+                index,
+                kind,
+                content: String::new(),
+                done: false,
+            });
+        }
+        "content_block_delta" => {
+            let delta = match event.get("delta") {
+                Some(d) => d,
+                None => return,
+            };
+            let (kind, fragment) = match delta.get("type").and_then(|v| v.as_str()) {
+                Some("text_delta") => (StreamBlockKind::Text, delta.get("text")),
+                Some("thinking_delta") => (StreamBlockKind::Thinking, delta.get("thinking")),
+                Some("input_json_delta") => (
+                    StreamBlockKind::ToolUse {
+                        name: "tool".to_string(),
+                    },
+                    delta.get("partial_json"),
+                ),
+                // signature_delta etc. carry nothing to show
+                _ => return,
+            };
+            let fragment = match fragment.and_then(|v| v.as_str()) {
+                Some(f) => f,
+                None => return,
+            };
+            if kind == StreamBlockKind::Text {
+                session.streaming_text.push_str(fragment);
+            }
+            // Deltas without a content_block_start still get a block
+            match session.stream_blocks.iter_mut().find(|b| b.index == index) {
+                Some(block) => block.content.push_str(fragment),
+                None => session.stream_blocks.push(StreamBlock {
+                    index,
+                    kind,
+                    content: fragment.to_string(),
+                    done: false,
+                }),
+            }
+            if !session.stream_tokens_exact {
+                // Approximate token count (rough: ~4 chars per token) until
+                // message_delta reports the real usage
+                session.stream_output_tokens += (fragment.len() as u64).div_ceil(4);
             }
         }
+        "content_block_stop" => {
+            if let Some(block) = session.stream_blocks.iter_mut().find(|b| b.index == index) {
+                block.done = true;
+            }
+        }
+        "message_delta" => {
+            if let Some(tokens) = event
+                .get("usage")
+                .and_then(|u| u.get("output_tokens"))
+                .and_then(|v| v.as_u64())
+            {
+                session.stream_output_tokens = tokens;
+                session.stream_tokens_exact = true;
+            }
+        }
+        _ => return,
     }
+    app.dirty = true;
 }
 
 fn handle_control_request(
@@ -757,26 +1058,79 @@ fn handle_control_request(
         ControlRequestPayload::CanUseTool {
             tool_name,
             input,
+            tool_use_id,
             description,
             permission_suggestions,
-            ..
+            agent_id,
         } => {
             tracing::info!("Permission request: {} - {:?}", tool_name, description);
 
             let summary = types::format_tool_summary(tool_name, input);
 
-            // Store as pending permission — user must approve via Y/N/A
-            session.pending_permission = Some(PendingPermission {
+            // Session and global rules can answer without prompting
+            if let Some(rule) = rules::evaluate(
+                &session.permission_rules,
+                &app.permission_rules,
+                tool_name,
+                input,
+                &session.cwd,
+            ) {
+                let (resp, tag) = match rule.action {
+                    RuleAction::Allow => (
+                        OutgoingControlResponse::allow(msg.request_id.clone(), input.clone()),
+                        "auto-allowed",
+                    ),
+                    RuleAction::Deny => (
+                        OutgoingControlResponse::deny(
+                            msg.request_id.clone(),
+                            &format!("Denied by rule: {}", rule),
+                        ),
+                        "auto-denied",
+                    ),
+                };
+                tracing::info!("Permission for {} decided by rule: {}", tool_name, rule);
+                session.send_to_cli(&resp.to_ndjson());
+                session.add_system_message(format!("[{}] {} {}", tag, tool_name, summary));
+                app.dirty = true;
+                return;
+            }
+
+            // Queue as pending permission — user must approve via Y/N/A.
+            // Parallel subagents can have several requests outstanding.
+            session.locate_edits(tool_use_id, tool_name, input);
+            session.pending_permissions.push_back(PendingPermission {
                 request_id: msg.request_id,
+                tool_use_id: tool_use_id.clone(),
                 tool_name: tool_name.clone(),
                 input: input.clone(),
                 description: Some(format!("{} {}", tool_name, summary)),
                 permission_suggestions: permission_suggestions.clone(),
+                agent_id: agent_id.clone(),
             });
             app.dirty = true;
         }
-        ControlRequestPayload::HookCallback { .. } => {
-            tracing::debug!("Hook callback (not implemented)");
+        ControlRequestPayload::HookCallback {
+            callback_id, input, ..
+        } => {
+            let hook = app
+                .hooks
+                .iter()
+                .find(|h| h.matches(callback_id, input))
+                .cloned();
+            tracing::info!(
+                "Hook callback {} -> {}",
+                callback_id,
+                hook.as_ref().map(|h| h.name.as_str()).unwrap_or("(no-op)")
+            );
+            if let Some(sender) = session.cli_sender.clone() {
+                hooks::runner::respond(
+                    hook,
+                    msg.request_id.clone(),
+                    input.clone(),
+                    session.cwd.clone(),
+                    sender,
+                );
+            }
         }
         ControlRequestPayload::Unknown => {
             tracing::debug!("Unknown control request subtype");
@@ -784,6 +1138,152 @@ fn handle_control_request(
     }
 }
 
+fn handle_control_response(
+    msg: types::ControlResponseMessage,
+    session_id: &str,
+    app: &mut App,
+) {
+    let resp = msg.response;
+    let pending = match app.sessions.get_mut(session_id) {
+        Some(s) => s.pending_controls.remove(&resp.request_id),
+        None => return,
+    };
+    let pending = match pending {
+        Some((p, _)) => p,
+        None => {
+            tracing::debug!("Control response for unknown request {}", resp.request_id);
+            return;
+        }
+    };
+    let ok = resp.subtype == "success";
+
+    match pending {
+        PendingControl::SetModel { model } => {
+            let session = match app.sessions.get_mut(session_id) {
+                Some(s) => s,
+                None => return,
+            };
+            if ok {
+                // Prefer the model the CLI reports, if any
+                let confirmed = resp
+                    .response
+                    .as_ref()
+                    .and_then(|r| r.get("model"))
+                    .and_then(|m| m.as_str())
+                    .map(String::from)
+                    .unwrap_or(model);
+                session.add_system_message(format!("Model changed to: {}", confirmed));
+                session.model = confirmed;
+                session.dirty_persist = true;
+            } else if unsupported_request(resp.error.as_deref()) {
+                tracing::warn!(
+                    "set_model unsupported for session {}: {:?}",
+                    session_id,
+                    resp.error
+                );
+                if session.cli_process_handle.is_none() {
+                    // Not a CLI we launched (--connect): we can't relaunch it
+                    session.add_system_message(format!(
+                        "CLI can't switch models live; restart it with --model {}",
+                        model
+                    ));
+                    return;
+                }
+                // Restart our CLI with --model
+                session.add_system_message(format!(
+                    "CLI can't switch models live, restarting with --model {}",
+                    model
+                ));
+                session.model = model;
+                session.dirty_persist = true;
+                app.restart_cli(session_id);
+            } else {
+                // e.g. an unknown model: keep the current one
+                session.add_system_message(format!(
+                    "Model not changed: {}",
+                    resp.error.as_deref().unwrap_or("rejected by CLI")
+                ));
+            }
+        }
+        PendingControl::SetPermissionMode { mode } => {
+            let session = match app.sessions.get_mut(session_id) {
+                Some(s) => s,
+                None => return,
+            };
+            if ok {
+                apply_permission_mode(session, &mode);
+            } else {
+                session.add_system_message(format!(
+                    "Permission mode not changed: {}",
+                    resp.error.as_deref().unwrap_or("rejected by CLI")
+                ));
+            }
+            // A later :mode may already be waiting on its own reply
+            if session.requested_permission_mode.as_ref().map(|(m, _)| m) == Some(&mode) {
+                session.requested_permission_mode = None;
+            }
+        }
+    }
+}
+
+/// Error the CLI answers a control request subtype it doesn't know with,
+/// followed by the subtype
+const UNSUPPORTED_SUBTYPE_ERROR: &str = "Unsupported control request subtype";
+
+/// Whether a control error says the CLI doesn't know the request subtype
+/// at all, as opposed to rejecting its arguments
+fn unsupported_request(error: Option<&str>) -> bool {
+    error.is_some_and(|e| e.starts_with(UNSUPPORTED_SUBTYPE_ERROR))
+}
+
+/// How long a control request may go unanswered
+const CONTROL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Give up on control requests and mode changes the CLI never answered,
+/// keeping the state they would have changed.
+fn expire_pending_controls(app: &mut App) {
+    for session in app.sessions.values_mut() {
+        let expired: Vec<String> = session
+            .pending_controls
+            .iter()
+            .filter(|(_, (_, sent))| sent.elapsed() > CONTROL_TIMEOUT)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in expired {
+            let pending = match session.pending_controls.remove(&id) {
+                Some((pending, _)) => pending,
+                None => continue,
+            };
+            match pending {
+                PendingControl::SetModel { model } => {
+                    session.add_system_message(format!(
+                        "CLI didn't answer set_model within {}s; model not changed to {}",
+                        CONTROL_TIMEOUT.as_secs(),
+                        model
+                    ));
+                }
+                // Reported through requested_permission_mode below
+                PendingControl::SetPermissionMode { .. } => {}
+            }
+            app.dirty = true;
+        }
+
+        let expired = session
+            .requested_permission_mode
+            .as_ref()
+            .is_some_and(|(_, sent)| sent.elapsed() > CONTROL_TIMEOUT);
+        if expired {
+            if let Some((mode, _)) = session.requested_permission_mode.take() {
+                session.add_system_message(format!(
+                    "CLI didn't confirm permission mode {}; still {}",
+                    mode, session.permission_mode
+                ));
+            }
+            app.dirty = true;
+        }
+    }
+}
+
 // ─── Task Extraction ────────────────────────────────────────────────────────
 
 fn extract_tasks_from_blocks(
@@ -945,3 +1445,318 @@ fn extract_question_from_blocks(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::Session;
+    use crate::server::ws_server::Endpoint;
+    use std::time::Instant;
+
+    fn app_with_set_model(launched: bool) -> App {
+        let mut app = App::new(Endpoint::Tcp(0), "/tmp".to_string(), None);
+        let mut session = Session::new("s1".to_string(), "test".to_string(), "/tmp".to_string());
+        session.model = "old".to_string();
+        session.cli_connected = true;
+        if launched {
+            session.cli_process_handle = Some(tokio::spawn(async {}));
+        }
+        session.pending_controls.insert(
+            "req_1".to_string(),
+            (PendingControl::SetModel { model: "new".to_string() }, Instant::now()),
+        );
+        app.sessions.insert("s1".to_string(), session);
+        app
+    }
+
+    fn reply(app: &mut App, error: &str) {
+        let msg = types::ControlResponseMessage {
+            response: types::IncomingControlResponse {
+                subtype: "error".to_string(),
+                request_id: "req_1".to_string(),
+                response: None,
+                error: Some(error.to_string()),
+            },
+        };
+        handle_control_response(msg, "s1", app);
+    }
+
+    #[test]
+    fn only_the_unknown_subtype_error_means_unsupported() {
+        assert!(unsupported_request(Some("Unsupported control request subtype: set_model")));
+        assert!(!unsupported_request(Some("Unknown model: claude-foo")));
+        assert!(!unsupported_request(Some("Invalid subtype arguments")));
+        assert!(!unsupported_request(None));
+    }
+
+    #[tokio::test]
+    async fn unsupported_set_model_restarts_a_launched_cli() {
+        let mut app = app_with_set_model(true);
+        reply(&mut app, "Unsupported control request subtype: set_model");
+        assert_eq!(app.sessions["s1"].model, "new");
+        assert_eq!(app.pending_spawns, vec!["s1"]);
+    }
+
+    #[test]
+    fn unsupported_set_model_leaves_an_external_cli_alone() {
+        let mut app = app_with_set_model(false);
+        reply(&mut app, "Unsupported control request subtype: set_model");
+        assert_eq!(app.sessions["s1"].model, "old");
+        assert!(app.sessions["s1"].cli_connected);
+        assert!(app.pending_spawns.is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejected_set_model_keeps_the_model() {
+        let mut app = app_with_set_model(true);
+        reply(&mut app, "Unknown model: new");
+        assert_eq!(app.sessions["s1"].model, "old");
+        assert!(app.pending_spawns.is_empty());
+    }
+
+    #[tokio::test]
+    async fn unanswered_set_model_keeps_the_model() {
+        let mut app = app_with_set_model(true);
+        expire_pending_controls(&mut app);
+        assert_eq!(app.sessions["s1"].pending_controls.len(), 1);
+
+        let sent = Instant::now() - CONTROL_TIMEOUT - Duration::from_secs(1);
+        for (_, at) in app.sessions.get_mut("s1").unwrap().pending_controls.values_mut() {
+            *at = sent;
+        }
+        expire_pending_controls(&mut app);
+        let session = &app.sessions["s1"];
+        assert!(session.pending_controls.is_empty());
+        assert_eq!(session.model, "old");
+        assert!(session.cli_connected);
+        assert!(app.pending_spawns.is_empty());
+    }
+
+    #[test]
+    fn plan_toggle_saves_the_mode_only_once_acknowledged() {
+        let mut session = Session::new("s1".to_string(), "test".to_string(), "/tmp".to_string());
+        session.permission_mode = "acceptEdits".to_string();
+        session.request_permission_mode("plan");
+        assert_eq!(session.previous_permission_mode, None);
+
+        apply_permission_mode(&mut session, "plan");
+        assert_eq!(session.permission_mode, "plan");
+        assert_eq!(session.previous_permission_mode.as_deref(), Some("acceptEdits"));
+        // Repeated reports of plan mode keep what it was entered from
+        apply_permission_mode(&mut session, "plan");
+        assert_eq!(session.previous_permission_mode.as_deref(), Some("acceptEdits"));
+
+        apply_permission_mode(&mut session, "acceptEdits");
+        assert_eq!(session.previous_permission_mode, None);
+    }
+
+    fn app() -> App {
+        let mut app = App::new(Endpoint::Tcp(0), "/tmp".to_string(), None);
+        let session = Session::new("s1".to_string(), "test".to_string(), "/tmp".to_string());
+        app.sessions.insert("s1".to_string(), session);
+        app
+    }
+
+    /// Feed stream_event lines as the CLI sends them
+    fn stream(app: &mut App, events: &[&str]) {
+        for event in events {
+            let line = format!(r#"{{"type": "stream_event", "event": {}}}"#, event);
+            let msg: CliMessage = serde_json::from_str(&line).unwrap();
+            handle_cli_message(msg, "s1", app);
+        }
+    }
+
+    fn blocks(app: &App) -> Vec<(u64, StreamBlockKind, String, bool)> {
+        app.sessions["s1"]
+            .stream_blocks
+            .iter()
+            .map(|b| (b.index, b.kind.clone(), b.content.clone(), b.done))
+            .collect()
+    }
+
+    #[test]
+    fn deltas_are_assembled_per_block_index() {
+        let mut app = app();
+        stream(
+            &mut app,
+            &[
+                r#"{"type": "message_start"}"#,
+                r#"{"type": "content_block_start", "index": 0, "content_block": {"type": "thinking"}}"#,
+                r#"{"type": "content_block_delta", "index": 0, "delta": {"type": "thinking_delta", "thinking": "Let me "}}"#,
+                r#"{"type": "content_block_start", "index": 1, "content_block": {"type": "tool_use", "name": "Bash"}}"#,
+                r#"{"type": "content_block_delta", "index": 0, "delta": {"type": "thinking_delta", "thinking": "look"}}"#,
+                r#"{"type": "content_block_delta", "index": 0, "delta": {"type": "signature_delta", "signature": "abc"}}"#,
+                r#"{"type": "content_block_stop", "index": 0}"#,
+                r#"{"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "{\"comm"}}"#,
+                r#"{"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "and\": \"ls\"}"}}"#,
+            ],
+        );
+        let bash = StreamBlockKind::ToolUse {
+            name: "Bash".to_string(),
+        };
+        assert_eq!(
+            blocks(&app),
+            [
+                (0, StreamBlockKind::Thinking, "Let me look".to_string(), true),
+                (1, bash, r#"{"command": "ls"}"#.to_string(), false),
+            ]
+        );
+        // Only text deltas go to the streaming text
+        assert!(app.sessions["s1"].streaming_text.is_empty());
+    }
+
+    #[test]
+    fn text_deltas_also_feed_the_streaming_text() {
+        let mut app = app();
+        stream(
+            &mut app,
+            &[
+                r#"{"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}"#,
+                r#"{"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Hel"}}"#,
+                r#"{"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "lo"}}"#,
+            ],
+        );
+        assert_eq!(app.sessions["s1"].streaming_text, "Hello");
+        assert_eq!(blocks(&app), [(0, StreamBlockKind::Text, "Hello".to_string(), false)]);
+    }
+
+    #[test]
+    fn a_delta_without_a_start_still_gets_a_block() {
+        let mut app = app();
+        stream(
+            &mut app,
+            &[r#"{"type": "content_block_delta", "index": 2, "delta": {"type": "thinking_delta", "thinking": "hm"}}"#],
+        );
+        assert_eq!(blocks(&app), [(2, StreamBlockKind::Thinking, "hm".to_string(), false)]);
+    }
+
+    #[test]
+    fn unknown_blocks_and_deltas_are_ignored() {
+        let mut app = app();
+        stream(
+            &mut app,
+            &[
+                r#"{"type": "content_block_start", "index": 0, "content_block": {"type": "image"}}"#,
+                r#"{"type": "content_block_delta", "index": 0, "delta": {"type": "citations_delta"}}"#,
+                r#"{"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta"}}"#,
+                r#"{"type": "content_block_delta", "index": 0}"#,
+            ],
+        );
+        assert!(blocks(&app).is_empty());
+    }
+
+    #[test]
+    fn a_restarted_index_replaces_its_block_and_message_start_clears_all() {
+        let mut app = app();
+        stream(
+            &mut app,
+            &[
+                r#"{"type": "content_block_start", "index": 0, "content_block": {"type": "thinking"}}"#,
+                r#"{"type": "content_block_delta", "index": 0, "delta": {"type": "thinking_delta", "thinking": "old"}}"#,
+                r#"{"type": "content_block_start", "index": 0, "content_block": {"type": "text"}}"#,
+            ],
+        );
+        assert_eq!(blocks(&app), [(0, StreamBlockKind::Text, String::new(), false)]);
+
+        stream(&mut app, &[r#"{"type": "message_start"}"#]);
+        assert!(blocks(&app).is_empty());
+    }
+
+    #[test]
+    fn usage_from_message_delta_replaces_the_estimate() {
+        let mut app = app();
+        stream(
+            &mut app,
+            &[
+                r#"{"type": "message_start"}"#,
+                r#"{"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "12345678"}}"#,
+            ],
+        );
+        assert_eq!(app.sessions["s1"].stream_output_tokens, 2);
+
+        stream(
+            &mut app,
+            &[
+                r#"{"type": "message_delta", "usage": {"output_tokens": 40}}"#,
+                r#"{"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "more text"}}"#,
+            ],
+        );
+        assert_eq!(app.sessions["s1"].stream_output_tokens, 40);
+    }
+
+    /// Feed an assistant message with one text block
+    fn assistant(app: &mut App, id: &str, parent: Option<&str>, text: &str) {
+        let line = serde_json::json!({
+            "type": "assistant",
+            "message": {"id": id, "content": [{"type": "text", "text": text}]},
+            "parent_tool_use_id": parent,
+        });
+        handle_cli_message(serde_json::from_value(line).unwrap(), "s1", app);
+    }
+
+    fn assistant_texts(app: &App) -> Vec<(Option<String>, String)> {
+        app.sessions["s1"]
+            .messages
+            .iter()
+            .filter(|m| matches!(m.role, ChatRole::Assistant))
+            .map(|m| (m.parent_tool_use_id.clone(), m.content.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn repeated_subagent_message_keeps_the_main_stream() {
+        let mut app = app();
+        stream(
+            &mut app,
+            &[
+                r#"{"type": "message_start"}"#,
+                r#"{"type": "content_block_start", "index": 0, "content_block": {"type": "text"}}"#,
+                r#"{"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Work"}}"#,
+            ],
+        );
+        assistant(&mut app, "msg_sub", Some("toolu_task"), "found it");
+        assistant(&mut app, "msg_sub", Some("toolu_task"), "found it");
+
+        assert_eq!(
+            assistant_texts(&app),
+            [(Some("toolu_task".to_string()), "found it".to_string())]
+        );
+        let session = &app.sessions["s1"];
+        assert_eq!(session.streaming_text, "Work");
+        assert_eq!(session.stream_blocks.len(), 1);
+    }
+
+    #[test]
+    fn same_message_id_under_different_parents_is_kept() {
+        let mut app = app();
+        assistant(&mut app, "msg_1", None, "main");
+        assistant(&mut app, "msg_1", Some("toolu_a"), "sub a");
+        assistant(&mut app, "msg_1", Some("toolu_b"), "sub b");
+        // The main agent's repeat is still dropped after subagent messages
+        assistant(&mut app, "msg_1", None, "main");
+
+        assert_eq!(
+            assistant_texts(&app),
+            [
+                (None, "main".to_string()),
+                (Some("toolu_a".to_string()), "sub a".to_string()),
+                (Some("toolu_b".to_string()), "sub b".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn thread_progress_is_keyed_by_parent() {
+        let mut app = app();
+        for line in [
+            r#"{"type": "tool_progress", "tool_use_id": "toolu_1", "tool_name": "Grep", "parent_tool_use_id": "toolu_task", "elapsed_time_seconds": 2.5}"#,
+            r#"{"type": "tool_progress", "tool_use_id": "toolu_2", "tool_name": "Bash", "elapsed_time_seconds": 1.0}"#,
+        ] {
+            handle_cli_message(serde_json::from_str(line).unwrap(), "s1", &mut app);
+        }
+        let session = &app.sessions["s1"];
+        assert_eq!(session.thread_progress.len(), 1);
+        assert_eq!(session.thread_progress["toolu_task"], ("Grep".to_string(), 2.5));
+        assert_eq!(session.current_tool, Some(("Bash".to_string(), 1.0)));
+    }
+}
@@ -0,0 +1,410 @@
+//! Double-buffered cell grid. Each frame is drawn into a `Frame` with the
+//! same commands as a terminal (`draw!` mirrors crossterm's `queue!`), then
+//! `present` compares it with the frame on screen and writes only the cells
+//! that changed, in a single write.
+
+use std::fmt::Display;
+use std::io::Write;
+
+use crossterm::{
+    cursor, queue,
+    style::{
+        Attribute, Attributes, Color, Print, ResetColor, SetAttribute, SetAttributes,
+        SetBackgroundColor, SetForegroundColor,
+    },
+    terminal::{Clear, ClearType},
+};
+use unicode_width::UnicodeWidthChar;
+
+use crate::ui::highlight::ColorDepth;
+use crate::ui::theme;
+
+/// `queue!` for a `Frame`: apply each command in order
+macro_rules! draw {
+    ($frame:expr $(, $command:expr)* $(,)?) => {{
+        $( $crate::ui::frame::Draw::draw($command, &mut *$frame); )*
+    }};
+}
+pub(crate) use draw;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Style {
+    fg: Option<Color>,
+    bg: Option<Color>,
+    attrs: Attributes,
+    /// Target of an OSC 8 hyperlink
+    link: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Cell {
+    /// One character plus any combining marks; empty for the right half
+    /// of a wide character
+    symbol: String,
+    style: Style,
+}
+
+impl Cell {
+    fn blank() -> Self {
+        Self {
+            symbol: " ".to_string(),
+            style: Style::default(),
+        }
+    }
+}
+
+/// A screen's worth of cells plus the pen and cursor drawing into it
+pub struct Frame {
+    width: u16,
+    height: u16,
+    cells: Vec<Cell>,
+    pen: Style,
+    x: u16,
+    y: u16,
+    /// Where the cursor is shown after drawing; None hides it
+    cursor: Option<(u16, u16)>,
+    /// Colors are fitted to these as they are set
+    colors: ColorDepth,
+}
+
+impl Frame {
+    pub fn new(width: u16, height: u16, colors: ColorDepth) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![Cell::blank(); width as usize * height as usize],
+            pen: Style::default(),
+            x: 0,
+            y: 0,
+            cursor: None,
+            colors,
+        }
+    }
+
+    pub fn size(&self) -> (u16, u16) {
+        (self.width, self.height)
+    }
+
+    fn index(&self, x: u16, y: u16) -> Option<usize> {
+        (x < self.width && y < self.height).then(|| y as usize * self.width as usize + x as usize)
+    }
+
+    /// Text at the cursor with the current pen. Lines are clipped at the
+    /// right edge rather than wrapped.
+    fn print(&mut self, text: &str) {
+        // Whether the last character was cut off at the right edge
+        let mut clipped = false;
+        for ch in text.chars() {
+            if ch.is_control() {
+                continue;
+            }
+            let w = ch.width().unwrap_or(0);
+            if w == 0 {
+                // Combining mark: joins the character before it (the left
+                // half when that one is wide)
+                if !clipped {
+                    let mut i = self.x.checked_sub(1).and_then(|x| self.index(x, self.y));
+                    if let Some(j) = i.filter(|&j| self.cells[j].symbol.is_empty()) {
+                        i = j.checked_sub(1);
+                    }
+                    if let Some(i) = i {
+                        self.cells[i].symbol.push(ch);
+                    }
+                }
+                continue;
+            }
+            if self.x as usize + w > self.width as usize {
+                self.x = self.width;
+                clipped = true;
+                continue;
+            }
+            self.put(ch, w);
+            self.x += w as u16;
+            clipped = false;
+        }
+    }
+
+    fn put(&mut self, ch: char, w: usize) {
+        let Some(i) = self.index(self.x, self.y) else {
+            return;
+        };
+        let row_end = (self.y as usize + 1) * self.width as usize;
+        // Overwriting half of a wide character blanks its other half
+        if self.cells[i].symbol.is_empty() && self.x > 0 {
+            self.cells[i - 1].symbol = " ".to_string();
+        }
+        let after = i + w;
+        if after < row_end && self.cells[after].symbol.is_empty() {
+            self.cells[after].symbol = " ".to_string();
+        }
+        self.cells[i] = Cell {
+            symbol: ch.to_string(),
+            style: self.pen.clone(),
+        };
+        if w == 2 {
+            self.cells[i + 1] = Cell {
+                symbol: String::new(),
+                style: self.pen.clone(),
+            };
+        }
+    }
+
+    /// Write what differs from `shown` (everything when None or a different
+    /// size) to `out`; nothing at all when the frames are the same
+    pub fn present(&self, shown: Option<&Frame>, out: &mut impl Write) -> std::io::Result<()> {
+        let shown = shown.filter(|s| s.width == self.width && s.height == self.height);
+        let mut body = Vec::new();
+        // Pen state of the terminal while drawing; None until first set
+        let mut current: Option<&Style> = None;
+        let blank = Cell::blank();
+        if shown.is_none() {
+            queue!(body, SetAttribute(Attribute::Reset), Clear(ClearType::All))?;
+        }
+        let mut at = None;
+        for (i, cell) in self.cells.iter().enumerate() {
+            let unchanged = match shown {
+                Some(shown) => shown.cells[i] == *cell,
+                None => *cell == blank,
+            };
+            // Right halves of wide characters are drawn with their left half
+            if unchanged || cell.symbol.is_empty() {
+                continue;
+            }
+            let x = (i % self.width as usize) as u16;
+            let y = (i / self.width as usize) as u16;
+            if at != Some((x, y)) {
+                queue!(body, cursor::MoveTo(x, y))?;
+            }
+            if current.map(|c| (c.fg, c.bg, c.attrs)) != Some((cell.style.fg, cell.style.bg, cell.style.attrs)) {
+                queue!(body, ResetColor)?;
+                if let Some(fg) = cell.style.fg {
+                    queue!(body, SetForegroundColor(fg))?;
+                }
+                if let Some(bg) = cell.style.bg {
+                    queue!(body, SetBackgroundColor(bg))?;
+                }
+                queue!(body, SetAttributes(cell.style.attrs))?;
+            }
+            if current.map(|c| &c.link) != Some(&cell.style.link) {
+                let url = cell.style.link.as_deref().unwrap_or("");
+                queue!(body, Print(format!("\x1b]8;;{}\x1b\\", url)))?;
+            }
+            current = Some(&cell.style);
+            let w = cell.symbol.chars().next().and_then(|c| c.width()).unwrap_or(1);
+            queue!(body, Print(&cell.symbol))?;
+            at = Some((x + w as u16, y));
+        }
+        if let Some(style) = current {
+            if style.link.is_some() {
+                queue!(body, Print("\x1b]8;;\x1b\\"))?;
+            }
+            queue!(body, ResetColor)?;
+        }
+
+        let cursor_changed = shown.is_none_or(|s| s.cursor != self.cursor);
+        if body.is_empty() && !cursor_changed {
+            return Ok(());
+        }
+        let mut buf = Vec::new();
+        if !body.is_empty() {
+            // Keep the cursor out of sight while cells change under it
+            queue!(buf, cursor::Hide)?;
+            buf.extend(body);
+        }
+        match self.cursor {
+            Some((x, y)) => queue!(buf, cursor::MoveTo(x, y), cursor::Show)?,
+            None if buf.is_empty() => queue!(buf, cursor::Hide)?,
+            None => {}
+        }
+        out.write_all(&buf)?;
+        out.flush()
+    }
+}
+
+// ─── Commands ───────────────────────────────────────────────────────────────
+
+/// A drawing command applied to a `Frame`, as crossterm would to a terminal
+pub trait Draw {
+    fn draw(self, frame: &mut Frame);
+}
+
+/// Start (Some) or end (None) an OSC 8 hyperlink around the text printed
+/// in between
+pub struct SetLink(pub Option<String>);
+
+impl Draw for SetLink {
+    fn draw(self, frame: &mut Frame) {
+        frame.pen.link = self.0;
+    }
+}
+
+impl Draw for cursor::MoveTo {
+    fn draw(self, frame: &mut Frame) {
+        frame.x = self.0;
+        frame.y = self.1;
+    }
+}
+
+impl Draw for cursor::Show {
+    fn draw(self, frame: &mut Frame) {
+        frame.cursor = Some((frame.x, frame.y));
+    }
+}
+
+impl Draw for cursor::Hide {
+    fn draw(self, frame: &mut Frame) {
+        frame.cursor = None;
+    }
+}
+
+impl<T: Display> Draw for Print<T> {
+    fn draw(self, frame: &mut Frame) {
+        frame.print(&self.0.to_string());
+    }
+}
+
+impl Draw for SetForegroundColor {
+    fn draw(self, frame: &mut Frame) {
+        frame.pen.fg = Some(theme::fit(self.0, frame.colors)).filter(|c| *c != Color::Reset);
+    }
+}
+
+impl Draw for SetBackgroundColor {
+    fn draw(self, frame: &mut Frame) {
+        frame.pen.bg = Some(theme::fit(self.0, frame.colors)).filter(|c| *c != Color::Reset);
+    }
+}
+
+/// A theme style sets the colors it has and turns on bold when it's bold
+impl Draw for theme::Style {
+    fn draw(self, frame: &mut Frame) {
+        if let Some(fg) = self.fg {
+            SetForegroundColor(fg).draw(frame);
+        }
+        if let Some(bg) = self.bg {
+            SetBackgroundColor(bg).draw(frame);
+        }
+        if self.bold {
+            frame.pen.attrs.set(Attribute::Bold);
+        }
+    }
+}
+
+/// Like the `SGR 0` it sends to a terminal, this also clears attributes
+impl Draw for ResetColor {
+    fn draw(self, frame: &mut Frame) {
+        frame.pen.fg = None;
+        frame.pen.bg = None;
+        frame.pen.attrs = Attributes::default();
+    }
+}
+
+impl Draw for SetAttribute {
+    fn draw(self, frame: &mut Frame) {
+        match self.0 {
+            Attribute::Reset => ResetColor.draw(frame),
+            attr => frame.pen.attrs.set(attr),
+        }
+    }
+}
+
+impl Draw for Clear {
+    fn draw(self, frame: &mut Frame) {
+        if let ClearType::All | ClearType::Purge = self.0 {
+            frame.cells.fill(Cell::blank());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(width: u16) -> Frame {
+        Frame::new(width, 1, ColorDepth::TrueColor)
+    }
+
+    fn symbols(frame: &Frame) -> Vec<&str> {
+        frame.cells.iter().map(|c| c.symbol.as_str()).collect()
+    }
+
+    fn present(frame: &Frame, shown: Option<&Frame>) -> String {
+        let mut out = Vec::new();
+        frame.present(shown, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn wide_characters_take_two_cells() {
+        let mut f = frame(5);
+        draw!(&mut f, Print("a\u{4e2d}b"));
+        assert_eq!(symbols(&f), ["a", "\u{4e2d}", "", "b", " "]);
+    }
+
+    #[test]
+    fn wide_character_past_the_edge_is_clipped() {
+        let mut f = frame(3);
+        draw!(&mut f, Print("ab\u{4e2d}"));
+        assert_eq!(symbols(&f), ["a", "b", " "]);
+    }
+
+    #[test]
+    fn overwriting_half_of_a_wide_character_blanks_the_other_half() {
+        let mut f = frame(4);
+        draw!(&mut f, Print("\u{4e2d}\u{6587}"), cursor::MoveTo(1, 0), Print("x"));
+        assert_eq!(symbols(&f), [" ", "x", "\u{6587}", ""]);
+
+        let mut f = frame(4);
+        draw!(&mut f, Print("\u{4e2d}\u{6587}"), cursor::MoveTo(0, 0), Print("x"));
+        assert_eq!(symbols(&f), ["x", " ", "\u{6587}", ""]);
+
+        // A wide character straddling two others blanks both outer halves
+        let mut f = frame(4);
+        draw!(&mut f, Print("\u{4e2d}\u{6587}"), cursor::MoveTo(1, 0), Print("\u{5b57}"));
+        assert_eq!(symbols(&f), [" ", "\u{5b57}", "", " "]);
+    }
+
+    #[test]
+    fn combining_marks_join_the_character_before() {
+        let mut f = frame(4);
+        draw!(&mut f, Print("e\u{301}x"));
+        assert_eq!(symbols(&f), ["e\u{301}", "x", " ", " "]);
+
+        let mut f = frame(4);
+        draw!(&mut f, Print("\u{4e2d}\u{301}x"));
+        assert_eq!(symbols(&f), ["\u{4e2d}\u{301}", "", "x", " "]);
+
+        // Nothing to join at the left edge or after a clipped character
+        let mut f = frame(2);
+        draw!(&mut f, Print("\u{301}a\u{4e2d}\u{301}"));
+        assert_eq!(symbols(&f), ["a", " "]);
+    }
+
+    #[test]
+    fn present_writes_only_changed_cells() {
+        let mut shown = frame(6);
+        draw!(&mut shown, Print("ab\u{4e2d}cd"));
+        let first = present(&shown, None);
+        assert!(first.contains("ab\u{4e2d}cd"));
+
+        assert_eq!(present(&shown, Some(&shown)), "");
+
+        let mut next = frame(6);
+        draw!(&mut next, Print("ab\u{4e2d}cX"));
+        let out = present(&next, Some(&shown));
+        assert!(out.contains('X'));
+        assert!(!out.contains('a') && !out.contains('\u{4e2d}'));
+    }
+
+    #[test]
+    fn present_clears_both_halves_of_a_replaced_wide_character() {
+        let mut shown = frame(4);
+        draw!(&mut shown, Print("\u{4e2d}ab"));
+        let mut next = frame(4);
+        draw!(&mut next, Print("x"));
+        // `x` plus the blanked right half; the right half itself isn't printed
+        let out = present(&next, Some(&shown));
+        assert!(out.contains("x "));
+        assert!(!out.contains('\u{4e2d}'));
+    }
+}
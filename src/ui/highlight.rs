@@ -0,0 +1,630 @@
+//! Syntax highlighting for fenced code blocks and tool results.
+//!
+//! A small per-line lexer, not a parser: keywords, types and constants,
+//! strings, numbers, comments, function calls and a few language-specific
+//! bits (Rust macros and attributes, Python decorators, shell variables,
+//! JSON/TOML keys). Comments and strings that span lines carry over through
+//! `HighlightState`.
+
+use crossterm::style::Color;
+use serde::{Deserialize, Serialize};
+
+use crate::ui::theme::Theme;
+
+// ─── Color Depth ────────────────────────────────────────────────────────────
+
+/// Colors the terminal can show. The daemon renders for the attached
+/// client's terminal, so the client reports its own.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorDepth {
+    Ansi16,
+    Ansi256,
+    TrueColor,
+}
+
+impl ColorDepth {
+    /// From `COLORTERM` and `TERM`
+    pub fn detect() -> Self {
+        let var = |name| std::env::var(name).unwrap_or_default();
+        ColorDepth::from_env(&var("COLORTERM"), &var("TERM"))
+    }
+
+    fn from_env(colorterm: &str, term: &str) -> Self {
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            return ColorDepth::TrueColor;
+        }
+        if term.contains("256color") {
+            ColorDepth::Ansi256
+        } else {
+            ColorDepth::Ansi16
+        }
+    }
+}
+
+// ─── Tokens ─────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Token {
+    Plain,
+    Keyword,
+    /// Types, builtin constants, lifetimes
+    Type,
+    String,
+    Number,
+    Comment,
+    Function,
+    /// Macros, attributes, decorators, TOML tables
+    Meta,
+    /// JSON and TOML keys
+    Key,
+    /// Shell variables
+    Variable,
+    Added,
+    Removed,
+    Hunk,
+}
+
+/// Foreground for a token; None keeps the terminal's default
+pub fn token_color(token: Token, theme: &Theme) -> Option<Color> {
+    let style = match token {
+        Token::Plain => theme.code_plain,
+        Token::Keyword => theme.code_keyword,
+        Token::Type => theme.code_type,
+        Token::String | Token::Added => theme.code_string,
+        Token::Number => theme.code_number,
+        Token::Comment => theme.code_comment,
+        Token::Function => theme.code_function,
+        Token::Meta | Token::Hunk => theme.code_meta,
+        Token::Key | Token::Variable | Token::Removed => theme.code_key,
+    };
+    style.fg
+}
+
+/// Background of code blocks; none on 16-color terminals
+pub fn code_background(theme: &Theme, depth: ColorDepth) -> Option<Color> {
+    theme.code.bg.filter(|_| depth != ColorDepth::Ansi16)
+}
+
+// ─── Languages ──────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lang {
+    Rust,
+    Python,
+    /// JavaScript and TypeScript
+    Script,
+    Shell,
+    Json,
+    Toml,
+    Diff,
+}
+
+impl Lang {
+    /// From a code fence info string (```rust)
+    pub fn from_tag(tag: &str) -> Option<Lang> {
+        let tag = tag.split([' ', ',', '{']).next().unwrap_or("");
+        match tag.to_ascii_lowercase().as_str() {
+            "rust" | "rs" => Some(Lang::Rust),
+            "python" | "py" | "python3" => Some(Lang::Python),
+            "javascript" | "js" | "jsx" | "mjs" | "cjs" | "typescript" | "ts" | "tsx" => {
+                Some(Lang::Script)
+            }
+            "sh" | "bash" | "shell" | "zsh" | "console" | "shellsession" => Some(Lang::Shell),
+            "json" | "jsonc" | "json5" => Some(Lang::Json),
+            "toml" => Some(Lang::Toml),
+            "diff" | "patch" => Some(Lang::Diff),
+            _ => None,
+        }
+    }
+
+    /// From a file name's extension
+    pub fn from_path(path: &str) -> Option<Lang> {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        if name == "Cargo.lock" {
+            return Some(Lang::Toml);
+        }
+        let ext = name.rsplit_once('.').map(|(_, ext)| ext)?;
+        Lang::from_tag(ext)
+    }
+
+    fn syntax(self) -> &'static Syntax {
+        match self {
+            Lang::Rust => &RUST,
+            Lang::Python => &PYTHON,
+            Lang::Script => &SCRIPT,
+            Lang::Shell => &SHELL,
+            Lang::Json => &JSON,
+            // Diff lines are classified whole, never lexed
+            Lang::Toml | Lang::Diff => &TOML,
+        }
+    }
+}
+
+struct Syntax {
+    keywords: &'static [&'static str],
+    /// Builtin types and constants
+    types: &'static [&'static str],
+    line_comment: Option<&'static str>,
+    block_comment: Option<(&'static str, &'static str)>,
+    /// String delimiters, longest first, and whether they may span lines
+    strings: &'static [(&'static str, bool)],
+}
+
+const RUST: Syntax = Syntax {
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+        "extern", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+        "pub", "ref", "return", "self", "static", "struct", "super", "trait", "type", "unsafe",
+        "use", "where", "while",
+    ],
+    types: &[
+        "bool", "char", "str", "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32",
+        "i64", "i128", "isize", "f32", "f64", "Self", "true", "false", "Some", "None", "Ok", "Err",
+    ],
+    line_comment: Some("//"),
+    block_comment: Some(("/*", "*/")),
+    strings: &[("\"", true)],
+};
+
+const PYTHON: Syntax = Syntax {
+    keywords: &[
+        "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del",
+        "elif", "else", "except", "finally", "for", "from", "global", "if", "import", "in", "is",
+        "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while", "with",
+        "yield", "match", "case",
+    ],
+    types: &[
+        "True", "False", "None", "self", "cls", "int", "str", "float", "bool", "list", "dict",
+        "set", "tuple", "bytes", "object",
+    ],
+    line_comment: Some("#"),
+    block_comment: None,
+    strings: &[("\"\"\"", true), ("'''", true), ("\"", false), ("'", false)],
+};
+
+const SCRIPT: Syntax = Syntax {
+    keywords: &[
+        "async", "await", "break", "case", "catch", "class", "const", "continue", "default",
+        "delete", "do", "else", "enum", "export", "extends", "finally", "for", "from", "function",
+        "if", "implements", "import", "in", "instanceof", "interface", "let", "new", "of",
+        "private", "protected", "public", "readonly", "return", "static", "switch", "throw",
+        "try", "type", "typeof", "var", "void", "while", "yield", "as",
+    ],
+    types: &[
+        "true", "false", "null", "undefined", "this", "super", "string", "number", "boolean",
+        "any", "unknown", "never", "object", "Promise",
+    ],
+    line_comment: Some("//"),
+    block_comment: Some(("/*", "*/")),
+    strings: &[("`", true), ("\"", false), ("'", false)],
+};
+
+const SHELL: Syntax = Syntax {
+    keywords: &[
+        "if", "then", "else", "elif", "fi", "for", "while", "until", "do", "done", "case", "esac",
+        "in", "function", "return", "export", "local", "readonly", "set", "unset", "source",
+        "exit",
+    ],
+    types: &["true", "false"],
+    line_comment: Some("#"),
+    block_comment: None,
+    strings: &[("\"", false), ("'", false)],
+};
+
+const JSON: Syntax = Syntax {
+    keywords: &[],
+    types: &["true", "false", "null"],
+    line_comment: Some("//"),
+    block_comment: None,
+    strings: &[("\"", false)],
+};
+
+const TOML: Syntax = Syntax {
+    keywords: &[],
+    types: &["true", "false"],
+    line_comment: Some("#"),
+    block_comment: None,
+    strings: &[("\"\"\"", true), ("'''", true), ("\"", false), ("'", false)],
+};
+
+// ─── Lexer ──────────────────────────────────────────────────────────────────
+
+/// What a code block carries from one line to the next
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HighlightState {
+    /// Closing delimiter of a comment or string still open
+    open: Option<&'static str>,
+}
+
+/// Tokens of one line, adjacent tokens of the same kind merged
+pub fn highlight_line(lang: Lang, line: &str, state: &mut HighlightState) -> Vec<(Token, String)> {
+    let mut out = Tokens::default();
+    if lang == Lang::Diff {
+        let token = if line.starts_with("@@") {
+            Token::Hunk
+        } else if line.starts_with("+++") || line.starts_with("---") {
+            Token::Meta
+        } else if line.starts_with('+') {
+            Token::Added
+        } else if line.starts_with('-') {
+            Token::Removed
+        } else {
+            Token::Plain
+        };
+        out.push(token, line);
+        return out.0;
+    }
+
+    let syntax = lang.syntax();
+    let chars: Vec<char> = line.chars().collect();
+    let text = |from: usize, to: usize| chars[from..to].iter().collect::<String>();
+    let mut i = 0;
+
+    // A comment or string left open on an earlier line
+    if let Some(close) = state.open {
+        let token = if Some(close) == syntax.block_comment.map(|b| b.1) {
+            Token::Comment
+        } else {
+            Token::String
+        };
+        match find_close(&chars, 0, close) {
+            Some(end) => {
+                out.push(token, &text(0, end));
+                state.open = None;
+                i = end;
+            }
+            None => {
+                out.push(token, line);
+                return out.0;
+            }
+        }
+    }
+
+    if lang == Lang::Toml && line.trim_start().starts_with('[') {
+        out.push(Token::Meta, line);
+        return out.0;
+    }
+
+    'outer: while i < chars.len() {
+        let c = chars[i];
+
+        if let Some(prefix) = syntax.line_comment {
+            // `#` only starts a shell comment at a word boundary ($#, a#b)
+            let boundary = lang != Lang::Shell || i == 0 || chars[i - 1].is_whitespace();
+            if boundary && starts_at(&chars, i, prefix) {
+                out.push(Token::Comment, &text(i, chars.len()));
+                break;
+            }
+        }
+
+        if let Some((open, close)) = syntax.block_comment {
+            if starts_at(&chars, i, open) {
+                match find_close(&chars, i + open.len(), close) {
+                    Some(end) => {
+                        out.push(Token::Comment, &text(i, end));
+                        i = end;
+                    }
+                    None => {
+                        out.push(Token::Comment, &text(i, chars.len()));
+                        state.open = Some(close);
+                        break;
+                    }
+                }
+                continue;
+            }
+        }
+
+        for &(delim, multiline) in syntax.strings {
+            if starts_at(&chars, i, delim) {
+                let end = find_close(&chars, i + delim.chars().count(), delim);
+                // JSON strings followed by `:` are keys
+                let token = match end {
+                    Some(end) if lang == Lang::Json && next_non_space(&chars, end) == Some(':') => {
+                        Token::Key
+                    }
+                    _ => Token::String,
+                };
+                match end {
+                    Some(end) => {
+                        out.push(token, &text(i, end));
+                        i = end;
+                    }
+                    None => {
+                        out.push(token, &text(i, chars.len()));
+                        if multiline {
+                            state.open = Some(delim);
+                        }
+                        i = chars.len();
+                    }
+                }
+                continue 'outer;
+            }
+        }
+
+        // Rust char literals vs lifetimes
+        if lang == Lang::Rust && c == '\'' {
+            let literal_end = if chars.get(i + 1) == Some(&'\\') {
+                chars[i + 2..].iter().position(|&ch| ch == '\'').map(|p| i + 3 + p)
+            } else if chars.get(i + 2) == Some(&'\'') {
+                Some(i + 3)
+            } else {
+                None
+            };
+            let (token, end) = match literal_end {
+                Some(end) => (Token::String, end),
+                None => (Token::Type, scan(&chars, i + 1, is_ident_char)),
+            };
+            out.push(token, &text(i, end));
+            i = end;
+            continue;
+        }
+
+        // Attributes, decorators, shell variables
+        if lang == Lang::Rust && c == '#' && matches!(chars.get(i + 1), Some('[') | Some('!')) {
+            let end = chars[i..].iter().position(|&ch| ch == ']').map(|p| i + p + 1);
+            let end = end.unwrap_or(chars.len());
+            out.push(Token::Meta, &text(i, end));
+            i = end;
+            continue;
+        }
+        if lang == Lang::Python && c == '@' && (i == 0 || chars[..i].iter().all(|ch| ch.is_whitespace())) {
+            let end = scan(&chars, i + 1, |ch| is_ident_char(ch) || ch == '.');
+            out.push(Token::Meta, &text(i, end));
+            i = end;
+            continue;
+        }
+        if lang == Lang::Shell && c == '$' {
+            let end = match chars.get(i + 1) {
+                Some('{') => chars[i..].iter().position(|&ch| ch == '}').map(|p| i + p + 1),
+                Some(ch) if is_ident_char(*ch) => Some(scan(&chars, i + 1, is_ident_char)),
+                Some(ch) if "?#@*!$0123456789".contains(*ch) => Some(i + 2),
+                _ => None,
+            };
+            if let Some(end) = end {
+                out.push(Token::Variable, &text(i, end));
+                i = end;
+                continue;
+            }
+        }
+
+        if c.is_ascii_digit() && (i == 0 || !is_ident_char(chars[i - 1])) {
+            let end = scan(&chars, i, |ch| ch.is_ascii_alphanumeric() || ch == '.' || ch == '_');
+            out.push(Token::Number, &text(i, end));
+            i = end;
+            continue;
+        }
+
+        if c.is_alphabetic() || c == '_' {
+            // Shell words and TOML keys include dashes (`--foo`, `dev-dependencies`)
+            let end = if matches!(lang, Lang::Shell | Lang::Toml) {
+                scan(&chars, i, |ch| is_ident_char(ch) || ch == '-')
+            } else {
+                scan(&chars, i, is_ident_char)
+            };
+            let word = text(i, end);
+            let next = next_non_space(&chars, end);
+            let token = if lang == Lang::Toml
+                && chars[..i].iter().all(|ch| ch.is_whitespace())
+                && matches!(next, Some('=') | Some('.'))
+            {
+                Token::Key
+            } else if syntax.keywords.contains(&word.as_str()) {
+                Token::Keyword
+            } else if syntax.types.contains(&word.as_str()) {
+                Token::Type
+            } else if lang == Lang::Rust && chars.get(end) == Some(&'!') {
+                out.push(Token::Meta, &format!("{}!", word));
+                i = end + 1;
+                continue;
+            } else if next == Some('(') && lang != Lang::Shell && lang != Lang::Toml {
+                Token::Function
+            } else if matches!(lang, Lang::Rust | Lang::Script) && c.is_uppercase() {
+                Token::Type
+            } else {
+                Token::Plain
+            };
+            out.push(token, &word);
+            i = end;
+            continue;
+        }
+
+        out.push(Token::Plain, &c.to_string());
+        i += 1;
+    }
+    out.0
+}
+
+#[derive(Default)]
+struct Tokens(Vec<(Token, String)>);
+
+impl Tokens {
+    fn push(&mut self, token: Token, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        match self.0.last_mut() {
+            Some((last, buf)) if *last == token => buf.push_str(text),
+            _ => self.0.push((token, text.to_string())),
+        }
+    }
+}
+
+fn starts_at(chars: &[char], i: usize, pat: &str) -> bool {
+    (i..).zip(pat.chars()).all(|(at, p)| chars.get(at) == Some(&p))
+}
+
+/// Index just past `close`, skipping backslash escapes
+fn find_close(chars: &[char], from: usize, close: &str) -> Option<usize> {
+    let mut i = from;
+    while i < chars.len() {
+        if chars[i] == '\\' {
+            i += 2;
+            continue;
+        }
+        if starts_at(chars, i, close) {
+            return Some(i + close.chars().count());
+        }
+        i += 1;
+    }
+    None
+}
+
+fn scan(chars: &[char], from: usize, pred: impl Fn(char) -> bool) -> usize {
+    chars[from..]
+        .iter()
+        .position(|&ch| !pred(ch))
+        .map(|p| from + p)
+        .unwrap_or(chars.len())
+}
+
+fn next_non_space(chars: &[char], from: usize) -> Option<char> {
+    chars[from.min(chars.len())..]
+        .iter()
+        .copied()
+        .find(|ch| !ch.is_whitespace())
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tokens of each line, lexed in order as one block
+    fn lex(lang: Lang, lines: &[&str]) -> Vec<Vec<(Token, String)>> {
+        let mut state = HighlightState::default();
+        lines.iter().map(|line| highlight_line(lang, line, &mut state)).collect()
+    }
+
+    fn tok(token: Token, text: &str) -> (Token, String) {
+        (token, text.to_string())
+    }
+
+    #[test]
+    fn color_depth_from_env() {
+        assert_eq!(ColorDepth::from_env("truecolor", "xterm"), ColorDepth::TrueColor);
+        assert_eq!(ColorDepth::from_env("24bit", ""), ColorDepth::TrueColor);
+        assert_eq!(ColorDepth::from_env("", "xterm-256color"), ColorDepth::Ansi256);
+        assert_eq!(ColorDepth::from_env("yes", "screen-256color"), ColorDepth::Ansi256);
+        assert_eq!(ColorDepth::from_env("", "xterm"), ColorDepth::Ansi16);
+        assert_eq!(ColorDepth::from_env("", ""), ColorDepth::Ansi16);
+    }
+
+    #[test]
+    fn languages_from_tags_and_paths() {
+        assert_eq!(Lang::from_tag("rust,ignore"), Some(Lang::Rust));
+        assert_eq!(Lang::from_tag("TSX"), Some(Lang::Script));
+        assert_eq!(Lang::from_tag("console"), Some(Lang::Shell));
+        assert_eq!(Lang::from_tag("text"), None);
+        assert_eq!(Lang::from_path("src/main.rs"), Some(Lang::Rust));
+        assert_eq!(Lang::from_path("/repo/Cargo.lock"), Some(Lang::Toml));
+        assert_eq!(Lang::from_path("Makefile"), None);
+    }
+
+    #[test]
+    fn rust_block_comments_and_strings_span_lines() {
+        let lines = lex(
+            Lang::Rust,
+            &["let x = 1; /* start", "still comment", "end */ let s = \"a", "b\";"],
+        );
+        assert_eq!(lines[0].last(), Some(&tok(Token::Comment, "/* start")));
+        assert_eq!(lines[1], [tok(Token::Comment, "still comment")]);
+        assert_eq!(lines[2][0], tok(Token::Comment, "end */"));
+        assert_eq!(lines[2].last(), Some(&tok(Token::String, "\"a")));
+        assert_eq!(lines[3][0], tok(Token::String, "b\""));
+        assert_eq!(lines[3][1], tok(Token::Plain, ";"));
+    }
+
+    #[test]
+    fn rust_lifetimes_chars_macros_and_attributes() {
+        let lines = lex(Lang::Rust, &["#[derive(Debug)]", "fn f<'a>(c: &'a str) { g(); println!('x') }"]);
+        assert_eq!(lines[0], [tok(Token::Meta, "#[derive(Debug)]")]);
+        assert!(lines[1].contains(&tok(Token::Type, "'a")));
+        assert!(lines[1].contains(&tok(Token::String, "'x'")));
+        assert!(lines[1].contains(&tok(Token::Meta, "println!")));
+        assert!(lines[1].contains(&tok(Token::Function, "g")));
+    }
+
+    #[test]
+    fn python_triple_quoted_strings_span_lines() {
+        let lines = lex(
+            Lang::Python,
+            &["def f():", "    \"\"\"Doc", "    more", "    \"\"\" # done", "'open"],
+        );
+        assert_eq!(lines[1].last(), Some(&tok(Token::String, "\"\"\"Doc")));
+        assert_eq!(lines[2], [tok(Token::String, "    more")]);
+        assert_eq!(lines[3][0], tok(Token::String, "    \"\"\""));
+        assert_eq!(lines[3].last(), Some(&tok(Token::Comment, "# done")));
+        // Single quotes don't carry over
+        assert_eq!(lines[4], [tok(Token::String, "'open")]);
+        let next = lex(Lang::Python, &["'open", "x"]);
+        assert_eq!(next[1], [tok(Token::Plain, "x")]);
+    }
+
+    #[test]
+    fn python_decorators() {
+        let lines = lex(Lang::Python, &["    @app.route", "x = a @ b"]);
+        assert_eq!(lines[0][1], tok(Token::Meta, "@app.route"));
+        assert!(!lines[1].iter().any(|(t, _)| *t == Token::Meta));
+    }
+
+    #[test]
+    fn script_template_literals_and_block_comments_span_lines() {
+        let lines = lex(Lang::Script, &["const s = `a", "${b}`; /*", "*/ f();"]);
+        assert_eq!(lines[0].last(), Some(&tok(Token::String, "`a")));
+        assert_eq!(lines[1][0], tok(Token::String, "${b}`"));
+        assert_eq!(lines[1].last(), Some(&tok(Token::Comment, "/*")));
+        assert_eq!(lines[2][0], tok(Token::Comment, "*/"));
+        assert!(lines[2].contains(&tok(Token::Function, "f")));
+    }
+
+    #[test]
+    fn shell_comments_need_a_word_boundary() {
+        let lines = lex(Lang::Shell, &["echo $# a#b ${HOME} # note", "\"open", "next"]);
+        assert!(lines[0].contains(&tok(Token::Variable, "$#")));
+        assert!(lines[0].contains(&tok(Token::Variable, "${HOME}")));
+        assert!(lines[0].contains(&tok(Token::Plain, " a#b ")));
+        assert_eq!(lines[0].last(), Some(&tok(Token::Comment, "# note")));
+        // Shell strings don't carry over
+        assert_eq!(lines[2], [tok(Token::Plain, "next")]);
+    }
+
+    #[test]
+    fn json_keys_and_values() {
+        let lines = lex(Lang::Json, &["{\"a\": \"b\", \"n\" : 1.5, \"t\": true}"]);
+        assert!(lines[0].contains(&tok(Token::Key, "\"a\"")));
+        assert!(lines[0].contains(&tok(Token::String, "\"b\"")));
+        assert!(lines[0].contains(&tok(Token::Key, "\"n\"")));
+        assert!(lines[0].contains(&tok(Token::Number, "1.5")));
+        assert!(lines[0].contains(&tok(Token::Type, "true")));
+    }
+
+    #[test]
+    fn toml_tables_keys_and_multiline_strings() {
+        let lines = lex(
+            Lang::Toml,
+            &["[dev-dependencies]", "dev-tool = \"\"\"one", "[not a table]", "\"\"\" # c"],
+        );
+        assert_eq!(lines[0], [tok(Token::Meta, "[dev-dependencies]")]);
+        assert_eq!(lines[1][0], tok(Token::Key, "dev-tool"));
+        assert_eq!(lines[1].last(), Some(&tok(Token::String, "\"\"\"one")));
+        // Inside the string a `[` line is not a table
+        assert_eq!(lines[2], [tok(Token::String, "[not a table]")]);
+        assert_eq!(lines[3][0], tok(Token::String, "\"\"\""));
+        assert_eq!(lines[3].last(), Some(&tok(Token::Comment, "# c")));
+    }
+
+    #[test]
+    fn diff_lines_are_classified_whole() {
+        let lines = lex(Lang::Diff, &["--- a/x", "+++ b/x", "@@ -1 +1 @@", "-old", "+new", " same"]);
+        let tokens: Vec<Token> = lines.iter().map(|l| l[0].0).collect();
+        assert_eq!(
+            tokens,
+            [Token::Meta, Token::Meta, Token::Hunk, Token::Removed, Token::Added, Token::Plain]
+        );
+        assert!(lines.iter().all(|l| l.len() == 1));
+    }
+}
@@ -1,16 +1,35 @@
-use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::time::Instant;
 
-use crate::app::{App, InputState, Mode, SessionStatus};
-use crate::protocol::types::{OutgoingControlRequest, OutgoingControlResponse, OutgoingSetPermissionMode};
+use crossterm::event::{
+    KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+
+use crate::app::{
+    App, ChatRole, InboxItem, InputState, Mode, PermissionPrompt, Session, SessionStatus,
+    SplitDirection,
+};
+use crate::permissions::rules::PermissionRule;
+use crate::protocol::types::{
+    format_permission_suggestion, OutgoingControlRequest, OutgoingControlResponse,
+    PERMISSION_MODES,
+};
 
 /// Handle permission overlay keys. Returns true if a permission key was handled.
 pub fn handle_permission_keys(key: KeyEvent, app: &mut App) -> bool {
+    if app
+        .active_session()
+        .map(|s| s.permission_prompt.is_some())
+        .unwrap_or(false)
+    {
+        return handle_permission_prompt_keys(key, app);
+    }
+
     let session = match app.active_session_mut() {
         Some(s) => s,
         None => return false,
     };
 
-    if let Some(perm) = session.pending_permission.take() {
+    if let Some(perm) = session.pending_permissions.pop_front() {
         match key.code {
             KeyCode::Char('y') | KeyCode::Char('Y') => {
                 let resp = OutgoingControlResponse::allow(perm.request_id, perm.input);
@@ -26,22 +45,278 @@ pub fn handle_permission_keys(key: KeyEvent, app: &mut App) -> bool {
                 app.dirty = true;
                 return true;
             }
-            KeyCode::Char('a') | KeyCode::Char('A') => {
+            KeyCode::Char('B') if !session.pending_permissions.is_empty() => {
+                // Answer the whole queue, after confirming
+                session.permission_prompt = Some(PermissionPrompt::ConfirmAll);
+                session.pending_permissions.push_front(perm);
+                app.dirty = true;
+                return true;
+            }
+            KeyCode::Char('d') | KeyCode::Char('D') => {
+                // Deny with a message explaining what to do instead
+                session.permission_prompt = Some(PermissionPrompt::DenyReason(InputState::new()));
+                session.pending_permissions.push_front(perm);
+                app.dirty = true;
+                return true;
+            }
+            KeyCode::Char('e') | KeyCode::Char('E') => {
+                // Edit the tool input inline before approving
+                let json = serde_json::to_string_pretty(&perm.input).unwrap_or_default();
+                session.permission_prompt =
+                    Some(PermissionPrompt::EditInput(InputState::with_text(json)));
+                session.pending_permissions.push_front(perm);
+                app.dirty = true;
+                return true;
+            }
+            KeyCode::Char(c @ '1'..='9') => {
+                // Apply one of the CLI's suggested permission updates
+                let idx = (c as usize) - ('1' as usize);
+                let suggestion = perm
+                    .permission_suggestions
+                    .as_ref()
+                    .and_then(|s| s.get(idx))
+                    .cloned();
+                if let Some(suggestion) = suggestion {
+                    // The CLI applies a setMode update and reports the new mode
+                    if suggestion.get("type").and_then(|v| v.as_str()) == Some("setMode") {
+                        if let Some(mode) = suggestion.get("mode").and_then(|v| v.as_str()) {
+                            session.requested_permission_mode =
+                                Some((mode.to_string(), Instant::now()));
+                        }
+                    }
+                    let label = format_permission_suggestion(&suggestion);
+                    let resp = OutgoingControlResponse::allow_with_permissions(
+                        perm.request_id,
+                        perm.input,
+                        vec![suggestion],
+                    );
+                    session.send_to_cli(&resp.to_ndjson());
+                    session.add_system_message(format!("[approved] {}: {}", perm.tool_name, label));
+                    app.dirty = true;
+                    return true;
+                }
+                session.pending_permissions.push_front(perm);
+            }
+            KeyCode::Char('a') => {
+                // Always allow for this session
+                let rule = PermissionRule::allow_for(&perm.tool_name, &perm.input);
+                let resp = OutgoingControlResponse::allow(perm.request_id, perm.input);
+                session.send_to_cli(&resp.to_ndjson());
+                session.add_system_message(format!("[always-allow] {} (session)", rule));
+                session.permission_rules.push(rule);
+                session.dirty_persist = true;
+                app.dirty = true;
+                return true;
+            }
+            KeyCode::Char('A') => {
+                // Always allow globally (saved to ~/.companion/permissions.json)
+                let rule = PermissionRule::allow_for(&perm.tool_name, &perm.input);
                 let resp = OutgoingControlResponse::allow(perm.request_id, perm.input);
                 session.send_to_cli(&resp.to_ndjson());
-                session.add_system_message(format!("[always-allow] {}", perm.tool_name));
+                session.add_system_message(format!("[always-allow] {} (global)", rule));
+                app.permission_rules.push(rule);
+                app.save_permission_rules();
                 app.dirty = true;
                 return true;
             }
             _ => {
                 // Put permission back — unhandled key
-                session.pending_permission = Some(perm);
+                session.pending_permissions.push_front(perm);
             }
         }
     }
     false
 }
 
+/// Handle keys while an inline permission prompt is open. Consumes every key.
+fn handle_permission_prompt_keys(key: KeyEvent, app: &mut App) -> bool {
+    let session = match app.active_session_mut() {
+        Some(s) => s,
+        None => return false,
+    };
+    let (input, multiline) = match session.permission_prompt.as_mut() {
+        Some(PermissionPrompt::EditInput(input)) => (input, true),
+        Some(PermissionPrompt::DenyReason(input)) => (input, false),
+        Some(PermissionPrompt::ConfirmAll) => {
+            let approve = match key.code {
+                KeyCode::Char('a') => Some(true),
+                KeyCode::Char('d') => Some(false),
+                KeyCode::Esc => None,
+                _ => return true,
+            };
+            session.permission_prompt = None;
+            if let Some(approve) = approve {
+                let count = session.pending_permissions.len();
+                for perm in std::mem::take(&mut session.pending_permissions) {
+                    let resp = if approve {
+                        OutgoingControlResponse::allow(perm.request_id, perm.input)
+                    } else {
+                        OutgoingControlResponse::deny(perm.request_id, "Denied by user")
+                    };
+                    session.send_to_cli(&resp.to_ndjson());
+                }
+                let verb = if approve { "approved" } else { "denied" };
+                session.add_system_message(format!("[{}] all {} pending requests", verb, count));
+            }
+            app.dirty = true;
+            return true;
+        }
+        None => return false,
+    };
+
+    if key.modifiers.contains(KeyModifiers::CONTROL) {
+        match key.code {
+            KeyCode::Char('c') => session.permission_prompt = None,
+            KeyCode::Char('j') if multiline => input.insert_newline(),
+            KeyCode::Char('a') => input.home(),
+            KeyCode::Char('e') => input.end(),
+            KeyCode::Char('k') => input.kill_to_end(),
+            KeyCode::Char('u') => input.kill_to_start(),
+            KeyCode::Char('w') => input.delete_word_back(),
+            _ => {}
+        }
+        app.dirty = true;
+        return true;
+    }
+
+    match key.code {
+        KeyCode::Esc => {
+            session.permission_prompt = None;
+        }
+        KeyCode::Enter => match session.permission_prompt.take() {
+            Some(PermissionPrompt::EditInput(input)) => {
+                match serde_json::from_str::<serde_json::Value>(&input.text) {
+                    Ok(edited) if edited.is_object() => {
+                        if let Some(perm) = session.pending_permissions.pop_front() {
+                            let resp = OutgoingControlResponse::allow(perm.request_id, edited);
+                            session.send_to_cli(&resp.to_ndjson());
+                            session.add_system_message(format!(
+                                "[approved] {} (edited input)",
+                                perm.tool_name
+                            ));
+                        }
+                    }
+                    Ok(_) => {
+                        session.permission_prompt = Some(PermissionPrompt::EditInput(input));
+                        app.flash("Tool input must be a JSON object".to_string());
+                    }
+                    Err(e) => {
+                        session.permission_prompt = Some(PermissionPrompt::EditInput(input));
+                        app.flash(format!("Invalid JSON: {}", e));
+                    }
+                }
+            }
+            Some(PermissionPrompt::DenyReason(input)) => {
+                if let Some(perm) = session.pending_permissions.pop_front() {
+                    let reason = input.text.trim();
+                    let message = if reason.is_empty() { "Denied by user" } else { reason };
+                    let resp = OutgoingControlResponse::deny(perm.request_id, message);
+                    session.send_to_cli(&resp.to_ndjson());
+                    session.add_system_message(format!("[denied] {}: {}", perm.tool_name, message));
+                }
+            }
+            Some(PermissionPrompt::ConfirmAll) | None => {}
+        },
+        KeyCode::Char(c) => input.insert_char(c),
+        KeyCode::Tab if multiline => {
+            input.insert_char(' ');
+            input.insert_char(' ');
+        }
+        KeyCode::Backspace => input.backspace(),
+        KeyCode::Delete => input.delete(),
+        KeyCode::Left => input.move_left(),
+        KeyCode::Right => input.move_right(),
+        KeyCode::Up => {
+            input.move_up();
+        }
+        KeyCode::Down => {
+            input.move_down();
+        }
+        KeyCode::Home => input.home(),
+        KeyCode::End => input.end(),
+        _ => {}
+    }
+    app.dirty = true;
+    true
+}
+
+/// Open the cross-session inbox, or flash if nothing is waiting.
+pub fn open_inbox(app: &mut App) {
+    if app.inbox_items().is_empty() {
+        app.flash("Inbox empty".to_string());
+    } else {
+        app.inbox = Some(Default::default());
+    }
+    app.dirty = true;
+}
+
+/// Handle keys while the inbox overlay is open. Always consumes the key.
+pub fn handle_inbox_keys(key: KeyEvent, app: &mut App) -> bool {
+    let items = app.inbox_items();
+    let selected = app.inbox.as_ref().map(|i| i.selected).unwrap_or(0);
+    let item = items.get(selected.min(items.len().saturating_sub(1))).cloned();
+    app.dirty = true;
+
+    match key.code {
+        KeyCode::Char('j') | KeyCode::Down => {
+            if let Some(inbox) = &mut app.inbox {
+                inbox.selected = (inbox.selected + 1).min(items.len().saturating_sub(1));
+            }
+        }
+        KeyCode::Char('k') | KeyCode::Up => {
+            if let Some(inbox) = &mut app.inbox {
+                inbox.selected = inbox.selected.saturating_sub(1);
+            }
+        }
+        KeyCode::Char('y') | KeyCode::Char('n') => match item {
+            Some(InboxItem::Permission { session_id, index }) => {
+                let approve = key.code == KeyCode::Char('y');
+                if let Some(session) = app.sessions.get_mut(&session_id) {
+                    let deny = (!approve).then_some("Denied by user");
+                    session.answer_permission(index, deny);
+                }
+                let remaining = app.inbox_items().len();
+                if remaining == 0 {
+                    app.inbox = None;
+                    app.flash("Inbox empty".to_string());
+                } else if let Some(inbox) = &mut app.inbox {
+                    inbox.selected = inbox.selected.min(remaining - 1);
+                }
+            }
+            Some(InboxItem::Question { .. }) => {
+                app.flash("Press Enter to answer the question in its session".to_string());
+            }
+            None => {}
+        },
+        KeyCode::Enter => {
+            if let Some(item) = item {
+                // Bring the selected request to the front so its banner shows
+                if let InboxItem::Permission { session_id, index } = &item {
+                    if let Some(session) = app.sessions.get_mut(session_id) {
+                        if *index > 0 {
+                            if let Some(perm) = session.pending_permissions.remove(*index) {
+                                session.permission_prompt = None;
+                                session.pending_permissions.push_front(perm);
+                            }
+                        }
+                    }
+                }
+                app.switch_to_session(item.session_id());
+                app.mode = Mode::Normal;
+            }
+            app.inbox = None;
+        }
+        KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('I') => {
+            app.inbox = None;
+        }
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            app.inbox = None;
+        }
+        _ => {}
+    }
+    true
+}
+
 /// Handle AskUserQuestion overlay keys. Returns true if a question key was handled.
 pub fn handle_question_keys(key: KeyEvent, app: &mut App) -> bool {
     let session = match app.active_session_mut() {
@@ -153,15 +428,6 @@ pub fn handle_key_normal(key: KeyEvent, app: &mut App) -> bool {
                     'n' => {
                         if !search.matches.is_empty() {
                             search.current_match = (search.current_match + 1) % search.matches.len();
-                            // Scroll to current match
-                            if let Some(session) = app.active_session_mut() {
-                                // We'll set scroll_offset so the matched line is visible
-                                // matches are line indices; we need to convert to scroll_offset from bottom
-                                // This is approximate — we just need to get close
-                                let match_line = search.matches[search.current_match];
-                                // scroll_offset = total_lines - match_line - half_screen (approx)
-                                // We don't know total lines here, so just set a flag for renderer
-                            }
                         }
                         app.dirty = true;
                         return false;
@@ -185,15 +451,13 @@ pub fn handle_key_normal(key: KeyEvent, app: &mut App) -> bool {
                     }
                 }
             }
-            KeyCode::Backspace => {
-                if search.matches.is_empty() && search.current_match == 0 {
-                    search.input.backspace();
-                    if search.input.is_empty() {
-                        app.search = None;
-                    }
-                    app.dirty = true;
-                    return false;
+            KeyCode::Backspace if search.matches.is_empty() && search.current_match == 0 => {
+                search.input.backspace();
+                if search.input.is_empty() {
+                    app.search = None;
                 }
+                app.dirty = true;
+                return false;
             }
             KeyCode::Enter => {
                 // Execute the search — populate matches
@@ -205,14 +469,15 @@ pub fn handle_key_normal(key: KeyEvent, app: &mut App) -> bool {
                     // Signal that search should be executed (renderer will populate matches)
                     search.current_match = 0;
                     // We need to do the actual matching here since we have access to session
-                    if let Some(session) = app.active_session() {
+                    let active = app.active_session_id.as_ref().and_then(|id| app.sessions.get(id));
+                    if let Some(session) = active {
                         let query = search.input.text.to_lowercase();
                         if !query.is_empty() {
                             // We can't call build_chat_lines from keybindings without renderer dependency
                             // Instead, search directly in session.messages
                             let mut match_indices = Vec::new();
                             let mut line_idx = 0usize;
-                            for msg in &session.messages {
+                            for msg in session.messages.iter().filter(|m| !m.is_tool_results()) {
                                 let text = match msg.role {
                                     crate::app::ChatRole::User => format!("You: {}", msg.content),
                                     crate::app::ChatRole::Assistant => format!("Claude: {}", msg.content),
@@ -269,9 +534,20 @@ pub fn handle_key_normal(key: KeyEvent, app: &mut App) -> bool {
         // Not 'g' after 'g' — fall through to handle this key normally
     }
 
+    // Ctrl+W <key>: pane commands
+    if app.window_pending {
+        app.window_pending = false;
+        handle_window_key(key, app);
+        return false;
+    }
+
     // Ctrl combos first
     if key.modifiers.contains(KeyModifiers::CONTROL) {
         match key.code {
+            KeyCode::Char('w') => {
+                app.window_pending = true;
+                return false;
+            }
             KeyCode::Char('c') => {
                 if let Some(session) = app.active_session_mut() {
                     if session.status == SessionStatus::Running && !session.interrupt_sent {
@@ -397,35 +673,31 @@ pub fn handle_key_normal(key: KeyEvent, app: &mut App) -> bool {
             app.flash(if app.show_thinking { "Thinking: shown".to_string() } else { "Thinking: hidden".to_string() });
             app.dirty = true;
         }
+        // Toggle mouse capture (off leaves text selection to the terminal)
+        KeyCode::Char('M') => {
+            app.mouse_capture = !app.mouse_capture;
+            app.flash(if app.mouse_capture { "Mouse: on".to_string() } else { "Mouse: off (select text freely)".to_string() });
+            app.dirty = true;
+        }
+        // Cross-session inbox
+        KeyCode::Char('I') => {
+            open_inbox(app);
+        }
         // Toggle plan mode
         KeyCode::Char('p') => {
             if let Some(session) = app.active_session_mut() {
                 if !session.cli_connected {
                     app.flash("CLI not connected".to_string());
                 } else if session.permission_mode == "plan" {
-                    // Restore previous mode
+                    // Back to the mode plan was entered from
                     let prev = session
                         .previous_permission_mode
-                        .take()
-                        .unwrap_or_else(|| "default".to_string());
-                    let cli_session_id = session
-                        .cli_session_id
                         .clone()
-                        .unwrap_or_else(|| session.id.clone());
-                    let msg = OutgoingSetPermissionMode::new(prev.clone(), cli_session_id);
-                    session.send_to_cli(&msg.to_ndjson());
-                    session.permission_mode = prev;
+                        .unwrap_or_else(|| "default".to_string());
+                    session.request_permission_mode(&prev);
                 } else {
-                    // Save current mode and switch to plan
-                    session.previous_permission_mode = Some(session.permission_mode.clone());
-                    let cli_session_id = session
-                        .cli_session_id
-                        .clone()
-                        .unwrap_or_else(|| session.id.clone());
-                    let msg =
-                        OutgoingSetPermissionMode::new("plan".to_string(), cli_session_id);
-                    session.send_to_cli(&msg.to_ndjson());
-                    session.permission_mode = "plan".to_string();
+                    // The mode to restore is saved once the CLI acknowledges
+                    session.request_permission_mode("plan");
                 }
             }
             app.dirty = true;
@@ -439,11 +711,10 @@ pub fn handle_key_normal(key: KeyEvent, app: &mut App) -> bool {
             });
             app.dirty = true;
         }
-        // Yank last assistant message to clipboard
+        // Yank the main agent's last reply to clipboard (not a subagent's)
         KeyCode::Char('y') => {
             if let Some(session) = app.active_session() {
-                if let Some(msg) = session.messages.iter().rev().find(|m| matches!(m.role, crate::app::ChatRole::Assistant)) {
-                    let content = msg.content.clone();
+                if let Some(content) = last_reply(session) {
                     match std::process::Command::new("pbcopy")
                         .stdin(std::process::Stdio::piped())
                         .spawn()
@@ -466,11 +737,19 @@ pub fn handle_key_normal(key: KeyEvent, app: &mut App) -> bool {
             }
             app.dirty = true;
         }
-        // Toggle tool results collapsed
+        // Threads and tool cards: select previous/next, expand/collapse
+        KeyCode::Char('{') => select_fold(app, false),
+        KeyCode::Char('}') => select_fold(app, true),
+        KeyCode::Char('o') => toggle_fold(app),
+        // Collapse or expand all tool cards
         KeyCode::Char('z') => {
-            if let Some(session) = app.active_session_mut() {
-                session.tool_results_collapsed = !session.tool_results_collapsed;
-                app.flash(if session.tool_results_collapsed {
+            let collapsed = app.active_session_mut().map(|session| {
+                let collapsed = !session.tool_results_collapsed;
+                session.set_tool_results_collapsed(collapsed);
+                collapsed
+            });
+            if let Some(collapsed) = collapsed {
+                app.flash(if collapsed {
                     "Tool results: collapsed".to_string()
                 } else {
                     "Tool results: expanded".to_string()
@@ -481,6 +760,11 @@ pub fn handle_key_normal(key: KeyEvent, app: &mut App) -> bool {
         // Esc
         KeyCode::Esc => {
             app.gg_pending = false;
+            if let Some(session) = app.active_session_mut() {
+                if session.selected_fold.take().is_some() {
+                    app.dirty = true;
+                }
+            }
         }
         // Page scroll
         KeyCode::PageUp => {
@@ -605,10 +889,8 @@ pub fn handle_key_insert(key: KeyEvent, app: &mut App) -> bool {
             app.mode = Mode::Normal;
             app.dirty = true;
         }
-        KeyCode::Enter => {
-            if !app.composer.is_empty() {
-                return true; // signal to event_loop to send the message
-            }
+        KeyCode::Enter if !app.composer.is_empty() => {
+            return true; // signal to event_loop to send the message
         }
         KeyCode::Char(c) => {
             if key.modifiers.contains(KeyModifiers::CONTROL) {
@@ -795,6 +1077,11 @@ pub fn handle_key_command(key: KeyEvent, app: &mut App) -> Option<String> {
             app.dirty = true;
             None
         }
+        KeyCode::Tab => {
+            complete_command_arg(app);
+            app.dirty = true;
+            None
+        }
         KeyCode::Left => {
             app.command_input.move_left();
             app.dirty = true;
@@ -841,3 +1128,571 @@ pub fn handle_key_command(key: KeyEvent, app: &mut App) -> Option<String> {
         _ => None,
     }
 }
+
+/// Tab completion for command arguments (`:mode` and `:theme`).
+/// A complete argument cycles to the next one.
+fn complete_command_arg(app: &mut App) {
+    let (cmd, arg) = match app.command_input.text.split_once(' ') {
+        Some((cmd, arg)) => (cmd.to_string(), arg.trim().to_string()),
+        None => (app.command_input.text.clone(), String::new()),
+    };
+    let options: Vec<String> = match cmd.as_str() {
+        "mode" => PERMISSION_MODES.iter().map(|m| m.to_string()).collect(),
+        "theme" => app.theme_names(),
+        _ => return,
+    };
+
+    let completed = if let Some(i) = options.iter().position(|m| *m == arg) {
+        Some(options[(i + 1) % options.len()].clone())
+    } else {
+        let candidates: Vec<&str> = options
+            .iter()
+            .map(|m| m.as_str())
+            .filter(|m| m.to_lowercase().starts_with(&arg.to_lowercase()))
+            .collect();
+        match candidates.as_slice() {
+            [] => None,
+            [only] => Some(only.to_string()),
+            many => {
+                app.flash(many.join("  "));
+                None
+            }
+        }
+    };
+
+    if let Some(completed) = completed {
+        app.command_input.text = format!("{} {}", cmd, completed);
+        app.command_input.cursor = app.command_input.text.len();
+    }
+}
+
+/// Text of the main agent's last reply, which `y` yanks
+fn last_reply(session: &Session) -> Option<String> {
+    session
+        .messages
+        .iter()
+        .rev()
+        .find(|m| matches!(m.role, ChatRole::Assistant) && m.parent_tool_use_id.is_none())
+        .map(|m| m.content.clone())
+}
+
+// ─── Folds ──────────────────────────────────────────────────────────────────
+
+/// Index of the lowest fold on screen (or above it), the default target of
+/// `{`, `}` and `o` when nothing is selected
+fn nearest_fold(app: &App, scroll_offset: usize) -> Option<usize> {
+    let view = &app.chat_view;
+    if view.folds.is_empty() || view.session_id != app.active_session_id {
+        return None;
+    }
+    let bottom = view.total_lines.saturating_sub(scroll_offset);
+    Some(
+        view.folds
+            .iter()
+            .rposition(|(_, line)| *line < bottom)
+            .unwrap_or(0),
+    )
+}
+
+/// Move the fold selection and scroll it into view
+fn select_fold(app: &mut App, forward: bool) {
+    let (selected, scroll_offset) = match app.active_session() {
+        Some(s) => (s.selected_fold.clone(), s.scroll_offset),
+        None => return,
+    };
+    let nearest = match nearest_fold(app, scroll_offset) {
+        Some(i) => i,
+        None => {
+            app.flash("No threads or tool cards".to_string());
+            return;
+        }
+    };
+    let view = &app.chat_view;
+    let current = selected.and_then(|id| view.folds.iter().position(|(f, _)| *f == id));
+    let next = match current {
+        Some(i) if forward => (i + 1).min(view.folds.len() - 1),
+        Some(i) => i.saturating_sub(1),
+        None => nearest,
+    };
+    let (id, line) = view.folds[next].clone();
+    let (total, height) = (view.total_lines, view.height);
+
+    if let Some(session) = app.active_session_mut() {
+        session.selected_fold = Some(id);
+        let end = total.saturating_sub(session.scroll_offset);
+        let start = end.saturating_sub(height);
+        if line < start || line >= end {
+            session.scroll_offset = total.saturating_sub(line + 1 + height / 2);
+            session.scroll_locked = session.scroll_offset > 0;
+        }
+    }
+    app.dirty = true;
+}
+
+/// Expand or collapse the selected thread or tool card (or the nearest one)
+fn toggle_fold(app: &mut App) {
+    let (selected, scroll_offset) = match app.active_session() {
+        Some(s) => (s.selected_fold.clone(), s.scroll_offset),
+        None => return,
+    };
+    let id = match selected {
+        Some(id) => id,
+        None => match nearest_fold(app, scroll_offset) {
+            Some(i) => app.chat_view.folds[i].0.clone(),
+            None => {
+                app.flash("No threads or tool cards".to_string());
+                return;
+            }
+        },
+    };
+    if let Some(session) = app.active_session_mut() {
+        session.toggle_fold(&id);
+    }
+    app.dirty = true;
+}
+
+// ─── Panes ──────────────────────────────────────────────────────────────────
+
+/// The key after Ctrl+W: split, move focus, rearrange or resize panes
+fn handle_window_key(key: KeyEvent, app: &mut App) {
+    match key.code {
+        KeyCode::Char(c @ ('v' | 's')) => {
+            let direction = if c == 'v' {
+                SplitDirection::Vertical
+            } else {
+                SplitDirection::Horizontal
+            };
+            // An open split keeps its session and only changes direction
+            let other = match app.split_session() {
+                Some(session) => Some(session.id.clone()),
+                None => app.next_session_id(),
+            };
+            match other {
+                Some(id) => app.open_split(direction, id),
+                None => app.flash("No other session to split with".to_string()),
+            }
+        }
+        KeyCode::Char('w') | KeyCode::Tab => app.focus_other_pane(),
+        // Swap the panes' places
+        KeyCode::Char('x') => {
+            if let Some(split) = &mut app.layout.split {
+                split.focused_first = !split.focused_first;
+            }
+        }
+        // Side by side <-> stacked
+        KeyCode::Char('r') => {
+            if let Some(split) = &mut app.layout.split {
+                split.direction = match split.direction {
+                    SplitDirection::Vertical => SplitDirection::Horizontal,
+                    SplitDirection::Horizontal => SplitDirection::Vertical,
+                };
+            }
+        }
+        KeyCode::Char('o') | KeyCode::Char('q') | KeyCode::Char('c') => app.layout.split = None,
+        KeyCode::Char('<') => app.layout.resize_sidebar(-2),
+        KeyCode::Char('>') => app.layout.resize_sidebar(2),
+        KeyCode::Char('+') => app.layout.resize_task_panel(1),
+        KeyCode::Char('-') => app.layout.resize_task_panel(-1),
+        KeyCode::Char('h') | KeyCode::Char('k') | KeyCode::Left | KeyCode::Up => {
+            app.layout.move_divider(-5)
+        }
+        KeyCode::Char('l') | KeyCode::Char('j') | KeyCode::Right | KeyCode::Down => {
+            app.layout.move_divider(5)
+        }
+        KeyCode::Char('=') => app.layout.reset_sizes(),
+        _ => return,
+    }
+    app.dirty = true;
+}
+
+// ─── Mouse ──────────────────────────────────────────────────────────────────
+
+/// Lines scrolled per wheel notch
+const WHEEL_LINES: usize = 3;
+
+/// Wheel scrolls the chat; a left click switches to a sidebar session,
+/// presses a permission banner button, or toggles the fold it lands on
+pub fn handle_mouse(mouse: MouseEvent, app: &mut App) {
+    // The inbox overlay is keyboard-driven and covers the chat
+    if app.inbox.is_some() {
+        return;
+    }
+    match mouse.kind {
+        MouseEventKind::ScrollUp | MouseEventKind::ScrollDown => {
+            if mouse.column < app.click_targets.sidebar_w {
+                return;
+            }
+            // The split's other pane scrolls under the pointer; anywhere
+            // else scrolls the focused one
+            let view = match &app.click_targets.other_pane {
+                Some(view) if view.contains(mouse.column, mouse.row) => view,
+                _ => &app.chat_view,
+            };
+            let max_offset = view.total_lines.saturating_sub(view.height);
+            let Some(id) = view.session_id.clone() else {
+                return;
+            };
+            if let Some(session) = app.sessions.get_mut(&id) {
+                if mouse.kind == MouseEventKind::ScrollUp {
+                    // Stop once the first line is at the top
+                    session.scroll_offset =
+                        session.scroll_offset.saturating_add(WHEEL_LINES).min(max_offset);
+                    session.scroll_locked = session.scroll_offset > 0;
+                } else {
+                    session.scroll_offset = session.scroll_offset.saturating_sub(WHEEL_LINES);
+                    if session.scroll_offset == 0 { session.scroll_locked = false; }
+                }
+            }
+            app.dirty = true;
+        }
+        MouseEventKind::Down(MouseButton::Left) => click(app, mouse.column, mouse.row),
+        _ => {}
+    }
+}
+
+fn click(app: &mut App, col: u16, row: u16) {
+    let targets = &app.click_targets;
+    if col < targets.sidebar_w {
+        if let Some(id) = targets.sidebar_rows.get(row as usize).cloned() {
+            app.switch_to_session(&id);
+        }
+        return;
+    }
+
+    let button = targets
+        .buttons
+        .iter()
+        .find(|(r, cols, _)| *r == row && cols.contains(&col))
+        .map(|(_, _, key)| *key);
+    if let Some(key) = button {
+        handle_permission_keys(KeyEvent::new(KeyCode::Char(key), KeyModifiers::NONE), app);
+        return;
+    }
+
+    if let Some(view) = &targets.other_pane {
+        if view.contains(col, row) {
+            app.focus_other_pane();
+            return;
+        }
+    }
+
+    let view = &app.chat_view;
+    if view.session_id != app.active_session_id || !view.contains(col, row) {
+        return;
+    }
+    let line = view.first_line + (row - view.y) as usize;
+    let fold = view.folds.iter().find(|(_, l)| *l == line).map(|(id, _)| id.clone());
+    if let Some(id) = fold {
+        if let Some(session) = app.active_session_mut() {
+            session.toggle_fold(&id);
+        }
+        app.dirty = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{PendingPermission, Session};
+    use crate::server::ws_server::Endpoint;
+    use tokio::sync::mpsc;
+
+    /// An app with one active session holding `tools` as queued permission
+    /// requests (ids "r0", "r1", ...), and the receiving end of its CLI
+    fn app_with_queue(tools: &[&str]) -> (App, mpsc::UnboundedReceiver<String>) {
+        let mut app = App::new(Endpoint::Tcp(0), "/tmp".to_string(), None);
+        let mut session = Session::new("s1".to_string(), "test".to_string(), "/tmp".to_string());
+        let (tx, rx) = mpsc::unbounded_channel();
+        session.cli_sender = Some(tx);
+        for (i, tool) in tools.iter().enumerate() {
+            session.pending_permissions.push_back(PendingPermission {
+                request_id: format!("r{}", i),
+                tool_use_id: format!("toolu_{}", i),
+                tool_name: tool.to_string(),
+                input: serde_json::json!({}),
+                description: None,
+                permission_suggestions: None,
+                agent_id: None,
+            });
+        }
+        app.sessions.insert("s1".to_string(), session);
+        app.active_session_id = Some("s1".to_string());
+        (app, rx)
+    }
+
+    fn press(app: &mut App, c: char) -> bool {
+        handle_permission_keys(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE), app)
+    }
+
+    /// (request_id, behavior) of every response sent so far
+    fn answers(rx: &mut mpsc::UnboundedReceiver<String>) -> Vec<(String, String)> {
+        let mut out = Vec::new();
+        while let Ok(line) = rx.try_recv() {
+            let msg: serde_json::Value = serde_json::from_str(line.trim()).unwrap();
+            let resp = &msg["response"];
+            out.push((
+                resp["request_id"].as_str().unwrap().to_string(),
+                resp["response"]["behavior"].as_str().unwrap().to_string(),
+            ));
+        }
+        out
+    }
+
+    fn queued(app: &App) -> Vec<String> {
+        app.sessions["s1"]
+            .pending_permissions
+            .iter()
+            .map(|p| p.request_id.clone())
+            .collect()
+    }
+
+    #[test]
+    fn requests_are_answered_in_order() {
+        let (mut app, mut rx) = app_with_queue(&["Bash", "Write", "Edit"]);
+        assert!(press(&mut app, 'y'));
+        assert!(press(&mut app, 'n'));
+        assert_eq!(
+            answers(&mut rx),
+            vec![("r0".into(), "allow".into()), ("r1".into(), "deny".into())]
+        );
+        assert_eq!(queued(&app), vec!["r2"]);
+    }
+
+    #[test]
+    fn shifted_y_and_n_answer_only_the_current_request() {
+        let (mut app, mut rx) = app_with_queue(&["Bash", "Write", "Edit"]);
+        assert!(press(&mut app, 'Y'));
+        assert_eq!(answers(&mut rx), vec![("r0".into(), "allow".into())]);
+        assert!(press(&mut app, 'N'));
+        assert_eq!(answers(&mut rx), vec![("r1".into(), "deny".into())]);
+        assert_eq!(queued(&app), vec!["r2"]);
+    }
+
+    #[test]
+    fn bulk_answers_need_confirmation() {
+        let (mut app, mut rx) = app_with_queue(&["Bash", "Write"]);
+        assert!(press(&mut app, 'B'));
+        assert!(matches!(
+            app.sessions["s1"].permission_prompt,
+            Some(PermissionPrompt::ConfirmAll)
+        ));
+        // Other keys wait for an answer
+        assert!(press(&mut app, 'y'));
+        assert!(answers(&mut rx).is_empty());
+        assert_eq!(queued(&app), vec!["r0", "r1"]);
+
+        assert!(press(&mut app, 'd'));
+        assert_eq!(
+            answers(&mut rx),
+            vec![("r0".into(), "deny".into()), ("r1".into(), "deny".into())]
+        );
+        assert!(queued(&app).is_empty());
+        assert!(app.sessions["s1"].permission_prompt.is_none());
+    }
+
+    #[test]
+    fn bulk_answer_can_be_cancelled() {
+        let (mut app, mut rx) = app_with_queue(&["Bash", "Write"]);
+        assert!(press(&mut app, 'B'));
+        let esc = KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE);
+        assert!(handle_permission_keys(esc, &mut app));
+        assert!(answers(&mut rx).is_empty());
+        assert_eq!(queued(&app), vec!["r0", "r1"]);
+        assert!(app.sessions["s1"].permission_prompt.is_none());
+
+        assert!(press(&mut app, 'B'));
+        assert!(press(&mut app, 'a'));
+        assert_eq!(
+            answers(&mut rx),
+            vec![("r0".into(), "allow".into()), ("r1".into(), "allow".into())]
+        );
+    }
+
+    #[test]
+    fn bulk_answer_needs_more_than_one_request() {
+        let (mut app, mut rx) = app_with_queue(&["Bash"]);
+        assert!(!press(&mut app, 'B'));
+        assert!(app.sessions["s1"].permission_prompt.is_none());
+        assert!(answers(&mut rx).is_empty());
+        assert_eq!(queued(&app), vec!["r0"]);
+    }
+
+    fn message(role: ChatRole, content: &str, parent: Option<&str>) -> crate::app::ChatMessage {
+        crate::app::ChatMessage {
+            role,
+            content: content.to_string(),
+            content_blocks: None,
+            model: None,
+            timestamp: 0,
+            parent_tool_use_id: parent.map(String::from),
+        }
+    }
+
+    #[test]
+    fn o_toggles_only_the_selected_fold() {
+        let (mut app, _rx) = app_with_queue(&[]);
+        app.chat_view = crate::app::ChatView {
+            session_id: Some("s1".to_string()),
+            total_lines: 40,
+            height: 20,
+            folds: vec![("toolu_a".to_string(), 5), ("toolu_b".to_string(), 30)],
+            ..Default::default()
+        };
+        app.sessions.get_mut("s1").unwrap().selected_fold = Some("toolu_a".to_string());
+        let o = KeyEvent::new(KeyCode::Char('o'), KeyModifiers::NONE);
+
+        handle_key_normal(o, &mut app);
+        let session = &app.sessions["s1"];
+        assert!(!session.is_fold_collapsed("toolu_a", true));
+        assert!(session.is_fold_collapsed("toolu_b", true));
+
+        handle_key_normal(o, &mut app);
+        assert!(app.sessions["s1"].is_fold_collapsed("toolu_a", true));
+    }
+
+    #[test]
+    fn yank_takes_the_main_agents_reply() {
+        let mut session = Session::new("s1".to_string(), "test".to_string(), "/tmp".to_string());
+        assert_eq!(last_reply(&session), None);
+        session.messages.push(message(ChatRole::Assistant, "main reply", None));
+        session.messages.push(message(ChatRole::Assistant, "subagent note", Some("toolu_a")));
+        session.messages.push(message(ChatRole::System, "[approved] Bash", None));
+        assert_eq!(last_reply(&session).as_deref(), Some("main reply"));
+    }
+
+    fn mouse(app: &mut App, kind: MouseEventKind, column: u16, row: u16) {
+        let event = MouseEvent { kind, column, row, modifiers: KeyModifiers::NONE };
+        handle_mouse(event, app);
+    }
+
+    fn left_click(app: &mut App, column: u16, row: u16) {
+        mouse(app, MouseEventKind::Down(MouseButton::Left), column, row);
+    }
+
+    /// An app whose last frame had a 20-column sidebar listing "s1" and "s2"
+    /// and showed lines 10..15 of 20 of "s1" to its right
+    fn app_on_screen(tools: &[&str]) -> (App, mpsc::UnboundedReceiver<String>) {
+        let (mut app, rx) = app_with_queue(tools);
+        let other = Session::new("s2".to_string(), "other".to_string(), "/tmp".to_string());
+        app.sessions.insert("s2".to_string(), other);
+        app.click_targets.sidebar_w = 20;
+        app.click_targets.sidebar_rows = vec!["s1".to_string(), "s2".to_string()];
+        app.chat_view = crate::app::ChatView {
+            session_id: Some("s1".to_string()),
+            total_lines: 20,
+            height: 5,
+            x: 20,
+            y: 0,
+            width: 60,
+            first_line: 10,
+            folds: vec![("toolu_a".to_string(), 12), ("toolu_b".to_string(), 14)],
+        };
+        (app, rx)
+    }
+
+    #[test]
+    fn wheel_scrolls_the_chat_up_to_its_first_line() {
+        let (mut app, _rx) = app_on_screen(&[]);
+        for _ in 0..6 {
+            mouse(&mut app, MouseEventKind::ScrollUp, 30, 2);
+        }
+        assert_eq!(app.sessions["s1"].scroll_offset, 15);
+        assert!(app.sessions["s1"].scroll_locked);
+
+        mouse(&mut app, MouseEventKind::ScrollDown, 30, 2);
+        assert_eq!(app.sessions["s1"].scroll_offset, 12);
+        assert!(app.sessions["s1"].scroll_locked);
+        for _ in 0..4 {
+            mouse(&mut app, MouseEventKind::ScrollDown, 30, 2);
+        }
+        assert_eq!(app.sessions["s1"].scroll_offset, 0);
+        assert!(!app.sessions["s1"].scroll_locked);
+
+        // Over the sidebar the wheel does nothing
+        mouse(&mut app, MouseEventKind::ScrollUp, 5, 2);
+        assert_eq!(app.sessions["s1"].scroll_offset, 0);
+    }
+
+    #[test]
+    fn clicking_a_sidebar_row_switches_sessions() {
+        let (mut app, _rx) = app_on_screen(&[]);
+        left_click(&mut app, 5, 1);
+        assert_eq!(app.active_session_id.as_deref(), Some("s2"));
+        // Below the last session
+        left_click(&mut app, 5, 4);
+        assert_eq!(app.active_session_id.as_deref(), Some("s2"));
+    }
+
+    #[test]
+    fn banner_buttons_press_their_key() {
+        let (mut app, mut rx) = app_on_screen(&["Bash", "Write", "Edit"]);
+        app.click_targets.buttons = vec![(6, 30..37, 'y'), (6, 38..44, 'n')];
+        left_click(&mut app, 30, 6);
+        left_click(&mut app, 43, 6);
+        // Between the buttons
+        left_click(&mut app, 37, 6);
+        assert_eq!(
+            answers(&mut rx),
+            vec![("r0".into(), "allow".into()), ("r1".into(), "deny".into())]
+        );
+        assert_eq!(queued(&app), vec!["r2"]);
+    }
+
+    #[test]
+    fn clicking_a_fold_header_toggles_only_that_fold() {
+        let (mut app, _rx) = app_on_screen(&[]);
+        left_click(&mut app, 25, 2);
+        let session = &app.sessions["s1"];
+        assert!(!session.is_fold_collapsed("toolu_a", true));
+        assert!(session.is_fold_collapsed("toolu_b", true));
+
+        // A line that is not a header
+        left_click(&mut app, 25, 3);
+        let session = &app.sessions["s1"];
+        assert!(!session.is_fold_collapsed("toolu_a", true));
+        assert!(session.is_fold_collapsed("toolu_b", true));
+    }
+
+    #[test]
+    fn mouse_is_ignored_while_the_inbox_is_open() {
+        let (mut app, mut rx) = app_on_screen(&["Bash"]);
+        app.click_targets.buttons = vec![(6, 30..37, 'y')];
+        app.inbox = Some(crate::app::InboxState::default());
+        left_click(&mut app, 5, 1);
+        left_click(&mut app, 30, 6);
+        left_click(&mut app, 25, 2);
+        mouse(&mut app, MouseEventKind::ScrollUp, 30, 2);
+
+        assert_eq!(app.active_session_id.as_deref(), Some("s1"));
+        assert!(answers(&mut rx).is_empty());
+        let session = &app.sessions["s1"];
+        assert!(session.is_fold_collapsed("toolu_a", true));
+        assert_eq!(session.scroll_offset, 0);
+    }
+
+    #[test]
+    fn wheel_and_clicks_reach_the_other_pane_under_the_pointer() {
+        let (mut app, _rx) = app_on_screen(&[]);
+        app.open_split(SplitDirection::Vertical, "s2".to_string());
+        app.chat_view.width = 30;
+        app.click_targets.other_pane = Some(crate::app::ChatView {
+            session_id: Some("s2".to_string()),
+            total_lines: 30,
+            height: 5,
+            x: 51,
+            width: 29,
+            ..Default::default()
+        });
+        for _ in 0..10 {
+            mouse(&mut app, MouseEventKind::ScrollUp, 60, 2);
+        }
+        assert_eq!(app.sessions["s2"].scroll_offset, 25);
+        assert_eq!(app.sessions["s1"].scroll_offset, 0);
+        mouse(&mut app, MouseEventKind::ScrollUp, 30, 2);
+        assert_eq!(app.sessions["s1"].scroll_offset, 3);
+
+        left_click(&mut app, 60, 2);
+        assert_eq!(app.active_session_id.as_deref(), Some("s2"));
+        assert_eq!(app.split_session().map(|s| s.id.as_str()), Some("s1"));
+    }
+}
@@ -0,0 +1,1398 @@
+//! Block-level markdown for assistant text (CommonMark plus GFM tables and
+//! strikethrough): headings, paragraphs, nested and numbered lists,
+//! blockquotes, fenced and indented code, tables and rules, with bold,
+//! italic, strikethrough, code spans and links inline.
+//!
+//! A message is parsed whole (the streamed text so far while streaming) and
+//! laid out to the chat width: paragraphs are word-wrapped, list items get a
+//! hanging indent and table columns are shrunk until the table fits.
+
+use std::collections::HashMap;
+
+use crossterm::style::Color;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::ui::highlight::{self, ColorDepth, HighlightState, Lang};
+use crate::ui::renderer::{highlighted_spans, wrap_spans, Span};
+use crate::ui::theme::Theme;
+
+/// Narrowest a table column gets before the table is left to overflow
+const MIN_COLUMN: usize = 3;
+
+/// What the terminal being drawn on can show
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Caps {
+    pub colors: ColorDepth,
+    /// OSC 8 hyperlinks; without them the URL is printed after the text
+    pub hyperlinks: bool,
+}
+
+/// Whether the local terminal renders OSC 8 hyperlinks, going by the
+/// variables of terminals known to support them. `FORCE_HYPERLINK=0/1`
+/// overrides.
+pub fn detect_hyperlinks() -> bool {
+    hyperlinks_from(|name| std::env::var_os(name).map(|v| v.to_string_lossy().into_owned()))
+}
+
+/// `detect_hyperlinks` over a variable lookup
+fn hyperlinks_from(lookup: impl Fn(&str) -> Option<String>) -> bool {
+    let var = |name: &str| lookup(name).unwrap_or_default();
+    match var("FORCE_HYPERLINK").as_str() {
+        "" => {}
+        "0" | "false" => return false,
+        _ => return true,
+    }
+    if ["iTerm.app", "WezTerm", "vscode", "ghostty", "Hyper"].contains(&var("TERM_PROGRAM").as_str()) {
+        return true;
+    }
+    if var("VTE_VERSION").parse::<u32>().map(|v| v >= 5000).unwrap_or(false) {
+        return true;
+    }
+    if ["WT_SESSION", "KONSOLE_VERSION", "KITTY_WINDOW_ID"]
+        .iter()
+        .any(|name| lookup(name).is_some())
+    {
+        return true;
+    }
+    let term = var("TERM");
+    ["kitty", "alacritty", "foot", "wezterm"].iter().any(|t| term.contains(t))
+}
+
+/// Render markdown to screen lines of at most `width` columns. `lead`
+/// (e.g. "Claude: ") starts the first paragraph, or gets its own line when
+/// the text opens with another block.
+pub(super) fn render(text: &str, lead: &str, width: usize, caps: Caps, theme: &Theme) -> Vec<Vec<Span>> {
+    let source: Vec<String> = text
+        .replace('\t', "    ")
+        .split('\n')
+        .map(|l| l.strip_suffix('\r').unwrap_or(l).to_string())
+        .collect();
+    let mut refs = HashMap::new();
+    let (blocks, _) = parse_blocks(&source, &mut refs);
+    let ctx = Ctx {
+        caps,
+        theme,
+        refs: &refs,
+    };
+
+    let mut lines = Vec::new();
+    let lead_inline = matches!(blocks.first(), Some(Block::Paragraph(_)));
+    if !lead_inline && !lead.is_empty() {
+        lines.push(vec![Span::plain(lead.trim_end().to_string())]);
+    }
+    for (i, block) in blocks.iter().enumerate() {
+        if i > 0 {
+            lines.push(vec![Span::plain(String::new())]);
+        }
+        match block {
+            Block::Paragraph(text) if i == 0 && lead_inline => {
+                let mut spans = vec![Span::plain(lead.to_string())];
+                inline(text, &Style::default(), &ctx, &mut spans);
+                lines.extend(wrap_words(&spans, width));
+            }
+            _ => lines.extend(render_block(block, width, 0, &ctx)),
+        }
+    }
+    if lines.is_empty() {
+        lines.push(vec![Span::plain(lead.trim_end().to_string())]);
+    }
+    lines
+}
+
+// ─── Blocks ─────────────────────────────────────────────────────────────────
+
+enum Block {
+    /// Inline text; hard line breaks are `\n`
+    Paragraph(String),
+    Heading(usize, String),
+    Code {
+        tag: String,
+        lines: Vec<String>,
+    },
+    Quote(Vec<Block>),
+    List {
+        /// Number of the first item of an ordered list
+        start: Option<u64>,
+        items: Vec<Vec<Block>>,
+        /// No blank lines between items
+        tight: bool,
+    },
+    Table {
+        aligns: Vec<Align>,
+        header: Vec<String>,
+        rows: Vec<Vec<String>>,
+    },
+    Rule,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// Parse lines (with container prefixes already stripped) into blocks.
+/// Link reference definitions go to `refs`. Also returns whether a blank
+/// line separated any two blocks, which makes a list item loose.
+fn parse_blocks(lines: &[String], refs: &mut HashMap<String, String>) -> (Vec<Block>, bool) {
+    let mut blocks = Vec::new();
+    let mut gap = false;
+    let mut saw_blank = false;
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i].as_str();
+        if line.trim().is_empty() {
+            saw_blank = true;
+            i += 1;
+            continue;
+        }
+        let indent = indent_of(line);
+
+        let block = if indent >= 4 {
+            // Indented code runs until a less indented line
+            let mut code = Vec::new();
+            while i < lines.len() && (lines[i].trim().is_empty() || indent_of(&lines[i]) >= 4) {
+                code.push(strip_indent(&lines[i], 4));
+                i += 1;
+            }
+            while code.last().is_some_and(|l| l.trim().is_empty()) {
+                code.pop();
+            }
+            Block::Code {
+                tag: String::new(),
+                lines: code,
+            }
+        } else if let Some((fence, len, info)) = fence_open(line) {
+            let tag = info.split_whitespace().next().unwrap_or("").to_string();
+            let mut code = Vec::new();
+            i += 1;
+            while i < lines.len() && !is_fence_close(&lines[i], fence, len) {
+                code.push(strip_indent(&lines[i], indent));
+                i += 1;
+            }
+            // Skip the closing fence; a block still being streamed has none
+            i += 1;
+            Block::Code { tag, lines: code }
+        } else if let Some((level, text)) = atx_heading(line) {
+            i += 1;
+            Block::Heading(level, text)
+        } else if is_rule(line) {
+            i += 1;
+            Block::Rule
+        } else if quote_content(line).is_some() {
+            let mut inner: Vec<String> = Vec::new();
+            while i < lines.len() {
+                match quote_content(&lines[i]) {
+                    Some(rest) => inner.push(rest),
+                    // Lazy continuation of a quoted paragraph
+                    None if !lines[i].trim().is_empty()
+                        && !starts_block(&lines[i])
+                        && inner.last().is_some_and(|l| !l.trim().is_empty()) =>
+                    {
+                        inner.push(lines[i].clone())
+                    }
+                    None => break,
+                }
+                i += 1;
+            }
+            Block::Quote(parse_blocks(&inner, refs).0)
+        } else if let Some(marker) = list_marker(line) {
+            let (list, next) = parse_list(lines, i, marker, refs);
+            i = next;
+            list
+        } else if let Some(aligns) = table_at(lines, i) {
+            let header = split_row(&lines[i], aligns.len());
+            i += 2;
+            let mut rows = Vec::new();
+            while i < lines.len()
+                && !lines[i].trim().is_empty()
+                && lines[i].contains('|')
+                && !starts_block(&lines[i])
+            {
+                rows.push(split_row(&lines[i], aligns.len()));
+                i += 1;
+            }
+            Block::Table {
+                aligns,
+                header,
+                rows,
+            }
+        } else if let Some((label, url)) = link_definition(line) {
+            refs.entry(label).or_insert(url);
+            i += 1;
+            continue;
+        } else {
+            // Paragraph, or a setext heading when underlined
+            let mut text: Vec<&str> = Vec::new();
+            let mut level = None;
+            while i < lines.len() {
+                let l = lines[i].as_str();
+                if l.trim().is_empty() {
+                    break;
+                }
+                if !text.is_empty() {
+                    if let Some(setext) = setext_level(l) {
+                        level = Some(setext);
+                        i += 1;
+                        break;
+                    }
+                    if starts_block(l) || table_at(lines, i).is_some() {
+                        break;
+                    }
+                }
+                text.push(l.trim_start());
+                i += 1;
+            }
+            let text = join_lines(&text);
+            match level {
+                Some(level) => Block::Heading(level, text),
+                None => Block::Paragraph(text),
+            }
+        };
+        if saw_blank && !blocks.is_empty() {
+            gap = true;
+        }
+        saw_blank = false;
+        blocks.push(block);
+    }
+    (blocks, gap)
+}
+
+/// A list starting at `lines[start]`; returns it and the index after it
+fn parse_list(
+    lines: &[String],
+    start: usize,
+    first: Marker,
+    refs: &mut HashMap<String, String>,
+) -> (Block, usize) {
+    let mut items = Vec::new();
+    let mut tight = true;
+    let mut i = start;
+    while i < lines.len() {
+        let marker = match list_marker(&lines[i]) {
+            Some(m) if m.kind == first.kind => m,
+            _ => break,
+        };
+        let line = &lines[i];
+        let mut item = vec![line.get(marker.content..).unwrap_or("").to_string()];
+        i += 1;
+        while i < lines.len() {
+            let l = &lines[i];
+            if l.trim().is_empty() {
+                item.push(String::new());
+            } else if indent_of(l) >= marker.content {
+                item.push(strip_indent(l, marker.content));
+            } else if item.last().is_some_and(|p| !p.trim().is_empty())
+                && !starts_block(l)
+                && list_marker(l).is_none()
+            {
+                // Lazy continuation of the item's paragraph
+                item.push(l.trim_start().to_string());
+            } else {
+                break;
+            }
+            i += 1;
+        }
+        let mut trailing_blank = false;
+        while item.last().is_some_and(|l| l.trim().is_empty()) {
+            item.pop();
+            trailing_blank = true;
+        }
+        let next_is_item = lines
+            .get(i)
+            .and_then(|l| list_marker(l))
+            .is_some_and(|m| m.kind == first.kind);
+        if trailing_blank && next_is_item {
+            tight = false;
+        }
+        let (blocks, gap) = parse_blocks(&item, refs);
+        if gap {
+            tight = false;
+        }
+        items.push(blocks);
+    }
+    // Give back blank lines swallowed after the last item
+    while i > start && lines[i - 1].trim().is_empty() {
+        i -= 1;
+    }
+    (
+        Block::List {
+            start: first.number,
+            items,
+            tight,
+        },
+        i,
+    )
+}
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+/// Remove up to `n` leading spaces
+fn strip_indent(line: &str, n: usize) -> String {
+    line[indent_of(line).min(n)..].to_string()
+}
+
+/// Paragraph lines as one text: soft breaks become spaces, lines ending in
+/// two spaces or a backslash are hard breaks
+fn join_lines(lines: &[&str]) -> String {
+    let mut text = String::new();
+    for (i, line) in lines.iter().enumerate() {
+        if i + 1 == lines.len() {
+            text.push_str(line.trim_end());
+        } else if line.ends_with("  ") || line.ends_with('\\') {
+            text.push_str(line.trim_end().trim_end_matches('\\'));
+            text.push('\n');
+        } else {
+            text.push_str(line.trim_end());
+            text.push(' ');
+        }
+    }
+    text
+}
+
+/// Whether a line starts a block that interrupts a paragraph
+fn starts_block(line: &str) -> bool {
+    indent_of(line) < 4
+        && (fence_open(line).is_some()
+            || atx_heading(line).is_some()
+            || is_rule(line)
+            || quote_content(line).is_some()
+            || list_marker(line).is_some_and(|m| !m.empty && m.number.unwrap_or(1) == 1))
+}
+
+/// Opening code fence: the fence character, its length and the info string
+fn fence_open(line: &str) -> Option<(char, usize, &str)> {
+    if indent_of(line) > 3 {
+        return None;
+    }
+    let trimmed = line.trim_start();
+    let fence = trimmed.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = trimmed.chars().take_while(|c| *c == fence).count();
+    let info = trimmed[len..].trim();
+    if len < 3 || (fence == '`' && info.contains('`')) {
+        return None;
+    }
+    Some((fence, len, info))
+}
+
+fn is_fence_close(line: &str, fence: char, len: usize) -> bool {
+    let trimmed = line.trim_start();
+    let run = trimmed.chars().take_while(|c| *c == fence).count();
+    indent_of(line) <= 3 && run >= len && trimmed[run..].trim().is_empty()
+}
+
+/// `## Heading ##`: the level and the text
+fn atx_heading(line: &str) -> Option<(usize, String)> {
+    if indent_of(line) > 3 {
+        return None;
+    }
+    let trimmed = line.trim_start();
+    let level = trimmed.chars().take_while(|c| *c == '#').count();
+    let rest = &trimmed[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with(' ')) {
+        return None;
+    }
+    let mut text = rest.trim();
+    // Optional closing sequence
+    let without = text.trim_end_matches('#');
+    if without.is_empty() || without.ends_with(' ') {
+        text = without.trim_end();
+    }
+    Some((level, text.to_string()))
+}
+
+/// `===` under a paragraph makes it a level 1 heading, `---` level 2
+fn setext_level(line: &str) -> Option<usize> {
+    let trimmed = line.trim();
+    if indent_of(line) > 3 || trimmed.is_empty() {
+        None
+    } else if trimmed.chars().all(|c| c == '=') {
+        Some(1)
+    } else if trimmed.chars().all(|c| c == '-') {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// `---`, `***` or `___` (spaces allowed between)
+fn is_rule(line: &str) -> bool {
+    if indent_of(line) > 3 {
+        return false;
+    }
+    let chars: Vec<char> = line.chars().filter(|c| *c != ' ').collect();
+    chars.len() >= 3
+        && matches!(chars[0], '-' | '*' | '_')
+        && chars.iter().all(|c| *c == chars[0])
+}
+
+/// The rest of a `>` line, without the marker and one following space
+fn quote_content(line: &str) -> Option<String> {
+    if indent_of(line) > 3 {
+        return None;
+    }
+    let rest = line.trim_start().strip_prefix('>')?;
+    Some(rest.strip_prefix(' ').unwrap_or(rest).to_string())
+}
+
+#[derive(Clone, Copy)]
+struct Marker {
+    /// The bullet character, or the delimiter after an ordered item's number;
+    /// a different one starts a new list
+    kind: char,
+    number: Option<u64>,
+    /// Column where the item's content starts
+    content: usize,
+    /// Nothing after the marker
+    empty: bool,
+}
+
+/// `- item`, `* item`, `+ item`, `1. item` or `1) item`
+fn list_marker(line: &str) -> Option<Marker> {
+    let indent = indent_of(line);
+    let rest = &line[indent..];
+    let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+    let (kind, number, marker_len) = match rest.chars().next()? {
+        c @ ('-' | '*' | '+') => (c, None, 1),
+        _ if (1..=9).contains(&digits) => {
+            let delim = rest[digits..].chars().next().filter(|c| *c == '.' || *c == ')')?;
+            (delim, rest[..digits].parse().ok(), digits + 1)
+        }
+        _ => return None,
+    };
+    let after = &rest[marker_len..];
+    if after.trim().is_empty() {
+        return Some(Marker {
+            kind,
+            number,
+            content: indent + marker_len + 1,
+            empty: true,
+        });
+    }
+    let spaces = indent_of(after);
+    if spaces == 0 {
+        return None;
+    }
+    // Five or more spaces start indented code in the item; count one
+    let spaces = if spaces > 4 { 1 } else { spaces };
+    Some(Marker {
+        kind,
+        number,
+        content: indent + marker_len + spaces,
+        empty: false,
+    })
+}
+
+/// Column alignments when `lines[i]` is a table header row followed by
+/// its delimiter row
+fn table_at(lines: &[String], i: usize) -> Option<Vec<Align>> {
+    let header = lines.get(i)?;
+    let delimiter = lines.get(i + 1)?;
+    if !header.contains('|') || !delimiter.contains('|') || indent_of(header) > 3 {
+        return None;
+    }
+    let aligns = split_row(delimiter, 0)
+        .iter()
+        .map(|cell| {
+            let dashes = cell.trim_start_matches(':').trim_end_matches(':');
+            if dashes.is_empty() || !dashes.chars().all(|c| c == '-') {
+                return None;
+            }
+            Some(match (cell.starts_with(':'), cell.ends_with(':')) {
+                (true, true) => Align::Center,
+                (false, true) => Align::Right,
+                _ => Align::Left,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    (split_row(header, 0).len() == aligns.len()).then_some(aligns)
+}
+
+/// The cells of a table row, padded or cut to `columns` (0 keeps them all)
+fn split_row(line: &str, columns: usize) -> Vec<String> {
+    let mut row = line.trim();
+    row = row.strip_prefix('|').unwrap_or(row);
+    if row.ends_with('|') && !row.ends_with("\\|") {
+        row = &row[..row.len() - 1];
+    }
+    let mut cells = vec![String::new()];
+    let mut chars = row.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'|') => {
+                cells.last_mut().unwrap().push('|');
+                chars.next();
+            }
+            '|' => cells.push(String::new()),
+            _ => cells.last_mut().unwrap().push(c),
+        }
+    }
+    let mut cells: Vec<String> = cells.iter().map(|c| c.trim().to_string()).collect();
+    if columns > 0 {
+        cells.resize(columns, String::new());
+    }
+    cells
+}
+
+/// `[label]: url "title"`: the lowercased label and the url
+fn link_definition(line: &str) -> Option<(String, String)> {
+    if indent_of(line) > 3 {
+        return None;
+    }
+    let rest = line.trim_start().strip_prefix('[')?;
+    let (label, rest) = rest.split_once("]:")?;
+    let url = rest.split_whitespace().next()?;
+    let url = url.strip_prefix('<').and_then(|u| u.strip_suffix('>')).unwrap_or(url);
+    if label.trim().is_empty() || label.contains('[') {
+        return None;
+    }
+    Some((normalize_label(label), url.to_string()))
+}
+
+fn normalize_label(label: &str) -> String {
+    label.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+// ─── Layout ─────────────────────────────────────────────────────────────────
+
+struct Ctx<'a> {
+    caps: Caps,
+    theme: &'a Theme,
+    /// Link reference definitions by normalized label
+    refs: &'a HashMap<String, String>,
+}
+
+fn render_blocks(blocks: &[Block], width: usize, tight: bool, depth: usize, ctx: &Ctx) -> Vec<Vec<Span>> {
+    let mut lines = Vec::new();
+    for (i, block) in blocks.iter().enumerate() {
+        if i > 0 && !tight {
+            lines.push(vec![Span::plain(String::new())]);
+        }
+        lines.extend(render_block(block, width, depth, ctx));
+    }
+    lines
+}
+
+/// One block as screen lines; `depth` is the list nesting level
+fn render_block(block: &Block, width: usize, depth: usize, ctx: &Ctx) -> Vec<Vec<Span>> {
+    let width = width.max(1);
+    match block {
+        Block::Paragraph(text) => {
+            let mut spans = Vec::new();
+            inline(text, &Style::default(), ctx, &mut spans);
+            wrap_words(&spans, width)
+        }
+        Block::Heading(level, text) => {
+            let style = Style {
+                fg: ctx.theme.heading.fg,
+                bold: ctx.theme.heading.bold,
+                underline: *level == 1,
+                ..Style::default()
+            };
+            let mut spans = Vec::new();
+            inline(text, &style, ctx, &mut spans);
+            wrap_words(&spans, width)
+        }
+        Block::Code { tag, lines } => code_lines(tag, lines, width, ctx),
+        Block::Quote(blocks) => render_blocks(blocks, width.saturating_sub(2), false, depth, ctx)
+            .into_iter()
+            .map(|line| {
+                let mut spans = vec![Span {
+                    text: "\u{2502} ".to_string(),
+                    fg: ctx.theme.rule.fg,
+                    ..Span::default()
+                }];
+                spans.extend(line);
+                spans
+            })
+            .collect(),
+        Block::List { start, items, tight } => {
+            let markers: Vec<String> = (0..items.len() as u64)
+                .map(|i| match start {
+                    Some(start) => format!("{}.", start + i),
+                    None => ["\u{2022}", "\u{25e6}", "\u{25aa}"][depth % 3].to_string(),
+                })
+                .collect();
+            let marker_width = markers.iter().map(|m| UnicodeWidthStr::width(m.as_str())).max().unwrap_or(1);
+            let mut lines = Vec::new();
+            for (i, (item, marker)) in items.iter().zip(&markers).enumerate() {
+                if i > 0 && !tight {
+                    lines.push(vec![Span::plain(String::new())]);
+                }
+                let item_width = width.saturating_sub(marker_width + 1);
+                let mut item_lines = render_blocks(item, item_width, *tight, depth + 1, ctx);
+                if item_lines.is_empty() {
+                    item_lines.push(Vec::new());
+                }
+                for (j, line) in item_lines.into_iter().enumerate() {
+                    let mut spans = vec![if j == 0 {
+                        Span {
+                            text: format!("{:>w$} ", marker, w = marker_width),
+                            fg: ctx.theme.list_marker.fg,
+                            ..Span::default()
+                        }
+                    } else {
+                        Span::plain(" ".repeat(marker_width + 1))
+                    }];
+                    spans.extend(line);
+                    lines.push(spans);
+                }
+            }
+            lines
+        }
+        Block::Table {
+            aligns,
+            header,
+            rows,
+        } => table_lines(aligns, header, rows, width, ctx),
+        Block::Rule => vec![vec![Span {
+            text: "\u{2500}".repeat(width),
+            fg: ctx.theme.rule.fg,
+            ..Span::default()
+        }]],
+    }
+}
+
+/// A code block on the code background, highlighted when the tag names a
+/// known language, under a line with the tag
+fn code_lines(tag: &str, code: &[String], width: usize, ctx: &Ctx) -> Vec<Vec<Span>> {
+    let bg = highlight::code_background(ctx.theme, ctx.caps.colors);
+    let lang = Lang::from_tag(tag);
+    let mut state = HighlightState::default();
+    let mut lines = Vec::new();
+    if !tag.is_empty() {
+        lines.push(vec![Span {
+            text: tag.to_string(),
+            fg: ctx.theme.code_tag.fg,
+            ..Span::default()
+        }]);
+    }
+    for line in code {
+        let mut spans = vec![Span {
+            text: "  ".to_string(),
+            bg,
+            ..Span::default()
+        }];
+        match lang {
+            Some(lang) => spans.extend(highlighted_spans(lang, line, &mut state, ctx.theme, bg)),
+            None => spans.push(Span {
+                text: line.clone(),
+                fg: ctx.theme.code.fg,
+                bg,
+                ..Span::default()
+            }),
+        }
+        lines.extend(wrap_spans(&spans, width));
+    }
+    lines
+}
+
+/// A GFM table with box-drawing borders. Columns get their natural width;
+/// when the table is too wide the widest column gives way until it fits,
+/// and cells wrap within their column.
+fn table_lines(
+    aligns: &[Align],
+    header: &[String],
+    rows: &[Vec<String>],
+    width: usize,
+    ctx: &Ctx,
+) -> Vec<Vec<Span>> {
+    let cell_spans = |text: &str, bold: bool| {
+        let mut spans = Vec::new();
+        let style = Style {
+            bold,
+            ..Style::default()
+        };
+        inline(text, &style, ctx, &mut spans);
+        spans
+    };
+    let header: Vec<Vec<Span>> = header.iter().map(|c| cell_spans(c, true)).collect();
+    let rows: Vec<Vec<Vec<Span>>> = rows
+        .iter()
+        .map(|row| row.iter().map(|c| cell_spans(c, false)).collect())
+        .collect();
+
+    let mut widths = vec![1; aligns.len()];
+    for row in std::iter::once(&header).chain(&rows) {
+        for (w, cell) in widths.iter_mut().zip(row) {
+            let text: String = cell.iter().map(|s| s.text.as_str()).collect();
+            let natural = text.split('\n').map(UnicodeWidthStr::width).max().unwrap_or(0);
+            *w = (*w).max(natural);
+        }
+    }
+    // Each column adds a border and a space on each side
+    let room = width.saturating_sub(3 * aligns.len() + 1);
+    while widths.iter().sum::<usize>() > room {
+        let (widest, w) = widths
+            .iter()
+            .copied()
+            .enumerate()
+            .max_by_key(|(_, w)| *w)
+            .unwrap_or((0, 0));
+        if w <= MIN_COLUMN {
+            break;
+        }
+        widths[widest] -= 1;
+    }
+
+    let border = |left: &str, mid: &str, right: &str| {
+        let segments: Vec<String> = widths.iter().map(|w| "\u{2500}".repeat(w + 2)).collect();
+        vec![Span {
+            text: format!("{}{}{}", left, segments.join(mid), right),
+            fg: ctx.theme.rule.fg,
+            ..Span::default()
+        }]
+    };
+    let bar = |text: &str| Span {
+        text: text.to_string(),
+        fg: ctx.theme.rule.fg,
+        ..Span::default()
+    };
+    let row_lines = |cells: &[Vec<Span>]| {
+        let wrapped: Vec<Vec<Vec<Span>>> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, w)| wrap_words(cell, *w))
+            .collect();
+        let height = wrapped.iter().map(|c| c.len()).max().unwrap_or(1);
+        (0..height)
+            .map(|r| {
+                let mut spans = vec![bar("\u{2502} ")];
+                for (c, (cell, w)) in wrapped.iter().zip(&widths).enumerate() {
+                    if c > 0 {
+                        spans.push(bar(" \u{2502} "));
+                    }
+                    let content = cell.get(r).cloned().unwrap_or_default();
+                    let used: usize = content.iter().map(|s| UnicodeWidthStr::width(s.text.as_str())).sum();
+                    let extra = w.saturating_sub(used);
+                    let left = match aligns[c] {
+                        Align::Left => 0,
+                        Align::Center => extra / 2,
+                        Align::Right => extra,
+                    };
+                    spans.push(Span::plain(" ".repeat(left)));
+                    spans.extend(content);
+                    spans.push(Span::plain(" ".repeat(extra - left)));
+                }
+                spans.push(bar(" \u{2502}"));
+                spans
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let mut lines = vec![border("\u{250c}", "\u{252c}", "\u{2510}")];
+    lines.extend(row_lines(&header));
+    lines.push(border("\u{251c}", "\u{253c}", "\u{2524}"));
+    for row in &rows {
+        lines.extend(row_lines(row));
+    }
+    lines.push(border("\u{2514}", "\u{2534}", "\u{2518}"));
+    lines
+}
+
+/// Word-wrap styled text at `width` columns. Spaces at a wrap are dropped,
+/// words longer than a line are split, and `\n` forces a break.
+fn wrap_words(spans: &[Span], width: usize) -> Vec<Vec<Span>> {
+    let width = width.max(1);
+    let mut lines: Vec<Vec<Span>> = vec![Vec::new()];
+    let mut col = 0;
+    // Spaces waiting for the next word (dropped if it wraps)
+    let mut pending: Vec<(char, usize)> = Vec::new();
+    let mut word: Vec<(char, usize)> = Vec::new();
+
+    fn push_char(line: &mut Vec<Span>, spans: &[Span], ch: char, source: usize, last: &mut Option<usize>) {
+        match line.last_mut() {
+            Some(span) if *last == Some(source) => span.text.push(ch),
+            _ => line.push(Span {
+                text: ch.to_string(),
+                ..spans[source].clone()
+            }),
+        }
+        *last = Some(source);
+    }
+
+    let mut last_source = None;
+    let chars = spans
+        .iter()
+        .enumerate()
+        .flat_map(|(i, span)| span.text.chars().map(move |c| (c, i)))
+        .chain(std::iter::once(('\n', usize::MAX)));
+    for (ch, source) in chars {
+        if ch != ' ' && ch != '\n' {
+            word.push((ch, source));
+            continue;
+        }
+        if !word.is_empty() {
+            let word_width: usize = word.iter().map(|(c, _)| c.width().unwrap_or(0)).sum();
+            if col > 0 && col + pending.len() + word_width > width {
+                lines.push(Vec::new());
+                last_source = None;
+                col = 0;
+            } else {
+                for (c, s) in pending.iter() {
+                    push_char(lines.last_mut().unwrap(), spans, *c, *s, &mut last_source);
+                }
+                col += pending.len();
+            }
+            pending.clear();
+            for (c, s) in word.drain(..) {
+                let w = c.width().unwrap_or(0);
+                if col + w > width && col > 0 {
+                    lines.push(Vec::new());
+                    last_source = None;
+                    col = 0;
+                }
+                push_char(lines.last_mut().unwrap(), spans, c, s, &mut last_source);
+                col += w;
+            }
+        }
+        if ch == '\n' {
+            if source != usize::MAX {
+                lines.push(Vec::new());
+                last_source = None;
+                col = 0;
+            }
+            pending.clear();
+        } else if col > 0 {
+            pending.push((ch, source));
+        }
+    }
+    lines
+}
+
+// ─── Inline ─────────────────────────────────────────────────────────────────
+
+#[derive(Clone, Default)]
+struct Style {
+    fg: Option<Color>,
+    bold: bool,
+    italic: bool,
+    strike: bool,
+    underline: bool,
+    code: bool,
+    link: Option<String>,
+}
+
+fn styled(text: String, style: &Style, ctx: &Ctx) -> Span {
+    // The URL goes into an escape sequence; keep control characters out
+    let link = style
+        .link
+        .clone()
+        .filter(|url| ctx.caps.hyperlinks && !url.chars().any(|c| c.is_control()));
+    Span {
+        text,
+        fg: if style.code { ctx.theme.inline_code.fg } else { style.fg },
+        bg: if style.code {
+            highlight::code_background(ctx.theme, ctx.caps.colors)
+        } else {
+            None
+        },
+        bold: style.bold,
+        dim: false,
+        italic: style.italic,
+        underline: style.underline,
+        strike: style.strike,
+        link,
+    }
+}
+
+fn flush(buf: &mut String, style: &Style, ctx: &Ctx, out: &mut Vec<Span>) {
+    if !buf.is_empty() {
+        out.push(styled(std::mem::take(buf), style, ctx));
+    }
+}
+
+/// Parse inline markup in `text`, appending spans styled on top of `style`
+fn inline(text: &str, style: &Style, ctx: &Ctx, out: &mut Vec<Span>) {
+    let chars: Vec<char> = text.chars().collect();
+    let mut buf = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let rest = &chars[i..];
+
+        if c == '\\' && rest.get(1).is_some_and(|n| n.is_ascii_punctuation()) {
+            buf.push(rest[1]);
+            i += 2;
+            continue;
+        }
+
+        if c == '`' {
+            let n = run_length(&chars, i);
+            match find_run(&chars, i + n, '`', n) {
+                Some(close) => {
+                    flush(&mut buf, style, ctx, out);
+                    let code: String = chars[i + n..close].iter().collect();
+                    let code = code.replace('\n', " ");
+                    let code = match code.strip_prefix(' ').and_then(|c| c.strip_suffix(' ')) {
+                        Some(inner) if !inner.trim().is_empty() => inner.to_string(),
+                        _ => code,
+                    };
+                    let code_style = Style {
+                        code: true,
+                        ..style.clone()
+                    };
+                    out.push(styled(code, &code_style, ctx));
+                    i = close + n;
+                }
+                None => {
+                    buf.extend(&chars[i..i + n]);
+                    i += n;
+                }
+            }
+            continue;
+        }
+
+        if matches!(c, '*' | '_' | '~') {
+            let n = run_length(&chars, i);
+            if let Some((len, close)) = emphasis(&chars, i, n) {
+                flush(&mut buf, style, ctx, out);
+                let inner: String = chars[i + len..close].iter().collect();
+                let mut inner_style = style.clone();
+                match (c, len) {
+                    ('~', _) => inner_style.strike = true,
+                    (_, 1) => inner_style.italic = true,
+                    (_, 2) => inner_style.bold = true,
+                    _ => {
+                        inner_style.bold = true;
+                        inner_style.italic = true;
+                    }
+                }
+                inline(&inner, &inner_style, ctx, out);
+                i = close + len;
+            } else {
+                buf.extend(&chars[i..i + n]);
+                i += n;
+            }
+            continue;
+        }
+
+        let image = c == '!' && rest.get(1) == Some(&'[');
+        if c == '[' || image {
+            let open = if image { i + 1 } else { i };
+            if let Some((label_end, url, end)) = link_at(&chars, open, ctx) {
+                flush(&mut buf, style, ctx, out);
+                let label: String = chars[open + 1..label_end].iter().collect();
+                let link_style = Style {
+                    fg: ctx.theme.link.fg,
+                    underline: true,
+                    link: Some(url.clone()),
+                    ..style.clone()
+                };
+                if image {
+                    out.push(styled(format!("[image: {}]", label), &link_style, ctx));
+                } else {
+                    inline(&label, &link_style, ctx, out);
+                }
+                if !ctx.caps.hyperlinks && label != url {
+                    let url_style = Style {
+                        fg: ctx.theme.link_url.fg,
+                        ..Style::default()
+                    };
+                    out.push(styled(format!(" ({})", url), &url_style, ctx));
+                }
+                i = end;
+                continue;
+            }
+        }
+
+        // Autolinks: <https://...> and bare URLs
+        if let Some((url, end)) = autolink_at(&chars, i, style.link.is_some()) {
+            flush(&mut buf, style, ctx, out);
+            let link_style = Style {
+                fg: ctx.theme.link.fg,
+                underline: true,
+                link: Some(url.clone()),
+                ..style.clone()
+            };
+            out.push(styled(url, &link_style, ctx));
+            i = end;
+            continue;
+        }
+
+        if c == '<' {
+            let tag: String = rest.iter().take(6).collect::<String>().to_ascii_lowercase();
+            if let Some(len) = ["<br>", "<br/>", "<br />"].iter().find(|t| tag.starts_with(*t)).map(|t| t.len()) {
+                buf.push('\n');
+                i += len;
+                continue;
+            }
+        }
+
+        if c == '&' {
+            if let Some((decoded, len)) = entity_at(&chars, i) {
+                buf.push(decoded);
+                i += len;
+                continue;
+            }
+        }
+
+        buf.push(c);
+        i += 1;
+    }
+    flush(&mut buf, style, ctx, out);
+}
+
+fn run_length(chars: &[char], i: usize) -> usize {
+    chars[i..].iter().take_while(|c| **c == chars[i]).count()
+}
+
+/// Start of the next run of exactly `n` `ch` at or after `from`
+fn find_run(chars: &[char], from: usize, ch: char, n: usize) -> Option<usize> {
+    let mut j = from;
+    while j < chars.len() {
+        if chars[j] == ch {
+            let len = run_length(chars, j);
+            if len == n {
+                return Some(j);
+            }
+            j += len;
+        } else {
+            j += 1;
+        }
+    }
+    None
+}
+
+/// Emphasis opened by the delimiter run of `n` chars at `i`: the delimiter
+/// length used and where the closing run starts
+fn emphasis(chars: &[char], i: usize, n: usize) -> Option<(usize, usize)> {
+    let c = chars[i];
+    let len = match c {
+        // A single `~` is too common in paths to strike through
+        '~' if n == 2 => 2,
+        '*' | '_' => n.min(3),
+        _ => return None,
+    };
+    let before = i.checked_sub(1).map(|p| chars[p]);
+    let after = chars.get(i + n).copied();
+    // Left-flanking: not followed by whitespace; `_` not inside a word
+    if after.is_none_or(|a| a.is_whitespace())
+        || (c == '_' && before.is_some_and(|b| b.is_alphanumeric()))
+    {
+        return None;
+    }
+    let mut j = i + n;
+    while j < chars.len() {
+        match chars[j] {
+            '`' => {
+                // Delimiters inside code spans don't count
+                let run = run_length(chars, j);
+                j = find_run(chars, j + run, '`', run).map(|close| close + run).unwrap_or(j + run);
+            }
+            '\\' => j += 2,
+            d if d == c => {
+                let run = run_length(chars, j);
+                let next = chars.get(j + run);
+                if run == len
+                    && !chars[j - 1].is_whitespace()
+                    && !(c == '_' && next.is_some_and(|n| n.is_alphanumeric()))
+                {
+                    return Some((len, j));
+                }
+                j += run;
+            }
+            _ => j += 1,
+        }
+    }
+    None
+}
+
+/// `[label](url "title")`, `[label][ref]`, `[label][]` or `[ref]` at
+/// `open`: the index of the closing `]`, the url and the index after
+fn link_at(chars: &[char], open: usize, ctx: &Ctx) -> Option<(usize, String, usize)> {
+    let mut depth = 0;
+    let mut j = open;
+    let label_end = loop {
+        match chars.get(j)? {
+            '\\' => j += 1,
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    break j;
+                }
+            }
+            _ => {}
+        }
+        j += 1;
+    };
+    let label: String = chars[open + 1..label_end].iter().collect();
+
+    match chars.get(label_end + 1) {
+        Some('(') => {
+            let mut depth = 0;
+            let mut k = label_end + 1;
+            let close = loop {
+                match chars.get(k)? {
+                    '\\' => k += 1,
+                    '(' => depth += 1,
+                    ')' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            break k;
+                        }
+                    }
+                    _ => {}
+                }
+                k += 1;
+            };
+            let inside: String = chars[label_end + 2..close].iter().collect();
+            let inside = inside.trim();
+            let url = match inside.strip_prefix('<') {
+                Some(rest) => rest.split('>').next().unwrap_or(""),
+                None => inside.split_whitespace().next().unwrap_or(""),
+            };
+            Some((label_end, url.to_string(), close + 1))
+        }
+        Some('[') => {
+            let close = (label_end + 2..chars.len()).find(|k| chars[*k] == ']')?;
+            let reference: String = chars[label_end + 2..close].iter().collect();
+            let key = if reference.trim().is_empty() { &label } else { &reference };
+            let url = ctx.refs.get(&normalize_label(key))?;
+            Some((label_end, url.clone(), close + 1))
+        }
+        _ => {
+            let url = ctx.refs.get(&normalize_label(&label))?;
+            Some((label_end, url.clone(), label_end + 1))
+        }
+    }
+}
+
+/// `<scheme:...>` or a bare `http(s)://` URL at `i` (not inside link text)
+fn autolink_at(chars: &[char], i: usize, in_link: bool) -> Option<(String, usize)> {
+    let rest: String = chars[i..].iter().take(9).collect();
+    if chars[i] == '<' {
+        let close = (i + 1..chars.len()).find(|k| chars[*k] == '>')?;
+        let url: String = chars[i + 1..close].iter().collect();
+        let scheme = url.split(':').next().unwrap_or("");
+        let is_url = url.contains(':')
+            && !scheme.is_empty()
+            && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+.-".contains(c))
+            && !url.chars().any(|c| c.is_whitespace() || c == '<');
+        return is_url.then_some((url, close + 1));
+    }
+    if in_link
+        || !(rest.starts_with("https://") || rest.starts_with("http://"))
+        || i.checked_sub(1).is_some_and(|p| chars[p].is_alphanumeric())
+    {
+        return None;
+    }
+    let mut end = (i..chars.len())
+        .find(|k| chars[*k].is_whitespace() || chars[*k] == '<')
+        .unwrap_or(chars.len());
+    // Trailing punctuation ends the sentence, not the URL
+    while end > i {
+        let last = chars[end - 1];
+        let unbalanced = last == ')'
+            && chars[i..end].iter().filter(|c| **c == '(').count()
+                < chars[i..end].iter().filter(|c| **c == ')').count();
+        if ".,:;!?'\"*_~".contains(last) || unbalanced {
+            end -= 1;
+        } else {
+            break;
+        }
+    }
+    Some((chars[i..end].iter().collect(), end))
+}
+
+/// A character reference (`&amp;`, `&#39;`, `&#x27;`) at `i`
+fn entity_at(chars: &[char], i: usize) -> Option<(char, usize)> {
+    let semi = (i + 1..chars.len().min(i + 10)).find(|k| chars[*k] == ';')?;
+    let name: String = chars[i + 1..semi].iter().collect();
+    let decoded = match name.as_str() {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        _ => {
+            let code = match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => name.strip_prefix('#')?.parse().ok()?,
+            };
+            char::from_u32(code).filter(|c| !c.is_control())?
+        }
+    };
+    Some((decoded, semi - i + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn caps(hyperlinks: bool) -> Caps {
+        Caps {
+            colors: ColorDepth::Ansi16,
+            hyperlinks,
+        }
+    }
+
+    fn spans(text: &str, width: usize, hyperlinks: bool) -> Vec<Vec<Span>> {
+        render(text, "", width, caps(hyperlinks), &Theme::dark())
+    }
+
+    /// Screen lines as plain text, trailing padding trimmed
+    fn lines(text: &str, width: usize) -> Vec<String> {
+        spans(text, width, false)
+            .iter()
+            .map(|line| line.iter().map(|s| s.text.as_str()).collect::<String>().trim_end().to_string())
+            .collect()
+    }
+
+    fn span_with<'a>(lines: &'a [Vec<Span>], text: &str) -> &'a Span {
+        lines
+            .iter()
+            .flatten()
+            .find(|s| s.text == text)
+            .unwrap_or_else(|| panic!("no span {:?}", text))
+    }
+
+    #[test]
+    fn hyperlinks_from_env() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| vars.iter().find(|(k, _)| *k == name).map(|(_, v)| v.to_string())
+        };
+        assert!(!hyperlinks_from(env(&[])));
+        assert!(!hyperlinks_from(env(&[("TERM", "xterm-256color")])));
+        assert!(hyperlinks_from(env(&[("TERM_PROGRAM", "WezTerm")])));
+        assert!(hyperlinks_from(env(&[("TERM", "xterm-kitty")])));
+        assert!(hyperlinks_from(env(&[("WT_SESSION", "")])));
+        assert!(hyperlinks_from(env(&[("VTE_VERSION", "6003")])));
+        assert!(!hyperlinks_from(env(&[("VTE_VERSION", "4601")])));
+        assert!(!hyperlinks_from(env(&[("VTE_VERSION", "new")])));
+        assert!(hyperlinks_from(env(&[("FORCE_HYPERLINK", "1")])));
+        assert!(!hyperlinks_from(env(&[("FORCE_HYPERLINK", "0"), ("TERM_PROGRAM", "iTerm.app")])));
+    }
+
+    /// A border of a two-column table whose first column is "Name"
+    fn border(left: &str, mid: &str, right: &str, second: usize) -> String {
+        let bar = |n: usize| "\u{2500}".repeat(n);
+        format!("{}{}{}{}{}", left, bar(6), mid, bar(second), right)
+    }
+
+    #[test]
+    fn tables_fit_the_width() {
+        let table = "| Name | Description |\n|:--|--:|\n| a | one two three four five |";
+        let wide = lines(table, 80);
+        let rule = |l: &str, m: &str, r: &str| border(l, m, r, 25);
+        assert_eq!(
+            wide,
+            [
+                rule("\u{250c}", "\u{252c}", "\u{2510}"),
+                "\u{2502} Name \u{2502}             Description \u{2502}".to_string(),
+                rule("\u{251c}", "\u{253c}", "\u{2524}"),
+                "\u{2502} a    \u{2502} one two three four five \u{2502}".to_string(),
+                rule("\u{2514}", "\u{2534}", "\u{2518}"),
+            ]
+        );
+        // Narrow: the wide column gives way and its cells wrap
+        let narrow = lines(table, 20);
+        assert_eq!(
+            narrow[1..narrow.len() - 1],
+            [
+                "\u{2502} Name \u{2502} Descripti \u{2502}",
+                "\u{2502}      \u{2502}        on \u{2502}",
+                &border("\u{251c}", "\u{253c}", "\u{2524}", 11),
+                "\u{2502} a    \u{2502}   one two \u{2502}",
+                "\u{2502}      \u{2502}     three \u{2502}",
+                "\u{2502}      \u{2502} four five \u{2502}",
+            ]
+        );
+        assert!(narrow.iter().all(|l| UnicodeWidthStr::width(l.as_str()) == 20));
+    }
+
+    #[test]
+    fn tables_too_narrow_to_fit_keep_a_minimum_column() {
+        let table = "| aaaa | bbbb | cccc |\n|---|---|---|\n| 1 | 2 | 3 |";
+        let narrow = lines(table, 8);
+        // Three columns of MIN_COLUMN plus borders
+        assert!(narrow.iter().all(|l| UnicodeWidthStr::width(l.as_str()) == 3 * (MIN_COLUMN + 3) + 1));
+    }
+
+    #[test]
+    fn nested_lists() {
+        let text = "- one\n  - inner\n    - deepest\n- two";
+        assert_eq!(
+            lines(text, 40),
+            ["\u{2022} one", "  \u{25e6} inner", "    \u{25aa} deepest", "\u{2022} two"]
+        );
+    }
+
+    #[test]
+    fn numbered_lists_keep_their_start_and_align_markers() {
+        let text = "8. eight\n9. nine\n10. ten";
+        assert_eq!(lines(text, 40), [" 8. eight", " 9. nine", "10. ten"]);
+        // A long item wraps under its text, not under the marker
+        let wrapped = lines("1. alpha beta gamma", 12);
+        assert_eq!(wrapped, ["1. alpha", "   beta", "   gamma"]);
+    }
+
+    #[test]
+    fn loose_lists_are_spaced() {
+        assert_eq!(lines("- a\n\n- b", 40), ["\u{2022} a", "", "\u{2022} b"]);
+    }
+
+    #[test]
+    fn links_without_hyperlinks_print_the_url() {
+        assert_eq!(
+            lines("see [the docs](https://example.com/docs) now", 80),
+            ["see the docs (https://example.com/docs) now"]
+        );
+        // Bare URLs aren't repeated
+        assert_eq!(lines("at https://example.com.", 80), ["at https://example.com."]);
+        let out = spans("[x](https://example.com)", 80, false);
+        assert_eq!(span_with(&out, "x").link, None);
+    }
+
+    #[test]
+    fn links_with_hyperlinks_carry_the_url() {
+        let out = spans("see [the docs](https://example.com/docs) and <https://a.example>", 80, true);
+        let text: String = out[0].iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(text, "see the docs and https://a.example");
+        assert_eq!(span_with(&out, "the docs").link.as_deref(), Some("https://example.com/docs"));
+        assert_eq!(span_with(&out, "https://a.example").link.as_deref(), Some("https://a.example"));
+        // Reference links resolve through their definition
+        let out = spans("[docs][d]\n\n[d]: https://example.com", 80, true);
+        assert_eq!(span_with(&out, "docs").link.as_deref(), Some("https://example.com"));
+    }
+
+    #[test]
+    fn urls_with_control_characters_are_not_linked() {
+        let out = spans("[x](<https://e.example/\u{7}>)", 80, true);
+        assert_eq!(span_with(&out, "x").link, None);
+    }
+
+    #[test]
+    fn unclosed_emphasis_and_backticks_stay_literal() {
+        assert_eq!(lines("a *b and `c", 80), ["a *b and `c"]);
+        assert_eq!(lines("**bold", 80), ["**bold"]);
+        assert_eq!(lines("``code` x", 80), ["``code` x"]);
+        let out = spans("*it* and **b** and `code`", 80, false);
+        assert!(span_with(&out, "it").italic);
+        assert!(span_with(&out, "b").bold);
+        assert_eq!(span_with(&out, "code").fg, Theme::dark().inline_code.fg);
+    }
+
+    #[test]
+    fn unclosed_fences_run_to_the_end() {
+        assert_eq!(lines("```rust\nlet a = 1;\n\nlet b = 2;", 80), ["rust", "  let a = 1;", "", "  let b = 2;"]);
+    }
+
+    #[test]
+    fn lead_starts_the_first_paragraph() {
+        assert_eq!(render_lead("hello", "Claude: "), ["Claude: hello"]);
+        assert_eq!(render_lead("# Title", "Claude: "), ["Claude:", "Title"]);
+    }
+
+    fn render_lead(text: &str, lead: &str) -> Vec<String> {
+        render(text, lead, 80, caps(false), &Theme::dark())
+            .iter()
+            .map(|line| line.iter().map(|s| s.text.as_str()).collect())
+            .collect()
+    }
+}
@@ -1,5 +1,10 @@
 pub mod commands;
+pub mod diff;
+pub mod frame;
 pub mod event_loop;
+pub mod highlight;
 pub mod input;
 pub mod keybindings;
+pub mod markdown;
 pub mod renderer;
+pub mod theme;
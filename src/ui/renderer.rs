@@ -1,7 +1,14 @@
+use std::collections::{HashMap, HashSet};
 use std::io::Write;
+use std::mem::Discriminant;
+use std::ops::Range;
+use std::rc::Rc;
+use std::time::Instant;
 
 use crossterm::{
-    cursor, queue,
+    cursor,
+    event::{DisableMouseCapture, EnableMouseCapture},
+    execute,
     style::{
         Attribute, Color, Print, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor,
     },
@@ -9,8 +16,18 @@ use crossterm::{
 };
 use unicode_width::UnicodeWidthStr;
 
-use crate::app::{App, ChatRole, Mode, Session, SessionStatus, TaskStatus};
+use crate::app::{
+    thinking_fold_id, thread_fold_id, App, ChatMessage, ChatRole, ChatView, ClickTargets,
+    InboxItem, Mode, PendingPermission, PermissionPrompt, Session, SessionStatus, Split,
+    SplitDirection, StreamBlockKind, TaskStatus,
+};
+use crate::permissions::rules::PermissionRule;
 use crate::protocol::types::{self, ContentBlock};
+use crate::ui::diff::{self, DiffLine};
+use crate::ui::frame::{draw, Frame, SetLink};
+use crate::ui::highlight::{self, HighlightState, Lang};
+use crate::ui::markdown::{self, Caps};
+use crate::ui::theme::{Style, Theme};
 
 // ─── Span-Based ChatLine ─────────────────────────────────────────────────────
 
@@ -27,18 +44,54 @@ enum LineStyle {
     Dim,
 }
 
-#[derive(Clone)]
-struct Span {
-    text: String,
-    fg: Option<Color>,
-    bg: Option<Color>,
-    bold: bool,
-    dim: bool,
+impl LineStyle {
+    fn style(&self, theme: &Theme) -> Style {
+        match self {
+            LineStyle::Normal => theme.normal,
+            LineStyle::User => theme.user,
+            LineStyle::Assistant => theme.assistant,
+            LineStyle::System => theme.system,
+            LineStyle::Tool => theme.tool,
+            LineStyle::ToolResult => theme.tool_result,
+            LineStyle::Streaming => theme.streaming,
+            LineStyle::Error => theme.error,
+            LineStyle::Dim => theme.dim,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub(super) struct Span {
+    pub(super) text: String,
+    pub(super) fg: Option<Color>,
+    pub(super) bg: Option<Color>,
+    pub(super) bold: bool,
+    pub(super) dim: bool,
+    pub(super) italic: bool,
+    pub(super) underline: bool,
+    pub(super) strike: bool,
+    /// Target of an OSC 8 hyperlink
+    pub(super) link: Option<String>,
 }
 
 impl Span {
-    fn plain(text: String) -> Self {
-        Self { text, fg: None, bg: None, bold: false, dim: false }
+    pub(super) fn plain(text: String) -> Self {
+        Self {
+            text,
+            ..Self::default()
+        }
+    }
+
+    /// No styling of its own, so the line's base style applies
+    fn is_plain(&self) -> bool {
+        self.fg.is_none()
+            && self.bg.is_none()
+            && !self.bold
+            && !self.dim
+            && !self.italic
+            && !self.underline
+            && !self.strike
+            && self.link.is_none()
     }
 }
 
@@ -46,6 +99,8 @@ impl Span {
 struct ChatLine {
     spans: Vec<Span>,
     base_style: LineStyle,
+    /// Header of a collapsible item (the id `{`/`}`/`o` act on)
+    fold: Option<String>,
 }
 
 impl ChatLine {
@@ -53,9 +108,15 @@ impl ChatLine {
         Self {
             spans: vec![Span::plain(text)],
             base_style: style,
+            fold: None,
         }
     }
 
+    fn with_fold(mut self, id: &str) -> Self {
+        self.fold = Some(id.to_string());
+        self
+    }
+
     fn text(&self) -> String {
         self.spans.iter().map(|s| s.text.as_str()).collect()
     }
@@ -86,16 +147,64 @@ fn truncate_spans(spans: &[Span], max_width: usize) -> Vec<Span> {
 
 // ─── Main Render ────────────────────────────────────────────────────────────
 
-pub fn render(app: &App, stdout: &mut impl Write) -> anyhow::Result<()> {
-    let (width, height) = terminal::size()?;
+/// The frame on screen and the chat layout, kept between renders
+#[derive(Default)]
+pub struct RenderState {
+    /// Last frame written to the terminal; None repaints everything
+    shown: Option<Frame>,
+    lines: LineCache,
+    /// Whether the terminal reports mouse events; None until told
+    mouse: Option<bool>,
+}
+
+impl RenderState {
+    /// Repaint the whole screen on the next render (the terminal was
+    /// resized or replaced by a newly attached client)
+    pub fn force_redraw(&mut self) {
+        self.shown = None;
+        self.mouse = None;
+    }
+}
+
+/// Draw the app into a fresh frame and write the cells that changed since
+/// the last one
+pub fn render(app: &mut App, stdout: &mut impl Write) -> anyhow::Result<()> {
+    let (width, height) = match app.screen_size {
+        Some(size) => size,
+        None => terminal::size()?,
+    };
+    let mut frame = Frame::new(width, height, app.color_depth);
+    let mut state = std::mem::take(&mut app.render_state);
+    if state.mouse != Some(app.mouse_capture) {
+        if app.mouse_capture {
+            execute!(stdout, EnableMouseCapture)?;
+        } else {
+            execute!(stdout, DisableMouseCapture)?;
+        }
+        state.mouse = Some(app.mouse_capture);
+    }
+    (app.chat_view, app.click_targets) = render_frame(app, &mut frame, &mut state.lines);
+    let result = frame.present(state.shown.as_ref(), stdout);
+    state.shown = result.is_ok().then_some(frame);
+    app.render_state = state;
+    Ok(result?)
+}
+
+/// Draw one frame; returns the chat area geometry for fold navigation and
+/// where the clickable parts went
+fn render_frame(
+    app: &App,
+    frame: &mut Frame,
+    cache: &mut LineCache,
+) -> (ChatView, ClickTargets) {
+    let (width, height) = frame.size();
     let width = width as usize;
     let height = height as usize;
 
     if height < 4 || width < 20 {
-        queue!(stdout, cursor::MoveTo(0, 0), Clear(ClearType::All))?;
-        queue!(stdout, Print("Terminal too small"))?;
-        stdout.flush()?;
-        return Ok(());
+        draw!(frame, cursor::MoveTo(0, 0), Clear(ClearType::All));
+        draw!(frame, Print("Terminal too small"));
+        return Default::default();
     }
 
     // Compute layout regions
@@ -118,7 +227,7 @@ pub fn render(app: &App, stdout: &mut impl Write) -> anyhow::Result<()> {
         })
         .unwrap_or_default();
     let task_h = if app.layout.task_panel_visible && !active_tasks.is_empty() {
-        (active_tasks.len() + 1).min(6) // header + tasks, max 6 rows
+        (active_tasks.len() + 1).min(app.layout.task_panel_height as usize) // header + tasks
     } else {
         0
     };
@@ -129,101 +238,122 @@ pub fn render(app: &App, stdout: &mut impl Write) -> anyhow::Result<()> {
     // Layout: chat area + task panel + input_h + 1 status
     let chat_height = height.saturating_sub(1 + input_h + task_h);
 
-    queue!(stdout, cursor::Hide, cursor::MoveTo(0, 0))?;
+    draw!(frame, cursor::Hide, cursor::MoveTo(0, 0));
 
     // Sidebar (if visible)
+    let mut targets = ClickTargets {
+        sidebar_w: sidebar_w as u16,
+        ..Default::default()
+    };
     if sidebar_w > 0 {
-        render_sidebar(stdout, app, sidebar_w, chat_height + task_h)?;
+        targets.sidebar_rows = render_sidebar(frame, app, sidebar_w, chat_height + task_h);
     }
 
-    // Chat area
-    let session = app.active_session();
-    let chat_lines = if let Some(session) = session {
-        build_chat_lines(session, content_w, app.show_thinking)
-    } else {
-        vec![ChatLine::simple(
-            "No active session. Press Ctrl+N to create one.".to_string(),
-            LineStyle::Dim,
-        )]
-    };
-
-    // Permission banner takes space from chat area bottom
-    let perm_lines = if let Some(session) = session {
-        build_permission_banner(session, content_w)
-    } else {
-        0
+    // Chat panes: the active session, and beside or below it the other
+    // session of a split
+    let caps = Caps {
+        colors: app.color_depth,
+        hyperlinks: app.hyperlinks,
     };
-    let question_lines = if let Some(session) = session {
-        session.pending_question.as_ref().map(|q| {
-            if q.questions.is_empty() { 0 }
-            else { q.questions[q.selected].options.len() + 2 }
-        }).unwrap_or(0)
-    } else {
-        0
+    cache.start_frame(caps, &app.theme);
+    let chat_area = Rect {
+        x: content_x,
+        y: 0,
+        width: content_w,
+        height: chat_height,
     };
-    let effective_chat_h = chat_height.saturating_sub(perm_lines + question_lines);
-
-    let scroll_offset = session.map(|s| s.scroll_offset).unwrap_or(0);
-    render_chat_area(
-        stdout,
-        &chat_lines,
-        effective_chat_h,
-        content_w,
-        content_x,
-        scroll_offset,
-        &app.search,
-    )?;
-
-    // Permission banner (rendered at bottom of chat area)
-    if perm_lines > 0 {
-        if let Some(session) = session {
-            let banner_row = effective_chat_h as u16;
-            render_permission_banner(stdout, session, banner_row, content_w, content_x)?;
+    let panes = app
+        .layout
+        .split
+        .as_ref()
+        .zip(app.split_session())
+        .and_then(|(split, other)| split_panes(chat_area, split).map(|panes| (split, other, panes)));
+    let (chat_view, mut prompt_cursor) = match panes {
+        Some((split, other, (first, second))) => {
+            let (focused, unfocused) = if split.focused_first {
+                (first, second)
+            } else {
+                (second, first)
+            };
+            if split.direction == SplitDirection::Vertical {
+                // Divider in the column between the panes
+                for row in 0..chat_height {
+                    draw!(
+                        frame,
+                        cursor::MoveTo((first.x + first.width) as u16, row as u16),
+                        app.theme.border,
+                        Print("\u{2502}"),
+                        ResetColor,
+                    );
+                }
+            }
+            let pane = Pane {
+                area: unfocused,
+                focused: false,
+                titled: true,
+            };
+            let (view, _) = render_pane(frame, app, Some(other), pane, caps, cache, &mut targets);
+            targets.other_pane = Some(view);
+            let pane = Pane {
+                area: focused,
+                focused: true,
+                titled: true,
+            };
+            render_pane(frame, app, app.active_session(), pane, caps, cache, &mut targets)
         }
-    }
-
-    // Question overlay
-    if question_lines > 0 {
-        if let Some(session) = session {
-            let question_row = (effective_chat_h + perm_lines) as u16;
-            render_question_overlay(stdout, session, question_row, content_w, content_x)?;
+        None => {
+            let pane = Pane {
+                area: chat_area,
+                focused: true,
+                titled: false,
+            };
+            render_pane(frame, app, app.active_session(), pane, caps, cache, &mut targets)
         }
+    };
+
+    // Inbox overlay covers the whole chat area
+    if app.inbox.is_some() {
+        render_inbox(frame, app, chat_height, content_w, content_x);
+        prompt_cursor = None;
     }
 
     // Task panel
     if task_h > 0 {
         let task_row = chat_height as u16;
-        render_task_panel(stdout, &active_tasks, task_row, task_h, content_w, content_x)?;
+        render_task_panel(frame, &app.theme, &active_tasks, task_row, task_h, content_w, content_x);
     }
 
     // Input line(s) — multi-line support
     let input_row = (chat_height + task_h) as u16;
-    let input_scroll_start = render_input(stdout, app, input_row, content_w, content_x, input_h)?;
+    let input_scroll_start = render_input(frame, app, input_row, content_w, content_x, input_h);
 
     // Slash command menu (above input line)
     if app.slash_menu.visible {
-        render_slash_menu(stdout, app, input_row.saturating_sub(1), content_w, content_x)?;
+        render_slash_menu(frame, app, input_row.saturating_sub(1), content_w, content_x);
     }
 
     // Status bar (full width, last row)
     let status_row = (height - 1) as u16;
-    render_status_bar(stdout, app, status_row, width)?;
+    render_status_bar(frame, app, status_row, width);
 
     // Flash message overlay (on status bar, right-aligned)
     if let Some((msg, _)) = &app.flash_message {
         let flash = truncate_to_width(msg, width / 2);
         let flash_x = (width.saturating_sub(flash.len() + 2)) as u16;
-        queue!(
-            stdout,
+        draw!(
+            frame,
             cursor::MoveTo(flash_x, status_row),
-            SetBackgroundColor(Color::DarkYellow),
-            SetForegroundColor(Color::Black),
+            app.theme.flash,
             Print(format!(" {} ", flash)),
             ResetColor,
-        )?;
+        );
     }
 
-    // Position cursor
+    // Position cursor (an open permission prompt takes precedence)
+    if let Some((x, y)) = prompt_cursor {
+        draw!(frame, cursor::MoveTo(x, y), cursor::Show);
+        return (chat_view, targets);
+    }
     match app.mode {
         Mode::Insert => {
             let prompt_len = 2; // "> "
@@ -236,43 +366,243 @@ pub fn render(app: &App, stdout: &mut impl Write) -> anyhow::Result<()> {
                 (content_x + prompt_len + cursor_col)
                     .min(width.saturating_sub(1)) as u16
             };
-            queue!(stdout, cursor::MoveTo(cursor_x, cursor_y), cursor::Show)?;
+            draw!(frame, cursor::MoveTo(cursor_x, cursor_y), cursor::Show);
         }
         Mode::Command => {
             // Cursor in command line (on status bar)
             let cursor_x = (1 + app.command_input.cursor_col()).min(width.saturating_sub(1)) as u16;
-            queue!(
-                stdout,
+            draw!(
+                frame,
                 cursor::MoveTo(cursor_x, status_row),
                 cursor::Show
-            )?;
+            );
         }
         Mode::Normal => {
-            queue!(stdout, cursor::Hide)?;
+            draw!(frame, cursor::Hide);
         }
     }
 
-    stdout.flush()?;
-    Ok(())
+    (chat_view, targets)
+}
+
+// ─── Chat Panes ─────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy)]
+struct Rect {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+/// Where a session's chat goes and how it is drawn
+#[derive(Clone, Copy)]
+struct Pane {
+    area: Rect,
+    /// The active session's pane, which shows its banners
+    focused: bool,
+    /// Whether the top row names the session (only in a split)
+    titled: bool,
+}
+
+/// Smallest pane a split leaves: columns side by side, rows stacked
+/// (title included)
+const MIN_PANE_WIDTH: usize = 20;
+const MIN_PANE_HEIGHT: usize = 4;
+
+/// The left/top and right/bottom panes of a split, or None when the chat
+/// area is too small for two. Side-by-side panes leave a column between
+/// them for the divider.
+fn split_panes(area: Rect, split: &Split) -> Option<(Rect, Rect)> {
+    match split.direction {
+        SplitDirection::Vertical => {
+            let room = area.width.checked_sub(1)?;
+            if room < 2 * MIN_PANE_WIDTH {
+                return None;
+            }
+            let first = (room * split.ratio as usize / 100).clamp(MIN_PANE_WIDTH, room - MIN_PANE_WIDTH);
+            Some((
+                Rect { width: first, ..area },
+                Rect {
+                    x: area.x + first + 1,
+                    width: room - first,
+                    ..area
+                },
+            ))
+        }
+        SplitDirection::Horizontal => {
+            if area.height < 2 * MIN_PANE_HEIGHT {
+                return None;
+            }
+            let first = (area.height * split.ratio as usize / 100)
+                .clamp(MIN_PANE_HEIGHT, area.height - MIN_PANE_HEIGHT);
+            Some((
+                Rect { height: first, ..area },
+                Rect {
+                    y: area.y + first,
+                    height: area.height - first,
+                    ..area
+                },
+            ))
+        }
+    }
+}
+
+/// One session's chat in its pane: the title, the chat lines and, when
+/// focused, the permission banner and question overlay at the bottom.
+/// Returns the chat view and the cursor position of an open banner prompt.
+fn render_pane(
+    frame: &mut Frame,
+    app: &App,
+    session: Option<&Session>,
+    pane: Pane,
+    caps: Caps,
+    cache: &mut LineCache,
+    targets: &mut ClickTargets,
+) -> (ChatView, Option<(u16, u16)>) {
+    let mut area = pane.area;
+    if pane.titled {
+        render_pane_title(frame, app, session, area, pane.focused);
+        area.y += 1;
+        area.height = area.height.saturating_sub(1);
+    }
+
+    let chat_lines = if let Some(session) = session {
+        build_chat_lines(session, area.width, app.show_thinking, caps, &app.theme, cache)
+    } else {
+        let mut lines = ChatLines::default();
+        lines.push(ChatLine::simple(
+            "No active session. Press Ctrl+N to create one.".to_string(),
+            LineStyle::Dim,
+        ));
+        lines
+    };
+
+    // Permission banner and question overlay take space from the bottom
+    let banner_session = session.filter(|_| pane.focused);
+    let perm_lines = if let Some(session) = banner_session {
+        build_permission_banner(session, area.width, &app.theme)
+    } else {
+        0
+    };
+    let question_lines = if let Some(session) = banner_session {
+        session.pending_question.as_ref().map(|q| {
+            if q.questions.is_empty() { 0 }
+            else { q.questions[q.selected].options.len() + 2 }
+        }).unwrap_or(0)
+    } else {
+        0
+    };
+    let effective_chat_h = area.height.saturating_sub(perm_lines + question_lines);
+
+    // Search matches index the active session's lines
+    let no_search = None;
+    let first_line = render_chat_area(
+        frame,
+        &chat_lines,
+        Rect {
+            height: effective_chat_h,
+            ..area
+        },
+        session,
+        if pane.focused { &app.search } else { &no_search },
+        &app.theme,
+    );
+    let chat_view = ChatView {
+        session_id: session.map(|s| s.id.clone()),
+        total_lines: chat_lines.len(),
+        height: effective_chat_h,
+        x: area.x as u16,
+        y: area.y as u16,
+        width: area.width as u16,
+        first_line,
+        folds: chat_lines.folds,
+    };
+
+    // Permission banner (rendered at bottom of chat area)
+    let mut prompt_cursor = None;
+    if perm_lines > 0 {
+        if let Some(session) = banner_session {
+            let banner_row = (area.y + effective_chat_h) as u16;
+            prompt_cursor = render_permission_banner(
+                frame,
+                &app.theme,
+                session,
+                banner_row,
+                area.width,
+                area.x,
+                &mut targets.buttons,
+            );
+        }
+    }
+
+    // Question overlay
+    if question_lines > 0 {
+        if let Some(session) = banner_session {
+            let question_row = (area.y + effective_chat_h + perm_lines) as u16;
+            render_question_overlay(frame, &app.theme, session, question_row, area.width, area.x);
+        }
+    }
+
+    (chat_view, prompt_cursor)
+}
+
+/// A split pane's top row: the session's number and name on a rule,
+/// highlighted for the focused pane
+fn render_pane_title(
+    frame: &mut Frame,
+    app: &App,
+    session: Option<&Session>,
+    area: Rect,
+    focused: bool,
+) {
+    let title = match session {
+        Some(session) => {
+            let index = app.visible_session_order().iter().position(|id| *id == session.id);
+            match index {
+                Some(i) => format!(" {}. {} ", i + 1, session.name),
+                None => format!(" {} ", session.name),
+            }
+        }
+        None => String::new(),
+    };
+    let title = truncate_to_width(&title, area.width.saturating_sub(2));
+    let rule = area.width.saturating_sub(2 + UnicodeWidthStr::width(title.as_str()));
+    draw!(
+        frame,
+        cursor::MoveTo(area.x as u16, area.y as u16),
+        app.theme.border,
+        Print("\u{2500}\u{2500}"),
+        ResetColor,
+    );
+    let style = if focused {
+        app.theme.pane_title_focused
+    } else {
+        app.theme.pane_title
+    };
+    draw!(frame, style, Print(&title), ResetColor, app.theme.border);
+    draw!(frame, Print("\u{2500}".repeat(rule)), ResetColor);
 }
 
 // ─── Sidebar ────────────────────────────────────────────────────────────────
 
 fn render_sidebar(
-    stdout: &mut impl Write,
+    frame: &mut Frame,
     app: &App,
     sidebar_w: usize,
     sidebar_h: usize,
-) -> anyhow::Result<()> {
+) -> Vec<String> {
     // Build visible (non-archived) session entries
     let visible_order = app.visible_session_order();
     let active_id = app.active_session_id.as_deref();
 
     // Build sidebar rows: each session gets 1 or 2 lines
     struct SidebarEntry {
+        id: String,
         line1: String,
         line2: Option<String>,
         is_active: bool,
+        is_waiting: bool,
     }
 
     let mut entries: Vec<SidebarEntry> = Vec::new();
@@ -296,13 +626,24 @@ fn render_sidebar(
             };
 
             let pin_marker = if session.pinned { "*" } else { "" };
+            // Badge sessions blocked on a permission or question
+            let waiting = session.waiting_count();
+            let badge = if waiting > 0 {
+                format!(" !{}", waiting)
+            } else {
+                String::new()
+            };
             let line1 = format!(
-                "{}{} {}. {}{}",
+                "{}{} {}. {}{}{}",
                 marker,
                 status_icon,
                 i + 1,
                 pin_marker,
-                truncate_to_width(&session.name, sidebar_w.saturating_sub(7 + pin_marker.len()))
+                truncate_to_width(
+                    &session.name,
+                    sidebar_w.saturating_sub(7 + pin_marker.len() + badge.len())
+                ),
+                badge
             );
 
             // Git info second line
@@ -334,202 +675,463 @@ fn render_sidebar(
             };
 
             entries.push(SidebarEntry {
+                id: id.clone(),
                 line1,
                 line2,
                 is_active,
+                is_waiting: waiting > 0,
             });
         }
     }
 
-    // Flatten entries into (text, is_git_line, is_active) tuples
-    let mut rows: Vec<(String, bool, bool)> = Vec::new();
+    // Flatten entries into (text, is_git_line, is_active, is_waiting) tuples
+    let mut rows: Vec<(String, bool, bool, bool)> = Vec::new();
+    // Session of each row, for clicks
+    let mut row_sessions = Vec::new();
     for entry in &entries {
+        row_sessions.push(entry.id.clone());
         rows.push((
             truncate_to_width(&entry.line1, sidebar_w.saturating_sub(1)),
             false,
             entry.is_active,
+            entry.is_waiting,
         ));
         if let Some(ref line2) = entry.line2 {
+            row_sessions.push(entry.id.clone());
             rows.push((
                 truncate_to_width(line2, sidebar_w.saturating_sub(1)),
                 true,
                 entry.is_active,
+                false,
             ));
         }
     }
 
     for i in 0..sidebar_h {
         let row = i as u16;
-        queue!(stdout, cursor::MoveTo(0, row))?;
+        draw!(frame, cursor::MoveTo(0, row));
 
-        let (text, is_git_line, is_active) = rows
+        let (text, is_git_line, is_active, is_waiting) = rows
             .get(i)
             .cloned()
-            .unwrap_or((String::new(), false, false));
+            .unwrap_or((String::new(), false, false, false));
 
+        let theme = &app.theme;
         if is_active {
-            queue!(
-                stdout,
-                SetBackgroundColor(Color::DarkGrey),
-                SetForegroundColor(if is_git_line { Color::Grey } else { Color::White }),
-                SetAttribute(Attribute::Bold),
-            )?;
+            draw!(frame, theme.sidebar_active);
+            if is_git_line {
+                draw!(frame, theme.sidebar_active_git);
+            }
         } else if !text.is_empty() {
             if is_git_line {
-                queue!(stdout, SetForegroundColor(Color::DarkGrey))?;
+                draw!(frame, theme.sidebar_git);
+            } else if is_waiting {
+                draw!(frame, theme.sidebar_waiting);
             } else {
-                queue!(stdout, SetForegroundColor(Color::Grey))?;
+                draw!(frame, theme.sidebar);
             }
         }
 
-        queue!(
-            stdout,
+        draw!(
+            frame,
             Print(format!("{:w$}", text, w = sidebar_w.saturating_sub(1))),
             ResetColor,
             SetAttribute(Attribute::Reset),
-        )?;
+        );
 
         // Sidebar border
-        queue!(
-            stdout,
-            SetForegroundColor(Color::DarkGrey),
+        draw!(
+            frame,
+            theme.border,
             Print("\u{2502}"), // │
             ResetColor,
-        )?;
+        );
     }
 
-    Ok(())
+    row_sessions.truncate(sidebar_h);
+    row_sessions
+}
+
+// ─── Line Cache ─────────────────────────────────────────────────────────────
+
+/// Laid-out message text and tool card bodies, reused across frames while
+/// their content, the width and the terminal stay the same. Entries not
+/// used by a frame are dropped by the next one, so memory follows what is
+/// on screen (the sessions in view).
+#[derive(Default)]
+struct LineCache {
+    caps: Option<Caps>,
+    theme: Option<Theme>,
+    /// By session id
+    sessions: HashMap<String, SessionLines>,
+}
+
+/// One session's cached layouts
+#[derive(Default)]
+struct SessionLines {
+    /// `Session::history_generation` they were laid out for
+    generation: u32,
+    /// Used by the frame being built
+    fresh: HashMap<CacheKey, Rc<Layout>>,
+    /// Used by the frame before it
+    stale: HashMap<CacheKey, Rc<Layout>>,
+}
+
+#[derive(PartialEq, Eq, Hash)]
+enum CacheKey {
+    /// Message text by where it comes from and its length (messages and
+    /// streamed blocks only grow), drawn in `style`
+    Text {
+        source: TextSource,
+        len: usize,
+        style: Discriminant<LineStyle>,
+        width: usize,
+    },
+    /// An expanded thinking block, by message and block index
+    Thinking { msg: usize, block: usize, width: usize },
+    /// A tool card's body by call id, and whether its result is in and failed
+    Card {
+        id: String,
+        width: usize,
+        result: Option<bool>,
+    },
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy)]
+enum TextSource {
+    /// A message, by index in the transcript
+    Message(usize),
+    /// A block of the message being streamed, by when the message started
+    /// and the block's index
+    Stream(Option<Instant>, usize),
+}
+
+struct Layout {
+    lines: Vec<ChatLine>,
+    /// Lines added and removed when the body is a diff
+    diff_stats: Option<(usize, usize)>,
+}
+
+impl LineCache {
+    fn start_frame(&mut self, caps: Caps, theme: &Theme) {
+        if self.caps != Some(caps) || self.theme.as_ref() != Some(theme) {
+            // Colors and links are baked into the lines
+            self.sessions.clear();
+            self.caps = Some(caps);
+            self.theme = Some(theme.clone());
+        }
+        self.sessions.retain(|_, lines| !lines.fresh.is_empty());
+        for lines in self.sessions.values_mut() {
+            lines.stale = std::mem::take(&mut lines.fresh);
+        }
+    }
+
+    fn session(&mut self, session: &Session) -> &mut SessionLines {
+        let lines = self.sessions.entry(session.id.clone()).or_default();
+        if lines.generation != session.history_generation {
+            // :clear starts the message indices over
+            lines.fresh.clear();
+            lines.stale.clear();
+            lines.generation = session.history_generation;
+        }
+        lines
+    }
+}
+
+impl SessionLines {
+    fn get_or_insert(&mut self, key: CacheKey, build: impl FnOnce() -> Layout) -> Rc<Layout> {
+        if let Some(layout) = self.fresh.get(&key) {
+            return Rc::clone(layout);
+        }
+        let layout = match self.stale.remove(&key) {
+            Some(layout) => layout,
+            None => Rc::new(build()),
+        };
+        self.fresh.insert(key, Rc::clone(&layout));
+        layout
+    }
+}
+
+/// A message's text as chat lines: markdown for Claude, wrapped plain text
+/// otherwise
+fn text_lines(
+    ctx: &mut ChatCtx,
+    source: TextSource,
+    content: &str,
+    style: LineStyle,
+    width: usize,
+) -> Rc<Layout> {
+    let key = CacheKey::Text {
+        source,
+        len: content.len(),
+        style: std::mem::discriminant(&style),
+        width,
+    };
+    let (caps, theme) = (ctx.caps, ctx.theme);
+    let build = || {
+        let lines = match style {
+            LineStyle::Assistant | LineStyle::Streaming => markdown::render(content, "Claude: ", width, caps, theme)
+                .into_iter()
+                .map(|spans| ChatLine {
+                    spans,
+                    base_style: style.clone(),
+                    fold: None,
+                })
+                .collect(),
+            LineStyle::User => wrap_text(&format!("You: {}", content), width)
+                .into_iter()
+                .map(|line| ChatLine::simple(line, LineStyle::User))
+                .collect(),
+            _ => wrap_text(content, width)
+                .into_iter()
+                .map(|line| ChatLine::simple(line, style.clone()))
+                .collect(),
+        };
+        Layout {
+            lines,
+            diff_stats: None,
+        }
+    };
+    ctx.cache.get_or_insert(key, build)
 }
 
 // ─── Chat Lines ─────────────────────────────────────────────────────────────
 
-fn build_chat_lines(session: &Session, width: usize, show_thinking: bool) -> Vec<ChatLine> {
-    let mut lines = Vec::new();
-    let mut in_code_block = false;
+/// The laid-out chat as runs of lines, most of them shared with the line
+/// cache. Only the lines in the window being drawn are copied out.
+#[derive(Default)]
+struct ChatLines {
+    runs: Vec<Run>,
+    len: usize,
+    /// Collapsible items by id, with their header line
+    folds: Vec<(String, usize)>,
+}
 
-    for msg in &session.messages {
-        match msg.role {
-            ChatRole::User => {
-                let text = format!("You: {}", msg.content);
-                for line in wrap_text(&text, width) {
-                    lines.push(ChatLine::simple(line, LineStyle::User));
-                }
-                lines.push(ChatLine::simple(String::new(), LineStyle::Normal));
+enum Run {
+    Line(ChatLine),
+    /// Some lines of a cached layout, drawn behind `indent`
+    Shared {
+        layout: Rc<Layout>,
+        range: Range<usize>,
+        indent: String,
+    },
+}
+
+impl Run {
+    fn len(&self) -> usize {
+        match self {
+            Run::Line(_) => 1,
+            Run::Shared { range, .. } => range.len(),
+        }
+    }
+}
+
+impl ChatLines {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn push(&mut self, line: ChatLine) {
+        if let Some(id) = &line.fold {
+            self.folds.push((id.clone(), self.len));
+        }
+        self.len += 1;
+        self.runs.push(Run::Line(line));
+    }
+
+    /// Add `range` of a cached layout's lines (which have no folds)
+    fn push_shared(&mut self, layout: &Rc<Layout>, range: Range<usize>, indent: &str) {
+        if range.is_empty() {
+            return;
+        }
+        self.len += range.len();
+        self.runs.push(Run::Shared {
+            layout: Rc::clone(layout),
+            range,
+            indent: indent.to_string(),
+        });
+    }
+
+    /// Lines `start..end`, ready to draw
+    fn window(&self, start: usize, end: usize) -> Vec<ChatLine> {
+        let mut lines = Vec::new();
+        let mut pos = 0;
+        for run in &self.runs {
+            if pos >= end {
+                break;
             }
-            ChatRole::Assistant => {
-                if let Some(blocks) = &msg.content_blocks {
-                    let mut i = 0;
-                    while i < blocks.len() {
-                        match &blocks[i] {
-                            ContentBlock::ToolUse { name, input, .. } => {
-                                let mut count = 1;
-                                let first_summary = types::format_tool_summary(name, input);
-                                while i + count < blocks.len() {
-                                    if let ContentBlock::ToolUse { name: next_name, .. } = &blocks[i + count] {
-                                        if next_name == name {
-                                            count += 1;
-                                        } else {
-                                            break;
-                                        }
-                                    } else {
-                                        break;
-                                    }
-                                }
-                                let text = if count > 1 {
-                                    format!("[{} x{}] {}", name, count, first_summary)
-                                } else {
-                                    format!("[{}] {}", name, first_summary)
-                                };
-                                for line in wrap_text(&text, width) {
-                                    lines.push(ChatLine::simple(line, LineStyle::Tool));
-                                }
-                                i += count;
-                            }
-                            ContentBlock::ToolResult {
-                                content, is_error, ..
-                            } => {
-                                if session.tool_results_collapsed {
-                                    lines.push(ChatLine::simple(
-                                        "[result hidden]".to_string(),
-                                        LineStyle::Dim,
-                                    ));
-                                } else {
-                                    let text = types::extract_tool_result_text(content);
-                                    if !text.is_empty() {
-                                        let truncated = truncate_chars(&text, 500);
-                                        for line in wrap_text(&truncated, width) {
-                                            lines.push(ChatLine::simple(
-                                                line,
-                                                if *is_error {
-                                                    LineStyle::Error
-                                                } else {
-                                                    LineStyle::ToolResult
-                                                },
-                                            ));
-                                        }
-                                    }
-                                }
-                                i += 1;
-                            }
-                            ContentBlock::Thinking { thinking, .. } => {
-                                if show_thinking && !thinking.is_empty() {
-                                    let truncated = truncate_chars(thinking, 200);
-                                    let text = format!("(thinking) {}", truncated);
-                                    for line in wrap_text(&text, width) {
-                                        lines.push(ChatLine::simple(line, LineStyle::Dim));
-                                    }
-                                }
-                                i += 1;
-                            }
-                            _ => {
-                                i += 1;
-                            }
-                        }
+            let len = run.len();
+            if pos + len > start {
+                let (from, to) = (start.saturating_sub(pos), (end - pos).min(len));
+                match run {
+                    Run::Line(line) => lines.push(line.clone()),
+                    Run::Shared {
+                        layout,
+                        range,
+                        indent,
+                    } => {
+                        let shown = &layout.lines[range.start + from..range.start + to];
+                        lines.extend(shown.iter().map(|line| indented(line.clone(), indent)));
                     }
                 }
+            }
+            pos += len;
+        }
+        lines
+    }
+}
 
-                if !msg.content.is_empty() {
-                    // Apply markdown rendering to assistant text
-                    let prefixed = format!("Claude: {}", msg.content);
-                    for raw_line in prefixed.split('\n') {
-                        for wrapped in wrap_text(raw_line, width) {
-                            let spans = parse_markdown_line(&wrapped, &mut in_code_block);
-                            lines.push(ChatLine {
-                                spans,
-                                base_style: LineStyle::Assistant,
-                            });
-                        }
-                    }
+/// Shared state while building chat lines
+struct ChatCtx<'a> {
+    session: &'a Session,
+    width: usize,
+    show_thinking: bool,
+    /// Ids of every tool call in the transcript
+    tool_use_ids: HashSet<&'a str>,
+    /// Tool results by the id of the call they answer
+    results: HashMap<&'a str, ToolOutcome<'a>>,
+    /// Subagent messages (with their index) by the Task tool call they
+    /// belong to
+    threads: HashMap<&'a str, Vec<(usize, &'a ChatMessage)>>,
+    caps: Caps,
+    theme: &'a Theme,
+    cache: &'a mut SessionLines,
+}
+
+#[derive(Clone, Copy)]
+struct ToolOutcome<'a> {
+    content: &'a serde_json::Value,
+    is_error: bool,
+    timestamp: i64,
+}
+
+/// Result lines shown in an expanded tool card
+const CARD_BODY_LINES: usize = 12;
+
+/// Diff lines shown in an expanded tool card
+const DIFF_CARD_LINES: usize = 40;
+
+fn build_chat_lines(
+    session: &Session,
+    width: usize,
+    show_thinking: bool,
+    caps: Caps,
+    theme: &Theme,
+    cache: &mut LineCache,
+) -> ChatLines {
+    let mut lines = ChatLines::default();
+
+    let mut tool_use_ids = HashSet::new();
+    let mut results = HashMap::new();
+    for msg in &session.messages {
+        for block in msg.content_blocks.iter().flatten() {
+            match block {
+                ContentBlock::ToolUse { id, .. } => {
+                    tool_use_ids.insert(id.as_str());
                 }
-                lines.push(ChatLine::simple(String::new(), LineStyle::Normal));
-            }
-            ChatRole::System => {
-                for line in wrap_text(&msg.content, width) {
-                    lines.push(ChatLine::simple(line, LineStyle::System));
+                ContentBlock::ToolResult {
+                    tool_use_id,
+                    content,
+                    is_error,
+                } => {
+                    results.insert(
+                        tool_use_id.as_str(),
+                        ToolOutcome {
+                            content,
+                            is_error: *is_error,
+                            timestamp: msg.timestamp,
+                        },
+                    );
                 }
-                lines.push(ChatLine::simple(String::new(), LineStyle::Normal));
+                _ => {}
             }
         }
     }
 
-    // Streaming text — also apply markdown
-    if !session.streaming_text.is_empty() {
-        let prefixed = format!("Claude: {}", session.streaming_text);
-        for raw_line in prefixed.split('\n') {
-            for wrapped in wrap_text(raw_line, width) {
-                let spans = parse_markdown_line(&wrapped, &mut in_code_block);
-                lines.push(ChatLine {
-                    spans,
-                    base_style: LineStyle::Streaming,
-                });
+    // Subagent messages are drawn under their Task call; ones whose parent
+    // isn't in the transcript stay inline so nothing goes missing
+    let mut threads: HashMap<&str, Vec<(usize, &ChatMessage)>> = HashMap::new();
+    let mut top_level = Vec::new();
+    for (i, msg) in session.messages.iter().enumerate() {
+        match msg.parent_tool_use_id.as_deref() {
+            Some(parent) if tool_use_ids.contains(parent) => {
+                threads.entry(parent).or_default().push((i, msg))
+            }
+            _ => top_level.push((i, msg)),
+        }
+    }
+
+    let mut ctx = ChatCtx {
+        session,
+        width,
+        show_thinking,
+        tool_use_ids,
+        results,
+        threads,
+        caps,
+        theme,
+        cache: cache.session(session),
+    };
+    for (i, msg) in top_level {
+        push_message_lines(&mut lines, &mut ctx, i, msg, "");
+    }
+
+    // Message being streamed, block by block
+    for (i, block) in session.stream_blocks.iter().enumerate() {
+        match &block.kind {
+            StreamBlockKind::Text => {
+                let source = TextSource::Stream(session.stream_start, i);
+                let text = text_lines(&mut ctx, source, &block.content, LineStyle::Streaming, width);
+                lines.push_shared(&text, 0..text.lines.len(), "");
+            }
+            StreamBlockKind::Thinking => {
+                if show_thinking {
+                    let text = format!("(thinking) {}", tail_chars(&block.content, 400));
+                    for line in wrap_text(&text, width) {
+                        lines.push(ChatLine::simple(line, LineStyle::Dim));
+                    }
+                } else if !block.done {
+                    lines.push(ChatLine::simple(
+                        "(thinking... T to show)".to_string(),
+                        LineStyle::Dim,
+                    ));
+                }
+            }
+            StreamBlockKind::ToolUse { name } => {
+                // The input only parses once complete; until then show its size
+                let summary = match serde_json::from_str::<serde_json::Value>(&block.content) {
+                    Ok(input) => types::format_tool_summary(name, &input),
+                    Err(_) => format!("receiving input ({})", format_size(block.content.len())),
+                };
+                for line in wrap_text(&format!("[{}] {}", name, summary), width) {
+                    lines.push(ChatLine::simple(line, LineStyle::Tool));
+                }
+                if !block.done {
+                    for line in partial_input_preview(&block.content, 4) {
+                        lines.push(ChatLine::simple(
+                            truncate_to_width(&format!("  {}", line), width),
+                            LineStyle::Dim,
+                        ));
+                    }
+                }
             }
         }
+    }
+    if !session.stream_blocks.is_empty() {
         if let Some(start) = session.stream_start {
             let elapsed = start.elapsed().as_secs_f64();
             let toks = session.stream_output_tokens;
             let tps = if elapsed > 0.0 { toks as f64 / elapsed } else { 0.0 };
+            let approx = if session.stream_tokens_exact { "" } else { "~" };
             lines.push(ChatLine::simple(
-                format!("{:.1}s \u{2502} ~{} tokens \u{2502} {:.0} tok/s", elapsed, toks, tps),
+                format!(
+                    "{:.1}s \u{2502} {}{} tokens \u{2502} {:.0} tok/s",
+                    elapsed, approx, toks, tps
+                ),
                 LineStyle::Dim,
             ));
         }
@@ -537,16 +1139,14 @@ fn build_chat_lines(session: &Session, width: usize, show_thinking: bool) -> Vec
 
     // Status indicators
     match session.status {
-        SessionStatus::WaitingForCli => {
-            if lines.is_empty() {
-                lines.push(ChatLine::simple(
-                    "Waiting for Claude CLI to connect...".to_string(),
-                    LineStyle::Dim,
-                ));
-            }
+        SessionStatus::WaitingForCli if lines.is_empty() => {
+            lines.push(ChatLine::simple(
+                "Waiting for Claude CLI to connect...".to_string(),
+                LineStyle::Dim,
+            ));
         }
         SessionStatus::Running
-            if session.streaming_text.is_empty()
+            if session.stream_blocks.is_empty()
                 && session
                     .messages
                     .last()
@@ -570,154 +1170,600 @@ fn build_chat_lines(session: &Session, width: usize, show_thinking: bool) -> Vec
     lines
 }
 
-// ─── Markdown Parser ────────────────────────────────────────────────────────
+/// Push a line, prefixed with `indent` when inside a thread
+fn push_indented(lines: &mut ChatLines, line: ChatLine, indent: &str) {
+    lines.push(indented(line, indent));
+}
 
-fn parse_markdown_line(line: &str, in_code_block: &mut bool) -> Vec<Span> {
-    // Code fence toggle
-    if line.trim_start().starts_with("```") {
-        *in_code_block = !*in_code_block;
-        return vec![Span {
-            text: line.to_string(),
-            fg: Some(Color::DarkYellow),
-            bg: Some(Color::Rgb { r: 30, g: 30, b: 30 }),
-            bold: false,
-            dim: false,
-        }];
+/// A line prefixed with `indent`
+fn indented(mut line: ChatLine, indent: &str) -> ChatLine {
+    if !indent.is_empty() {
+        if line.spans.len() == 1 && line.spans[0].is_plain() {
+            // Keep simple lines single-span so they keep their base style
+            line.spans[0].text = format!("{}{}", indent, line.spans[0].text);
+        } else {
+            line.spans.insert(0, Span::plain(indent.to_string()));
+        }
     }
+    line
+}
 
-    // Inside code block
-    if *in_code_block {
-        return vec![Span {
-            text: line.to_string(),
-            fg: Some(Color::DarkYellow),
-            bg: Some(Color::Rgb { r: 30, g: 30, b: 30 }),
-            bold: false,
-            dim: false,
-        }];
+fn push_message_lines(
+    lines: &mut ChatLines,
+    ctx: &mut ChatCtx,
+    index: usize,
+    msg: &ChatMessage,
+    indent: &str,
+) {
+    let source = TextSource::Message(index);
+    let width = ctx.width.saturating_sub(UnicodeWidthStr::width(indent)).max(10);
+    match msg.role {
+        ChatRole::User if msg.is_tool_results() => {
+            // Drawn in the cards of their calls; only results whose call
+            // isn't in the transcript are shown here
+            let mut shown = false;
+            for block in msg.content_blocks.iter().flatten() {
+                if let ContentBlock::ToolResult {
+                    tool_use_id,
+                    content,
+                    is_error,
+                } = block
+                {
+                    if !ctx.tool_use_ids.contains(tool_use_id.as_str()) {
+                        push_orphan_result(lines, ctx, content, *is_error, indent);
+                        shown = true;
+                    }
+                }
+            }
+            if !shown {
+                return;
+            }
+        }
+        ChatRole::User => {
+            let text = text_lines(ctx, source, &msg.content, LineStyle::User, width);
+            lines.push_shared(&text, 0..text.lines.len(), indent);
+        }
+        ChatRole::Assistant => {
+            for (b, block) in msg.content_blocks.iter().flatten().enumerate() {
+                match block {
+                    ContentBlock::ToolUse { id, name, input } => {
+                        push_tool_card(lines, ctx, msg, id, name, input, indent);
+                    }
+                    ContentBlock::ToolResult {
+                        content, is_error, ..
+                    } => {
+                        push_orphan_result(lines, ctx, content, *is_error, indent);
+                    }
+                    ContentBlock::Thinking { thinking, .. }
+                        if ctx.show_thinking && !thinking.is_empty() =>
+                    {
+                        push_thinking(lines, ctx, (index, b), thinking, indent);
+                    }
+                    _ => {}
+                }
+            }
+
+            if !msg.content.is_empty() {
+                let text = text_lines(ctx, source, &msg.content, LineStyle::Assistant, width);
+                lines.push_shared(&text, 0..text.lines.len(), indent);
+            }
+        }
+        ChatRole::System => {
+            let text = text_lines(ctx, source, &msg.content, LineStyle::System, width);
+            lines.push_shared(&text, 0..text.lines.len(), indent);
+        }
     }
+    // Threads stay compact: no blank line between their messages
+    if indent.is_empty() {
+        lines.push(ChatLine::simple(String::new(), LineStyle::Normal));
+    }
+}
 
-    // Header
-    if line.starts_with("# ") || line.starts_with("## ") || line.starts_with("### ") {
-        return vec![Span {
-            text: line.to_string(),
-            fg: Some(Color::Cyan),
-            bg: None,
-            bold: true,
-            dim: false,
-        }];
+/// A thinking block: one line with its start (the fold target) that
+/// expands to the whole text. `at` is the message and block index.
+fn push_thinking(
+    lines: &mut ChatLines,
+    ctx: &mut ChatCtx,
+    at: (usize, usize),
+    thinking: &str,
+    indent: &str,
+) {
+    let width = ctx.width.saturating_sub(UnicodeWidthStr::width(indent)).max(10);
+    let fold_id = thinking_fold_id(thinking);
+    if ctx.session.is_fold_collapsed(&fold_id, true) {
+        let start = truncate_chars(thinking, 200).split_whitespace().collect::<Vec<_>>().join(" ");
+        let header = truncate_to_width(&format!("\u{25b8} (thinking) {}", start), width);
+        push_indented(lines, ChatLine::simple(header, LineStyle::Dim).with_fold(&fold_id), indent);
+        return;
     }
+    let header = ChatLine::simple("\u{25be} (thinking)".to_string(), LineStyle::Dim);
+    push_indented(lines, header.with_fold(&fold_id), indent);
+    let width = width.saturating_sub(2);
+    let (msg, block) = at;
+    let body = ctx.cache.get_or_insert(CacheKey::Thinking { msg, block, width }, || Layout {
+        lines: wrap_text(thinking, width)
+            .into_iter()
+            .map(|line| ChatLine::simple(line, LineStyle::Dim))
+            .collect(),
+        diff_stats: None,
+    });
+    lines.push_shared(&body, 0..body.lines.len(), &format!("{}  ", indent));
+}
 
-    // Bullet list
-    let trimmed = line.trim_start();
-    if trimmed.starts_with("- ") || trimmed.starts_with("* ") {
-        let indent = line.len() - trimmed.len();
-        let indent_str: String = line[..indent].to_string();
-        let bullet = &trimmed[..2];
-        let rest = &trimmed[2..];
-        let mut spans = Vec::new();
-        if !indent_str.is_empty() {
-            spans.push(Span::plain(indent_str));
+/// A tool call joined with its result: a one-line header with the summary,
+/// status and duration (the fold target), the subagent thread of a Task
+/// call, and when expanded the result body or the diff of a file edit
+fn push_tool_card(
+    lines: &mut ChatLines,
+    ctx: &mut ChatCtx,
+    msg: &ChatMessage,
+    id: &str,
+    name: &str,
+    input: &serde_json::Value,
+    indent: &str,
+) {
+    let width = ctx.width.saturating_sub(UnicodeWidthStr::width(indent)).max(10);
+    let body_indent = format!("{}  \u{2502} ", indent);
+    let body_width = width.saturating_sub(4).max(10);
+    let outcome = ctx.results.get(id).copied();
+    let is_error = outcome.map(|o| o.is_error).unwrap_or(false);
+    let key = CacheKey::Card {
+        id: id.to_string(),
+        width: body_width,
+        result: outcome.map(|o| o.is_error),
+    };
+    let theme = ctx.theme;
+    let start_lines = ctx.session.edit_start_lines.get(id).map(Vec::as_slice).unwrap_or(&[]);
+    let body = ctx.cache.get_or_insert(key, || {
+        card_body(name, input, start_lines, outcome, body_width, theme)
+    });
+    let has_body = !body.lines.is_empty();
+    let collapsed = ctx
+        .session
+        .is_fold_collapsed(id, ctx.session.tool_results_collapsed);
+
+    let marker = match (has_body, collapsed) {
+        (false, _) => "\u{2022}",
+        (true, true) => "\u{25b8}",
+        (true, false) => "\u{25be}",
+    };
+    let mut status = Vec::new();
+    match outcome {
+        Some(outcome) => {
+            let mut done = if outcome.is_error { "\u{2717}" } else { "\u{2713}" }.to_string();
+            let measured = (outcome.timestamp - msg.timestamp) as f64;
+            let reported = ctx.session.tool_durations.get(id).copied().unwrap_or(0.0);
+            let secs = reported.max(measured);
+            if secs >= 1.0 {
+                done.push_str(&format!(" {}", format_duration(secs)));
+            }
+            status.push(done);
+        }
+        None => match ctx.session.tool_durations.get(id) {
+            Some(secs) => status.push(format!("running {}", format_duration(*secs))),
+            None => status.push("running".to_string()),
+        },
+    }
+    if let Some((added, removed)) = body.diff_stats {
+        status.push(format!("+{} -{}", added, removed));
+    } else if outcome.is_some() && body.lines.is_empty() {
+        status.push("no output".to_string());
+    } else if collapsed && !body.lines.is_empty() {
+        status.push(format!(
+            "{} line{}",
+            body.lines.len(),
+            if body.lines.len() == 1 { "" } else { "s" }
+        ));
+    }
+    let prefix = format!("{} [{}] ", marker, name);
+    let suffix = format!(" \u{00b7} {}", status.join(" \u{00b7} "));
+    let room = width
+        .saturating_sub(UnicodeWidthStr::width(prefix.as_str()) + UnicodeWidthStr::width(suffix.as_str()))
+        .max(10);
+    let summary = truncate_to_width(&types::format_tool_summary(name, input), room);
+    let header = truncate_to_width(&format!("{}{}{}", prefix, summary, suffix), width);
+    let style = if is_error { LineStyle::Error } else { LineStyle::Tool };
+    let mut line = ChatLine::simple(header, style);
+    if has_body {
+        line = line.with_fold(id);
+    }
+    push_indented(lines, line, indent);
+
+    if ctx.threads.contains_key(id) {
+        push_thread_lines(lines, ctx, id, indent);
+    }
+
+    if collapsed {
+        return;
+    }
+    let total = body.lines.len();
+    if body.diff_stats.is_some() {
+        // Diffs come capped already
+        lines.push_shared(&body, 0..total, &body_indent);
+    } else {
+        lines.push_shared(&body, 0..total.min(CARD_BODY_LINES), &body_indent);
+        if total > CARD_BODY_LINES {
+            let more = format!("\u{2026} {} more lines", total - CARD_BODY_LINES);
+            push_indented(lines, ChatLine::simple(more, LineStyle::Dim), &body_indent);
         }
-        spans.push(Span {
-            text: bullet.to_string(),
-            fg: Some(Color::Cyan),
-            bg: None,
-            bold: false,
-            dim: false,
-        });
-        spans.extend(parse_inline_markdown(rest));
-        return spans;
     }
+}
 
-    // Regular line — parse inline markdown
-    parse_inline_markdown(line)
+/// What an expanded tool card shows. File edits show what they change
+/// (also while awaiting approval); everything else, and failed edits, show
+/// the result text.
+fn card_body(
+    name: &str,
+    input: &serde_json::Value,
+    start_lines: &[Option<usize>],
+    outcome: Option<ToolOutcome>,
+    width: usize,
+    theme: &Theme,
+) -> Layout {
+    let is_error = outcome.map(|o| o.is_error).unwrap_or(false);
+    if !is_error {
+        if let Some(diff) = diff::tool_diff(name, input, start_lines).filter(|d| !d.is_empty()) {
+            return Layout {
+                lines: diff_chat_lines(&diff, DIFF_CARD_LINES, theme),
+                diff_stats: Some(diff::diff_stats(&diff)),
+            };
+        }
+    }
+    let lines = match outcome {
+        Some(outcome) => {
+            let text = types::extract_tool_result_text(outcome.content);
+            let lang = if is_error { None } else { result_lang(name, input, &text) };
+            result_lines(text.trim_end(), lang, width, is_error, theme)
+        }
+        None => Vec::new(),
+    };
+    Layout {
+        lines,
+        diff_stats: None,
+    }
 }
 
-fn parse_inline_markdown(text: &str) -> Vec<Span> {
-    let mut spans = Vec::new();
-    let mut buf = String::new();
-    let chars: Vec<char> = text.chars().collect();
-    let len = chars.len();
-    let mut i = 0;
+/// Language of a tool result worth highlighting: the file a `Read`
+/// returned, diffs from git, JSON output
+fn result_lang(name: &str, input: &serde_json::Value, text: &str) -> Option<Lang> {
+    let command = input.get("command").and_then(|v| v.as_str()).unwrap_or("");
+    if name == "Read" {
+        return input
+            .get("file_path")
+            .and_then(|v| v.as_str())
+            .and_then(Lang::from_path);
+    }
+    if name == "Bash" && ["git diff", "git show", "diff "].iter().any(|c| command.starts_with(c)) {
+        return Some(Lang::Diff);
+    }
+    let trimmed = text.trim_start();
+    if (trimmed.starts_with('{') || trimmed.starts_with('['))
+        && serde_json::from_str::<serde_json::Value>(text).is_ok()
+    {
+        return Some(Lang::Json);
+    }
+    None
+}
 
-    while i < len {
-        // Bold: **text**
-        if i + 1 < len && chars[i] == '*' && chars[i + 1] == '*' {
-            if !buf.is_empty() {
-                spans.push(Span::plain(std::mem::take(&mut buf)));
-            }
-            i += 2;
-            let mut bold_text = String::new();
-            while i + 1 < len && !(chars[i] == '*' && chars[i + 1] == '*') {
-                bold_text.push(chars[i]);
-                i += 1;
-            }
-            if i + 1 < len {
-                i += 2; // skip closing **
-            }
+/// Result text as card body lines, highlighted when the language is known.
+/// `Read` output keeps its line-number gutter out of the highlighter.
+fn result_lines(
+    text: &str,
+    lang: Option<Lang>,
+    width: usize,
+    is_error: bool,
+    theme: &Theme,
+) -> Vec<ChatLine> {
+    if text.is_empty() {
+        return Vec::new();
+    }
+    let lang = match lang {
+        Some(lang) => lang,
+        None => {
+            let style = if is_error {
+                LineStyle::Error
+            } else {
+                LineStyle::ToolResult
+            };
+            return text
+                .split('\n')
+                .flat_map(|l| wrap_text(l, width))
+                .map(|l| ChatLine::simple(l, style.clone()))
+                .collect();
+        }
+    };
+    let mut state = HighlightState::default();
+    let mut lines = Vec::new();
+    for raw_line in text.split('\n') {
+        let (gutter, code) = split_line_number(raw_line);
+        let mut spans = Vec::new();
+        if let Some(gutter) = gutter {
             spans.push(Span {
-                text: bold_text,
-                fg: None,
+                text: gutter,
+                fg: theme.diff_gutter.fg,
                 bg: None,
-                bold: true,
+                bold: false,
                 dim: false,
+                ..Default::default()
             });
-            continue;
         }
+        spans.extend(highlighted_spans(lang, code, &mut state, theme, None));
+        for spans in wrap_spans(&spans, width) {
+            lines.push(ChatLine {
+                spans,
+                base_style: LineStyle::ToolResult,
+                fold: None,
+            });
+        }
+    }
+    lines
+}
 
-        // Inline code: `text`
-        if chars[i] == '`' {
-            if !buf.is_empty() {
-                spans.push(Span::plain(std::mem::take(&mut buf)));
+/// Split off a `cat -n` style line number (`    12→code`, `    12\tcode`)
+fn split_line_number(line: &str) -> (Option<String>, &str) {
+    let trimmed = line.trim_start();
+    let digits = trimmed
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(trimmed.len());
+    if digits > 0 {
+        for sep in ['\u{2192}', '\t'] {
+            if let Some(code) = trimmed[digits..].strip_prefix(sep) {
+                let number = &line[..line.len() - trimmed.len() + digits];
+                return (Some(format!("{} ", number)), code);
             }
-            i += 1;
-            let mut code_text = String::new();
-            while i < len && chars[i] != '`' {
-                code_text.push(chars[i]);
-                i += 1;
+        }
+    }
+    (None, line)
+}
+
+/// A diff with a line-number gutter, removals in red and additions in
+/// green; past `max_lines` the rest is folded into a count
+fn diff_chat_lines(diff: &[DiffLine], max_lines: usize, theme: &Theme) -> Vec<ChatLine> {
+    let gutter = diff
+        .iter()
+        .filter_map(|l| l.number())
+        .max()
+        .map(|n| n.to_string().len())
+        .unwrap_or(0);
+    let mut lines = Vec::new();
+    for line in diff.iter().take(max_lines) {
+        let (sign, text, fg) = match line {
+            DiffLine::Hunk(header) => {
+                lines.push(ChatLine {
+                    spans: vec![Span {
+                        text: header.clone(),
+                        fg: theme.diff_hunk.fg,
+                        bg: None,
+                        bold: false,
+                        dim: false,
+                        ..Default::default()
+                    }],
+                    base_style: LineStyle::Normal,
+                    fold: None,
+                });
+                continue;
             }
-            if i < len {
-                i += 1; // skip closing `
+            DiffLine::Skipped(n) => {
+                let text = format!(
+                    "\u{22ef} {} unchanged line{}",
+                    n,
+                    if *n == 1 { "" } else { "s" }
+                );
+                lines.push(ChatLine::simple(text, LineStyle::Dim));
+                continue;
             }
+            DiffLine::Context { text, .. } => (' ', text, None),
+            DiffLine::Removed { text, .. } => ('-', text, theme.diff_removed.fg),
+            DiffLine::Added { text, .. } => ('+', text, theme.diff_added.fg),
+        };
+        let mut spans = Vec::new();
+        // Edits that couldn't be located in the file have no numbers
+        if gutter > 0 {
+            let number = line.number().map(|n| n.to_string()).unwrap_or_default();
             spans.push(Span {
-                text: code_text,
-                fg: Some(Color::Yellow),
-                bg: Some(Color::Rgb { r: 40, g: 40, b: 40 }),
+                text: format!("{:>w$} ", number, w = gutter),
+                fg: theme.diff_gutter.fg,
+                bg: None,
                 bold: false,
                 dim: false,
+                ..Default::default()
             });
-            continue;
         }
+        spans.push(Span {
+            text: format!("{}{}", sign, text.replace('\t', "    ")),
+            fg,
+            bg: None,
+            bold: false,
+            dim: false,
+            ..Default::default()
+        });
+        lines.push(ChatLine {
+            spans,
+            base_style: LineStyle::Normal,
+            fold: None,
+        });
+    }
+    if diff.len() > max_lines {
+        let more = format!("\u{2026} {} more lines", diff.len() - max_lines);
+        lines.push(ChatLine::simple(more, LineStyle::Dim));
+    }
+    lines
+}
 
-        buf.push(chars[i]);
-        i += 1;
+/// A result whose call isn't in the transcript, drawn on its own
+fn push_orphan_result(
+    lines: &mut ChatLines,
+    ctx: &ChatCtx,
+    content: &serde_json::Value,
+    is_error: bool,
+    indent: &str,
+) {
+    let width = ctx.width.saturating_sub(UnicodeWidthStr::width(indent)).max(10);
+    if ctx.session.tool_results_collapsed {
+        push_indented(
+            lines,
+            ChatLine::simple("[result hidden]".to_string(), LineStyle::Dim),
+            indent,
+        );
+        return;
     }
+    let text = types::extract_tool_result_text(content);
+    if !text.is_empty() {
+        let truncated = truncate_chars(&text, 500);
+        let style = if is_error {
+            LineStyle::Error
+        } else {
+            LineStyle::ToolResult
+        };
+        for line in wrap_text(&truncated, width) {
+            push_indented(lines, ChatLine::simple(line, style.clone()), indent);
+        }
+    }
+}
 
-    if !buf.is_empty() {
-        spans.push(Span::plain(buf));
+/// A subagent thread under its Task call: a summary header (the fold
+/// target) and, when expanded, its messages indented one level deeper
+fn push_thread_lines(lines: &mut ChatLines, ctx: &mut ChatCtx, id: &str, indent: &str) {
+    let children = ctx.threads.get(id).cloned().unwrap_or_default();
+    let fold_id = thread_fold_id(id);
+    let collapsed = ctx.session.is_fold_collapsed(&fold_id, true);
+    let messages = children.iter().filter(|(_, m)| !m.is_tool_results()).count();
+    let tool_calls = children
+        .iter()
+        .flat_map(|(_, m)| m.content_blocks.iter().flatten())
+        .filter(|b| matches!(b, ContentBlock::ToolUse { .. }))
+        .count();
+
+    let mut header = format!(
+        "{} {} message{} \u{00b7} {} tool call{}",
+        if collapsed { "\u{25b8}" } else { "\u{25be}" },
+        messages,
+        if messages == 1 { "" } else { "s" },
+        tool_calls,
+        if tool_calls == 1 { "" } else { "s" },
+    );
+    if let Some((tool, elapsed)) = ctx.session.thread_progress.get(id) {
+        header.push_str(&format!(" \u{00b7} running {} {:.1}s", tool, elapsed));
+    } else if collapsed {
+        if let Some(last) = children.iter().rev().find_map(|(_, m)| thread_activity(m)) {
+            header.push_str(&format!(" \u{00b7} {}", last));
+        }
+    }
+    let width = ctx.width.saturating_sub(UnicodeWidthStr::width(indent) + 2);
+    let line = ChatLine::simple(truncate_to_width(&header, width), LineStyle::Dim).with_fold(&fold_id);
+    push_indented(lines, line, &format!("{}  ", indent));
+
+    if !collapsed {
+        let child_indent = format!("{}  \u{2502} ", indent);
+        for (i, msg) in children {
+            push_message_lines(lines, ctx, i, msg, &child_indent);
+        }
     }
+}
+
+/// One-line description of what a thread message did
+fn thread_activity(msg: &ChatMessage) -> Option<String> {
+    let last_tool = msg.content_blocks.iter().flatten().rev().find_map(|b| match b {
+        ContentBlock::ToolUse { name, input, .. } => {
+            Some(format!("[{}] {}", name, types::format_tool_summary(name, input)))
+        }
+        _ => None,
+    });
+    last_tool.or_else(|| {
+        msg.content
+            .lines()
+            .find(|l| !l.trim().is_empty())
+            .map(|l| l.trim().to_string())
+    })
+}
 
+// ─── Styled Spans ───────────────────────────────────────────────────────────
+
+pub(super) fn highlighted_spans(
+    lang: Lang,
+    line: &str,
+    state: &mut HighlightState,
+    theme: &Theme,
+    bg: Option<Color>,
+) -> Vec<Span> {
+    let spans: Vec<Span> = highlight::highlight_line(lang, &line.replace('\t', "    "), state)
+        .into_iter()
+        .map(|(token, text)| Span {
+            text,
+            fg: highlight::token_color(token, theme),
+            bg,
+            bold: false,
+            dim: false,
+            ..Default::default()
+        })
+        .collect();
     if spans.is_empty() {
-        spans.push(Span::plain(String::new()));
+        // Keep blank code lines styled like the rest of the block
+        return vec![Span {
+            text: String::new(),
+            fg: None,
+            bg,
+            bold: false,
+            dim: false,
+            ..Default::default()
+        }];
     }
-
     spans
 }
 
+/// Hard-wrap spans at `width` columns, keeping each piece's style
+pub(super) fn wrap_spans(spans: &[Span], width: usize) -> Vec<Vec<Span>> {
+    let mut lines = vec![Vec::new()];
+    let mut col = 0;
+    for span in spans {
+        let mut piece = String::new();
+        for ch in span.text.chars() {
+            let ch_w = unicode_width::UnicodeWidthChar::width(ch).unwrap_or(0);
+            if width > 0 && col + ch_w > width && col > 0 {
+                if !piece.is_empty() {
+                    lines.last_mut().unwrap().push(Span {
+                        text: std::mem::take(&mut piece),
+                        ..span.clone()
+                    });
+                }
+                lines.push(Vec::new());
+                col = 0;
+            }
+            piece.push(ch);
+            col += ch_w;
+        }
+        if !piece.is_empty() || span.text.is_empty() {
+            lines.last_mut().unwrap().push(Span {
+                text: piece,
+                ..span.clone()
+            });
+        }
+    }
+    lines
+}
+
+/// Draw the visible chat lines; returns the index of the top one
 fn render_chat_area(
-    stdout: &mut impl Write,
-    lines: &[ChatLine],
-    chat_height: usize,
-    width: usize,
-    x_offset: usize,
-    scroll_offset: usize,
+    frame: &mut Frame,
+    lines: &ChatLines,
+    area: Rect,
+    session: Option<&Session>,
     search: &Option<crate::app::SearchState>,
-) -> anyhow::Result<()> {
+    theme: &Theme,
+) -> usize {
+    let Rect {
+        x: x_offset,
+        y: y_offset,
+        width,
+        height: chat_height,
+    } = area;
+    let scroll_offset = session.map(|s| s.scroll_offset).unwrap_or(0);
+    let selected_fold = session.and_then(|s| s.selected_fold.as_deref());
     let total = lines.len();
     let clamped_offset = scroll_offset.min(total.saturating_sub(1));
     let end = total.saturating_sub(clamped_offset);
     let start = end.saturating_sub(chat_height);
-    let visible = &lines[start..end];
+    let visible = lines.window(start, end);
 
     // Determine search match lines for highlighting
     let search_match_lines: Vec<usize> = search
@@ -729,59 +1775,37 @@ fn render_chat_area(
         .and_then(|s| s.matches.get(s.current_match).copied());
 
     for (i, line) in visible.iter().enumerate() {
-        let row = i as u16;
+        let row = (y_offset + i) as u16;
         let abs_line = start + i;
-        queue!(stdout, cursor::MoveTo(x_offset as u16, row))?;
+        draw!(frame, cursor::MoveTo(x_offset as u16, row));
 
         // Check if this line is a search match
         let is_match = search_match_lines.contains(&abs_line);
         let is_current_match = current_match_line == Some(abs_line);
 
+        if line.fold.is_some() && line.fold.as_deref() == selected_fold {
+            draw!(frame, SetAttribute(Attribute::Reverse));
+        }
+
         if is_current_match {
-            queue!(stdout, SetBackgroundColor(Color::Yellow), SetForegroundColor(Color::Black))?;
+            draw!(frame, theme.search_current);
         } else if is_match {
-            queue!(stdout, SetBackgroundColor(Color::DarkYellow), SetForegroundColor(Color::Black))?;
+            draw!(frame, theme.search_match);
         }
 
         // Render spans
-        if line.spans.len() == 1 && line.spans[0].fg.is_none() && line.spans[0].bg.is_none() && !line.spans[0].bold && !line.spans[0].dim {
+        if line.spans.len() == 1 && line.spans[0].is_plain() {
             // Simple single-span line — use base_style (backward compat)
             if !is_match && !is_current_match {
-                match line.base_style {
-                    LineStyle::User => {
-                        queue!(stdout, SetForegroundColor(Color::Green), SetAttribute(Attribute::Bold))?;
-                    }
-                    LineStyle::Assistant => {
-                        queue!(stdout, SetForegroundColor(Color::White))?;
-                    }
-                    LineStyle::System => {
-                        queue!(stdout, SetForegroundColor(Color::Yellow))?;
-                    }
-                    LineStyle::Tool => {
-                        queue!(stdout, SetForegroundColor(Color::Cyan))?;
-                    }
-                    LineStyle::ToolResult => {
-                        queue!(stdout, SetForegroundColor(Color::DarkGrey))?;
-                    }
-                    LineStyle::Streaming => {
-                        queue!(stdout, SetForegroundColor(Color::White))?;
-                    }
-                    LineStyle::Error => {
-                        queue!(stdout, SetForegroundColor(Color::Red))?;
-                    }
-                    LineStyle::Dim => {
-                        queue!(stdout, SetForegroundColor(Color::DarkGrey))?;
-                    }
-                    LineStyle::Normal => {}
-                }
+                draw!(frame, line.base_style.style(theme));
             }
             let display = truncate_to_width(&line.text(), width);
-            queue!(
-                stdout,
+            draw!(
+                frame,
                 Print(format!("{:width$}", display, width = width)),
                 ResetColor,
                 SetAttribute(Attribute::Reset),
-            )?;
+            );
         } else {
             // Multi-span line — render each span
             let truncated = truncate_spans(&line.spans, width);
@@ -789,102 +1813,154 @@ fn render_chat_area(
             for span in &truncated {
                 if !is_match && !is_current_match {
                     if let Some(fg) = span.fg {
-                        queue!(stdout, SetForegroundColor(fg))?;
+                        draw!(frame, SetForegroundColor(fg));
                     }
                     if let Some(bg) = span.bg {
-                        queue!(stdout, SetBackgroundColor(bg))?;
+                        draw!(frame, SetBackgroundColor(bg));
                     }
                 }
                 if span.bold {
-                    queue!(stdout, SetAttribute(Attribute::Bold))?;
+                    draw!(frame, SetAttribute(Attribute::Bold));
                 }
                 if span.dim {
-                    queue!(stdout, SetAttribute(Attribute::Dim))?;
+                    draw!(frame, SetAttribute(Attribute::Dim));
+                }
+                if span.italic {
+                    draw!(frame, SetAttribute(Attribute::Italic));
+                }
+                if span.underline {
+                    draw!(frame, SetAttribute(Attribute::Underlined));
+                }
+                if span.strike {
+                    draw!(frame, SetAttribute(Attribute::CrossedOut));
+                }
+                match &span.link {
+                    Some(url) => draw!(frame, SetLink(Some(url.clone())), Print(&span.text), SetLink(None)),
+                    None => draw!(frame, Print(&span.text)),
                 }
-                queue!(stdout, Print(&span.text))?;
                 printed_width += UnicodeWidthStr::width(span.text.as_str());
-                if span.bold || span.dim {
-                    queue!(stdout, SetAttribute(Attribute::Reset))?;
+                if span.bold || span.dim || span.italic || span.underline || span.strike {
+                    draw!(frame, SetAttribute(Attribute::Reset));
                     // Re-apply search bg if needed
                     if is_current_match {
-                        queue!(stdout, SetBackgroundColor(Color::Yellow), SetForegroundColor(Color::Black))?;
+                        draw!(frame, theme.search_current);
                     } else if is_match {
-                        queue!(stdout, SetBackgroundColor(Color::DarkYellow), SetForegroundColor(Color::Black))?;
+                        draw!(frame, theme.search_match);
                     }
                 }
-                if !is_match && !is_current_match {
-                    if span.fg.is_some() || span.bg.is_some() {
-                        queue!(stdout, ResetColor)?;
-                    }
+                if !is_match && !is_current_match && (span.fg.is_some() || span.bg.is_some()) {
+                    draw!(frame, ResetColor);
                 }
             }
             // Pad remaining width
             if printed_width < width {
-                queue!(stdout, Print(format!("{:w$}", "", w = width - printed_width)))?;
+                draw!(frame, Print(format!("{:w$}", "", w = width - printed_width)));
             }
-            queue!(stdout, ResetColor, SetAttribute(Attribute::Reset))?;
+            draw!(frame, ResetColor, SetAttribute(Attribute::Reset));
         }
     }
 
     // Clear remaining chat area lines
     let rendered = visible.len();
     for i in rendered..chat_height {
-        let row = i as u16;
-        queue!(
-            stdout,
+        let row = (y_offset + i) as u16;
+        draw!(
+            frame,
             cursor::MoveTo(x_offset as u16, row),
             Print(format!("{:width$}", "", width = width)),
-        )?;
+        );
     }
-
-    Ok(())
+    start
 }
 
 // ─── Permission Banner ──────────────────────────────────────────────────────
 
-fn build_permission_banner(session: &Session, _width: usize) -> usize {
-    if let Some(perm) = &session.pending_permission {
-        let mut count = 2; // header + key hints
-        if perm.tool_name == "Edit" {
+/// Max editor rows shown while editing a tool input in the permission banner
+const PERM_EDITOR_ROWS: usize = 10;
+
+fn build_permission_banner(session: &Session, _width: usize, theme: &Theme) -> usize {
+    if let Some(perm) = session.pending_permissions.front() {
+        match &session.permission_prompt {
+            Some(PermissionPrompt::EditInput(input)) => {
+                return input.line_count().min(PERM_EDITOR_ROWS) + 2; // header + editor + hints
+            }
+            Some(PermissionPrompt::DenyReason(_)) => return 3, // header + input + hints
+            Some(PermissionPrompt::ConfirmAll) => return 2, // question + hints
+            None => {}
+        }
+        let mut count = 3; // header + always-allow rule + key hints
+        if let Some(diff) = banner_diff(session, perm) {
             if perm.input.get("file_path").is_some() { count += 1; }
-            if perm.input.get("old_string").is_some() { count += 1; }
-            if perm.input.get("new_string").is_some() { count += 1; }
-        } else if perm.tool_name == "Write" || perm.tool_name == "Bash" {
-            count += 1;
+            count += diff_chat_lines(&diff, BANNER_DIFF_LINES, theme).len();
         } else {
             count += 1;
         }
+        count += perm.permission_suggestions.as_ref().map(|s| s.len().min(9)).unwrap_or(0);
         count
     } else {
         0
     }
 }
 
+/// Render the permission banner, recording where its buttons went. Returns
+/// the cursor position when an inline prompt is open.
 fn render_permission_banner(
-    stdout: &mut impl Write,
+    frame: &mut Frame,
+    theme: &Theme,
     session: &Session,
     start_row: u16,
     width: usize,
     x_offset: usize,
-) -> anyhow::Result<()> {
-    if let Some(perm) = &session.pending_permission {
-        let mut lines: Vec<String> = Vec::new();
-        lines.push(format!("\u{2502} Permission: {}", perm.tool_name));
-
-        // Show edit diff for Edit tool
-        if perm.tool_name == "Edit" {
-            if let Some(file_path) = perm.input.get("file_path").and_then(|v| v.as_str()) {
-                lines.push(format!("\u{2502} File: {}", file_path));
+    buttons: &mut Vec<(u16, std::ops::Range<u16>, char)>,
+) -> Option<(u16, u16)> {
+    if let Some(perm) = session.pending_permissions.front() {
+        match &session.permission_prompt {
+            Some(PermissionPrompt::EditInput(input)) => {
+                let pos = render_permission_editor(
+                    frame,
+                    theme,
+                    &perm.tool_name,
+                    input,
+                    start_row,
+                    width,
+                    x_offset,
+                );
+                return Some(pos);
             }
-            if let Some(old) = perm.input.get("old_string").and_then(|v| v.as_str()) {
-                let preview = truncate_chars(old, width.saturating_sub(6));
-                lines.push(format!("\u{2502} - {}", preview));
+            Some(PermissionPrompt::DenyReason(input)) => {
+                let pos = render_deny_reason_prompt(
+                    frame,
+                    theme,
+                    &perm.tool_name,
+                    input,
+                    start_row,
+                    width,
+                    x_offset,
+                );
+                return Some(pos);
             }
-            if let Some(new) = perm.input.get("new_string").and_then(|v| v.as_str()) {
-                let preview = truncate_chars(new, width.saturating_sub(6));
-                lines.push(format!("\u{2502} + {}", preview));
+            Some(PermissionPrompt::ConfirmAll) => {
+                render_confirm_all_prompt(
+                    frame, theme, session, start_row, width, x_offset, buttons,
+                );
+                return None;
             }
-        } else if perm.tool_name == "Write" {
+            None => {}
+        }
+
+        let mut lines: Vec<String> = Vec::new();
+        let mut header = format!("\u{2502} Permission: {}", perm.tool_name);
+        if session.pending_permissions.len() > 1 {
+            header.push_str(&format!("  (1 of {})", session.pending_permissions.len()));
+        }
+        if let Some(agent) = &perm.agent_id {
+            header.push_str(&format!("  agent: {}", agent));
+        }
+        lines.push(header);
+
+        // File edits show the diff they'll apply
+        let diff = banner_diff(session, perm);
+        if diff.is_some() {
             if let Some(file_path) = perm.input.get("file_path").and_then(|v| v.as_str()) {
                 lines.push(format!("\u{2502} File: {}", file_path));
             }
@@ -901,93 +1977,323 @@ fn render_permission_banner(
             lines.push(format!("\u{2502} {}", truncate_chars(desc, width.saturating_sub(4))));
         }
 
-        lines.push("\u{2502} [Y]es  [N]o  [A]lways allow".to_string());
+        let diff_rows = diff
+            .map(|d| diff_chat_lines(&d, BANNER_DIFF_LINES, theme))
+            .unwrap_or_default();
+        let diff_start = lines.len();
+
+        // CLI-suggested permission updates, selectable with 1-9
+        let suggestions = perm.permission_suggestions.as_deref().unwrap_or(&[]);
+        let mut tail = Vec::new();
+        for (i, suggestion) in suggestions.iter().take(9).enumerate() {
+            tail.push(format!(
+                "\u{2502} {}. {}",
+                i + 1,
+                types::format_permission_suggestion(suggestion)
+            ));
+        }
+
+        // What a/A would save, so a broad rule is never a surprise
+        let rule = PermissionRule::allow_for(&perm.tool_name, &perm.input);
+        tail.push(format!("\u{2502} [a]/[A] always allows: {}", rule));
+
+        // Each hint is also a button pressing its key
+        let mut keys = vec![
+            ('y', "[y]es"),
+            ('n', "[n]o"),
+            ('D', "[D]eny with message"),
+            ('E', "[E]dit"),
+            ('a', "[a]lways (session)"),
+            ('A', "[A]lways (global)"),
+        ];
+        let all_label = format!("[B]ulk ({})", session.pending_permissions.len());
+        if session.pending_permissions.len() > 1 {
+            keys.push(('B', all_label.as_str()));
+        }
+        let hint_row = start_row + (lines.len() + diff_rows.len() + tail.len()) as u16;
+        let mut hints = "\u{2502} ".to_string();
+        for (key, label) in keys {
+            let start = x_offset + UnicodeWidthStr::width(hints.as_str());
+            let end = (start + UnicodeWidthStr::width(label)).min(x_offset + width);
+            if start < end {
+                buttons.push((hint_row, start as u16..end as u16, key));
+            }
+            hints.push_str(label);
+            hints.push_str("  ");
+        }
+        if !suggestions.is_empty() {
+            hints.push_str(&format!("[1-{}] Apply suggestion", suggestions.len().min(9)));
+        }
+        tail.push(hints.trim_end().to_string());
 
-        for (i, line) in lines.iter().enumerate() {
+        for (i, line) in lines.iter().chain(&tail).enumerate() {
+            // The diff goes between the description and the suggestions
+            let i = if i < diff_start { i } else { i + diff_rows.len() };
             let row = start_row + i as u16;
+            // Suggestion rows are buttons too
+            let suggestion = i.checked_sub(lines.len() + diff_rows.len());
+            if let Some(n) = suggestion.filter(|n| *n < suggestions.len().min(9)) {
+                let key = char::from(b'1' + n as u8);
+                buttons.push((row, x_offset as u16..(x_offset + width) as u16, key));
+            }
             let display = truncate_to_width(line, width);
-            queue!(
-                stdout,
+            draw!(
+                frame,
                 cursor::MoveTo(x_offset as u16, row),
-                SetBackgroundColor(Color::DarkYellow),
-                SetForegroundColor(Color::Black),
+                theme.banner,
                 Print(format!("{:width$}", display, width = width)),
                 ResetColor,
-            )?;
+            );
+        }
+        for (i, line) in diff_rows.iter().enumerate() {
+            let row = start_row + (diff_start + i) as u16;
+            render_banner_diff_line(frame, theme, line, row, width, x_offset);
+        }
+    }
+    None
+}
+
+/// Diff rows shown in the permission banner before folding the rest
+const BANNER_DIFF_LINES: usize = 12;
+
+/// The diff of a pending file edit, if it is one
+fn banner_diff(session: &Session, perm: &PendingPermission) -> Option<Vec<DiffLine>> {
+    let start_lines = session.edit_start_lines.get(&perm.tool_use_id);
+    let start_lines = start_lines.map(Vec::as_slice).unwrap_or(&[]);
+    diff::tool_diff(&perm.tool_name, &perm.input, start_lines).filter(|d| !d.is_empty())
+}
+
+/// One diff row: the banner's edge, then the colored diff on the normal
+/// background so red and green stay readable
+fn render_banner_diff_line(
+    frame: &mut Frame,
+    theme: &Theme,
+    line: &ChatLine,
+    row: u16,
+    width: usize,
+    x_offset: usize,
+) {
+    draw!(
+        frame,
+        cursor::MoveTo(x_offset as u16, row),
+        theme.banner,
+        Print("\u{2502}"),
+        ResetColor,
+        Print(" "),
+    );
+    let mut printed = 2;
+    for span in truncate_spans(&line.spans, width.saturating_sub(printed)) {
+        let fg = match (span.fg, &line.base_style) {
+            (Some(fg), _) => fg,
+            (None, LineStyle::Dim) => theme.dim.fg.unwrap_or(Color::Reset),
+            (None, _) => Color::Reset,
+        };
+        draw!(frame, SetForegroundColor(fg), Print(&span.text), ResetColor);
+        printed += UnicodeWidthStr::width(span.text.as_str());
+    }
+    if printed < width {
+        draw!(frame, Print(format!("{:w$}", "", w = width - printed)));
+    }
+}
+
+/// Inline JSON editor for the tool input. Scrolls to keep the cursor visible
+/// and returns its screen position.
+fn render_permission_editor(
+    frame: &mut Frame,
+    theme: &Theme,
+    tool_name: &str,
+    input: &crate::app::InputState,
+    start_row: u16,
+    width: usize,
+    x_offset: usize,
+) -> (u16, u16) {
+    let rows = input.line_count().min(PERM_EDITOR_ROWS);
+    let (cursor_line, cursor_col) = input.cursor_line_col();
+    let first_line = (cursor_line + 1).saturating_sub(rows);
+    let available = width.saturating_sub(2);
+    let h_scroll = (cursor_col + 1).saturating_sub(available);
+
+    let header = format!("\u{2502} Edit {} input (JSON)", tool_name);
+    let hints = "\u{2502} Enter approve  Ctrl+J newline  Esc cancel";
+    draw!(
+        frame,
+        cursor::MoveTo(x_offset as u16, start_row),
+        theme.banner,
+        Print(format!("{:width$}", truncate_to_width(&header, width), width = width)),
+        ResetColor,
+    );
+
+    for (i, line) in input.text.split('\n').skip(first_line).take(rows).enumerate() {
+        let visible: String = line.chars().skip(h_scroll).collect();
+        draw!(
+            frame,
+            cursor::MoveTo(x_offset as u16, start_row + 1 + i as u16),
+            theme.banner,
+            Print("\u{2502} "),
+            theme.banner_input,
+            Print(format!("{:w$}", truncate_to_width(&visible, available), w = available)),
+            ResetColor,
+        );
+    }
+
+    draw!(
+        frame,
+        cursor::MoveTo(x_offset as u16, start_row + 1 + rows as u16),
+        theme.banner,
+        Print(format!("{:width$}", truncate_to_width(hints, width), width = width)),
+        ResetColor,
+    );
+
+    let x = (x_offset + 2 + cursor_col - h_scroll) as u16;
+    let y = start_row + 1 + (cursor_line - first_line) as u16;
+    (x, y)
+}
+
+/// One-line prompt for the deny message. Returns the cursor position.
+fn render_deny_reason_prompt(
+    frame: &mut Frame,
+    theme: &Theme,
+    tool_name: &str,
+    input: &crate::app::InputState,
+    start_row: u16,
+    width: usize,
+    x_offset: usize,
+) -> (u16, u16) {
+    let available = width.saturating_sub(4);
+    let cursor_col = input.cursor_col();
+    let h_scroll = (cursor_col + 1).saturating_sub(available);
+    let visible: String = input.text.chars().skip(h_scroll).collect();
+
+    let lines = [
+        format!("\u{2502} Deny {} \u{2014} tell Claude what to do instead:", tool_name),
+        format!("\u{2502} > {}", truncate_to_width(&visible, available)),
+        "\u{2502} Enter deny  Esc cancel".to_string(),
+    ];
+    for (i, line) in lines.iter().enumerate() {
+        draw!(
+            frame,
+            cursor::MoveTo(x_offset as u16, start_row + i as u16),
+            theme.banner,
+            Print(format!("{:width$}", truncate_to_width(line, width), width = width)),
+            ResetColor,
+        );
+    }
+
+    let x = (x_offset + 4 + cursor_col - h_scroll) as u16;
+    (x, start_row + 1)
+}
+
+/// Confirmation before answering every queued request at once, naming the
+/// tools so requests that were never shown aren't approved blind
+fn render_confirm_all_prompt(
+    frame: &mut Frame,
+    theme: &Theme,
+    session: &Session,
+    start_row: u16,
+    width: usize,
+    x_offset: usize,
+    buttons: &mut Vec<(u16, std::ops::Range<u16>, char)>,
+) {
+    let mut tools: Vec<(&str, usize)> = Vec::new();
+    for perm in &session.pending_permissions {
+        match tools.iter_mut().find(|(name, _)| *name == perm.tool_name) {
+            Some((_, n)) => *n += 1,
+            None => tools.push((&perm.tool_name, 1)),
         }
     }
-    Ok(())
+    let tools: Vec<String> = tools
+        .into_iter()
+        .map(|(name, n)| if n > 1 { format!("{} \u{00d7}{}", name, n) } else { name.to_string() })
+        .collect();
+    let question = format!(
+        "\u{2502} Answer all {} queued requests? {}",
+        session.pending_permissions.len(),
+        tools.join(", ")
+    );
+
+    let hint_row = start_row + 1;
+    let mut hints = "\u{2502} ".to_string();
+    for (key, label) in [('a', "[a]pprove all"), ('d', "[d]eny all")] {
+        let start = x_offset + UnicodeWidthStr::width(hints.as_str());
+        let end = (start + UnicodeWidthStr::width(label)).min(x_offset + width);
+        if start < end {
+            buttons.push((hint_row, start as u16..end as u16, key));
+        }
+        hints.push_str(label);
+        hints.push_str("  ");
+    }
+    hints.push_str("Esc cancel");
+
+    for (i, line) in [question, hints].iter().enumerate() {
+        draw!(
+            frame,
+            cursor::MoveTo(x_offset as u16, start_row + i as u16),
+            theme.banner,
+            Print(format!("{:width$}", truncate_to_width(line, width), width = width)),
+            ResetColor,
+        );
+    }
 }
 
 // ─── Slash Command Menu ──────────────────────────────────────────────────────
 
 fn render_slash_menu(
-    stdout: &mut impl Write,
+    frame: &mut Frame,
     app: &App,
     menu_row: u16,
     width: usize,
     x_offset: usize,
-) -> anyhow::Result<()> {
+) {
     if !app.slash_menu.visible {
-        return Ok(());
+        return;
     }
 
     let items = app.slash_menu.filtered_items();
     let max_visible = 8.min(items.len());
     if max_visible == 0 {
-        return Ok(());
+        return;
     }
 
     // Render upward from the menu_row
-    for i in 0..max_visible {
+    for (i, item) in items.iter().enumerate().take(max_visible) {
         let row = menu_row.saturating_sub((max_visible - i) as u16);
-        let item = &items[i];
         let is_selected = i == app.slash_menu.selected;
 
         let prefix = if item.is_skill { "\u{2726} " } else { "/ " };
         let label = format!("{}{}", prefix, item.name);
         let display = truncate_to_width(&label, width.saturating_sub(2));
 
-        queue!(stdout, cursor::MoveTo(x_offset as u16, row))?;
+        draw!(frame, cursor::MoveTo(x_offset as u16, row));
 
         if is_selected {
-            queue!(
-                stdout,
-                SetBackgroundColor(Color::Rgb { r: 60, g: 60, b: 100 }),
-                SetForegroundColor(Color::White),
-                SetAttribute(Attribute::Bold),
-            )?;
+            draw!(frame, app.theme.menu_selected);
         } else {
-            queue!(
-                stdout,
-                SetBackgroundColor(Color::Rgb { r: 40, g: 40, b: 40 }),
-                SetForegroundColor(Color::Grey),
-            )?;
+            draw!(frame, app.theme.menu);
         }
 
-        queue!(
-            stdout,
+        draw!(
+            frame,
             Print(format!(" {:w$}", display, w = width.saturating_sub(1))),
             ResetColor,
             SetAttribute(Attribute::Reset),
-        )?;
+        );
     }
 
-    Ok(())
 }
 
 // ─── Question Overlay ────────────────────────────────────────────────────────
 
 fn render_question_overlay(
-    stdout: &mut impl Write,
+    frame: &mut Frame,
+    theme: &Theme,
     session: &Session,
     start_row: u16,
     width: usize,
     x_offset: usize,
-) -> anyhow::Result<()> {
+) {
     if let Some(q) = &session.pending_question {
         if q.questions.is_empty() {
-            return Ok(());
+            return;
         }
         let question = &q.questions[q.selected];
         let mut lines: Vec<String> = Vec::new();
@@ -1001,45 +2307,144 @@ fn render_question_overlay(
         for (i, line) in lines.iter().enumerate() {
             let row = start_row + i as u16;
             let display = truncate_to_width(line, width);
-            queue!(
-                stdout,
+            draw!(
+                frame,
                 cursor::MoveTo(x_offset as u16, row),
-                SetBackgroundColor(Color::Rgb { r: 30, g: 50, b: 80 }),
-                SetForegroundColor(Color::White),
+                theme.question,
                 Print(format!("{:width$}", display, width = width)),
                 ResetColor,
-            )?;
+            );
+        }
+    }
+}
+
+// ─── Inbox ──────────────────────────────────────────────────────────────────
+
+fn render_inbox(
+    frame: &mut Frame,
+    app: &App,
+    height: usize,
+    width: usize,
+    x_offset: usize,
+) {
+    let items = app.inbox_items();
+    let selected = app
+        .inbox
+        .as_ref()
+        .map(|i| i.selected)
+        .unwrap_or(0)
+        .min(items.len().saturating_sub(1));
+
+    let mut rows: Vec<String> = Vec::new();
+    for item in &items {
+        let session = match app.sessions.get(item.session_id()) {
+            Some(s) => s,
+            None => continue,
+        };
+        let detail = match item {
+            InboxItem::Permission { index, .. } => {
+                match session.pending_permissions.get(*index) {
+                    Some(perm) => {
+                        let mut text = format!(
+                            "{}: {}",
+                            perm.tool_name,
+                            types::format_tool_summary(&perm.tool_name, &perm.input)
+                        );
+                        if let Some(agent) = &perm.agent_id {
+                            text.push_str(&format!("  (agent: {})", agent));
+                        }
+                        text
+                    }
+                    None => continue,
+                }
+            }
+            InboxItem::Question { .. } => {
+                let question = session
+                    .pending_question
+                    .as_ref()
+                    .and_then(|q| q.questions.first())
+                    .map(|q| q.question.as_str())
+                    .unwrap_or("");
+                format!("Question: {}", question)
+            }
+        };
+        rows.push(format!("{}  {}", session.name, detail));
+    }
+
+    let header = format!(" Inbox ({} waiting) ", items.len());
+    let footer = " j/k Select  y Approve  n Deny  Enter Jump  Esc Close";
+
+    // Keep the selected row visible
+    let list_h = height.saturating_sub(2);
+    let first = if list_h > 0 && selected >= list_h {
+        selected + 1 - list_h
+    } else {
+        0
+    };
+
+    for i in 0..height {
+        let row = i as u16;
+        draw!(frame, cursor::MoveTo(x_offset as u16, row));
+        if i == 0 {
+            draw!(
+                frame,
+                app.theme.inbox_header,
+                Print(format!("{:width$}", truncate_to_width(&header, width), width = width)),
+                SetAttribute(Attribute::Reset),
+                ResetColor,
+            );
+        } else if i == height - 1 {
+            draw!(
+                frame,
+                app.theme.inbox_hint,
+                Print(format!("{:width$}", truncate_to_width(footer, width), width = width)),
+                ResetColor,
+            );
+        } else {
+            let idx = first + i - 1;
+            let text = rows.get(idx).map(|r| format!(" {}", r)).unwrap_or_default();
+            let display = truncate_to_width(&text, width);
+            if idx == selected && idx < rows.len() {
+                draw!(
+                    frame,
+                    app.theme.inbox_selected,
+                    Print(format!("{:width$}", display, width = width)),
+                    ResetColor,
+                );
+            } else {
+                draw!(frame, Print(format!("{:width$}", display, width = width)));
+            }
         }
     }
-    Ok(())
+
 }
 
 // ─── Task Panel ─────────────────────────────────────────────────────────────
 
 fn render_task_panel(
-    stdout: &mut impl Write,
+    frame: &mut Frame,
+    theme: &Theme,
     tasks: &[&crate::app::TaskItem],
     start_row: u16,
     panel_h: usize,
     width: usize,
     x_offset: usize,
-) -> anyhow::Result<()> {
+) {
     // Header
     let header = format!(" Tasks ({}) ", tasks.len());
     let header_display = truncate_to_width(&header, width);
-    queue!(
-        stdout,
+    draw!(
+        frame,
         cursor::MoveTo(x_offset as u16, start_row),
-        SetBackgroundColor(Color::Rgb { r: 40, g: 40, b: 60 }),
-        SetForegroundColor(Color::White),
+        theme.task_header,
         Print(format!("{:width$}", header_display, width = width)),
         ResetColor,
-    )?;
+    );
 
     // Task rows
     for i in 0..panel_h.saturating_sub(1) {
         let row = start_row + 1 + i as u16;
-        queue!(stdout, cursor::MoveTo(x_offset as u16, row))?;
+        draw!(frame, cursor::MoveTo(x_offset as u16, row));
 
         if i < tasks.len() {
             let task = tasks[i];
@@ -1052,49 +2457,48 @@ fn render_task_panel(
             let text = format!(" {} {}", icon, task.subject);
             let display = truncate_to_width(&text, width);
 
-            let color = match task.status {
-                TaskStatus::Pending => Color::Grey,
-                TaskStatus::InProgress => Color::Yellow,
-                TaskStatus::Completed => Color::Green,
-                TaskStatus::Deleted => Color::DarkGrey,
+            let style = match task.status {
+                TaskStatus::Pending => theme.task_pending,
+                TaskStatus::InProgress => theme.task_in_progress,
+                TaskStatus::Completed => theme.task_completed,
+                TaskStatus::Deleted => theme.task_deleted,
             };
 
-            queue!(
-                stdout,
-                SetForegroundColor(color),
+            draw!(
+                frame,
+                style,
                 Print(format!("{:width$}", display, width = width)),
                 ResetColor,
-            )?;
+            );
         } else {
-            queue!(
-                stdout,
+            draw!(
+                frame,
                 Print(format!("{:width$}", "", width = width)),
-            )?;
+            );
         }
     }
 
-    Ok(())
 }
 
 // ─── Input Line ─────────────────────────────────────────────────────────────
 
 fn render_input(
-    stdout: &mut impl Write,
+    frame: &mut Frame,
     app: &App,
     row: u16,
     width: usize,
     x_offset: usize,
     input_h: usize,
-) -> anyhow::Result<usize> {
+) -> usize {
     let prompt = if app.mode == Mode::Insert {
         "> "
     } else {
         "  "
     };
-    let prompt_color = if app.mode == Mode::Insert {
-        Color::Cyan
+    let prompt_style = if app.mode == Mode::Insert {
+        app.theme.prompt
     } else {
-        Color::DarkGrey
+        app.theme.prompt_inactive
     };
     let available = width.saturating_sub(prompt.len());
 
@@ -1103,7 +2507,7 @@ fn render_input(
 
     // First line uses horizontal scrolling
     {
-        let first_line = input_lines.first().map(|s| *s).unwrap_or("");
+        let first_line = input_lines.first().copied().unwrap_or("");
         let char_count = first_line.chars().count();
         let cursor_line = app.composer.cursor_line();
         let cursor_col = if cursor_line == 0 {
@@ -1126,71 +2530,70 @@ fn render_input(
 
     for line_idx in 0..input_h {
         let r = row + line_idx as u16;
-        queue!(stdout, cursor::MoveTo(x_offset as u16, r))?;
+        draw!(frame, cursor::MoveTo(x_offset as u16, r));
 
         if line_idx == 0 {
             // First line with prompt and horizontal scrolling
-            let first_line = input_lines.first().map(|s| *s).unwrap_or("");
+            let first_line = input_lines.first().copied().unwrap_or("");
             let display_text: String = first_line.chars().skip(scroll_start).take(available).collect();
-            queue!(
-                stdout,
-                SetForegroundColor(prompt_color),
+            draw!(
+                frame,
+                prompt_style,
                 Print(prompt),
                 ResetColor,
                 Print(format!("{:width$}", display_text, width = available)),
-            )?;
+            );
         } else if line_idx < input_lines.len() {
             // Subsequent lines (continuation)
             let line = input_lines[line_idx];
             let display: String = line.chars().take(available).collect();
-            queue!(
-                stdout,
-                SetForegroundColor(prompt_color),
+            draw!(
+                frame,
+                prompt_style,
                 Print("  "), // indent to match prompt width
                 ResetColor,
                 Print(format!("{:width$}", display, width = available)),
-            )?;
+            );
         } else {
             // Empty line (padding)
-            queue!(
-                stdout,
+            draw!(
+                frame,
                 Print(format!("{:width$}", "", width = width)),
-            )?;
+            );
         }
     }
 
-    Ok(scroll_start)
+    scroll_start
 }
 
 // ─── Status Bar ─────────────────────────────────────────────────────────────
 
 fn render_status_bar(
-    stdout: &mut impl Write,
+    frame: &mut Frame,
     app: &App,
     row: u16,
     width: usize,
-) -> anyhow::Result<()> {
-    queue!(stdout, cursor::MoveTo(0, row))?;
+) {
+    draw!(frame, cursor::MoveTo(0, row));
 
     if app.mode == Mode::Command {
         // Command mode: show command input
         let cmd_text = format!(":{}", app.command_input.text);
         let display = truncate_to_width(&cmd_text, width);
-        queue!(
-            stdout,
-            SetBackgroundColor(Color::DarkGrey),
-            SetForegroundColor(Color::White),
+        draw!(
+            frame,
+            app.theme.command_line,
             Print(format!("{:width$}", display, width = width)),
             ResetColor,
-        )?;
-        return Ok(());
+        );
+        return;
     }
 
     // Mode indicator
-    let (mode_text, mode_color) = match app.mode {
-        Mode::Normal => ("NORMAL", Color::Grey),
-        Mode::Insert => ("INSERT", Color::Green),
-        Mode::Command => ("COMMAND", Color::Yellow),
+    let (mode_text, mode_style) = match app.mode {
+        Mode::Normal => ("NORMAL", app.theme.mode_normal),
+        Mode::Insert => ("INSERT", app.theme.mode_insert),
+        Mode::Command => ("COMMAND", app.theme.mode_command),
     };
 
     let session = app.active_session();
@@ -1254,6 +2657,17 @@ fn render_status_bar(
     }
     right_parts.push(format!("{}/{}", session_idx, session_count));
 
+    // Requests waiting in other sessions
+    let waiting_elsewhere: usize = app
+        .sessions
+        .iter()
+        .filter(|(id, _)| app.active_session_id.as_deref() != Some(id.as_str()))
+        .map(|(_, s)| s.waiting_count())
+        .sum();
+    if waiting_elsewhere > 0 {
+        right_parts.push(format!("inbox {}", waiting_elsewhere));
+    }
+
     // Git branch + worktree badge for status bar
     let branch_display = session
         .filter(|s| !s.git_branch.is_empty())
@@ -1342,34 +2756,33 @@ fn render_status_bar(
     let right_w = UnicodeWidthStr::width(right.as_str());
     let padding = width.saturating_sub(left_mode_w + left_info_w + left_status_w + search_w + right_w);
 
-    let status_color = if is_running {
-        Color::Yellow
+    let bar = app.theme.status_bar;
+    let status_style = if is_running {
+        app.theme.status_running
     } else {
-        Color::White
+        Style::default()
     };
 
-    queue!(
-        stdout,
-        SetBackgroundColor(Color::Rgb { r: 30, g: 30, b: 30 }),
-        SetForegroundColor(mode_color),
-        SetAttribute(Attribute::Bold),
+    draw!(
+        frame,
+        bar,
+        mode_style,
         Print(&left_mode),
         SetAttribute(Attribute::Reset),
-        SetBackgroundColor(Color::Rgb { r: 30, g: 30, b: 30 }),
-        SetForegroundColor(Color::White),
+        bar,
         Print(&left_info),
-        SetForegroundColor(status_color),
+        status_style,
         Print(&left_status),
-        SetForegroundColor(Color::Cyan),
+        bar,
+        app.theme.status_search,
         Print(&search_display),
-        SetForegroundColor(Color::White),
+        bar,
         Print(format!("{:pad$}", "", pad = padding)),
-        SetForegroundColor(Color::DarkGrey),
+        app.theme.status_info,
         Print(truncate_to_width(&right, right_w.min(width.saturating_sub(left_mode_w + left_info_w + left_status_w)))),
         ResetColor,
-    )?;
+    );
 
-    Ok(())
 }
 
 /// Shorten model names for the status bar (e.g. "claude-sonnet-4-5-20250929" → "sonnet-4.5")
@@ -1421,6 +2834,50 @@ fn truncate_chars(s: &str, max_chars: usize) -> String {
     }
 }
 
+/// The last `max_chars` characters of `s`, with a leading "..." if cut
+fn tail_chars(s: &str, max_chars: usize) -> String {
+    let count = s.chars().count();
+    if count <= max_chars {
+        return s.to_string();
+    }
+    let start = s
+        .char_indices()
+        .nth(count - max_chars)
+        .map(|(i, _)| i)
+        .unwrap_or(0);
+    format!("...{}", &s[start..])
+}
+
+/// `4.2s`, or `3m07s` past a minute
+fn format_duration(secs: f64) -> String {
+    if secs < 60.0 {
+        format!("{:.1}s", secs)
+    } else {
+        let secs = secs as u64;
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    }
+}
+
+fn format_size(bytes: usize) -> String {
+    if bytes < 1024 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} KB", bytes as f64 / 1024.0)
+    }
+}
+
+/// Last lines of a partial tool input JSON, with string escapes undone so
+/// file contents being written read naturally
+fn partial_input_preview(partial: &str, max_lines: usize) -> Vec<String> {
+    let tail = tail_chars(partial, 2000)
+        .replace("\\n", "\n")
+        .replace("\\t", "    ")
+        .replace("\\\"", "\"");
+    let lines: Vec<&str> = tail.lines().filter(|l| !l.trim().is_empty()).collect();
+    let start = lines.len().saturating_sub(max_lines);
+    lines[start..].iter().map(|l| l.to_string()).collect()
+}
+
 fn wrap_text(text: &str, width: usize) -> Vec<String> {
     if width == 0 {
         return vec![text.to_string()];
@@ -1482,3 +2939,51 @@ fn wrap_text(text: &str, width: usize) -> Vec<String> {
 
     lines
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(direction: SplitDirection, ratio: u16) -> Split {
+        Split {
+            direction,
+            other: "s2".to_string(),
+            focused_first: true,
+            ratio,
+        }
+    }
+
+    fn area(width: usize, height: usize) -> Rect {
+        Rect { x: 10, y: 2, width, height }
+    }
+
+    /// (x, width) of both panes, or (y, height) when stacked
+    fn spans(area: Rect, split: &Split) -> Option<((usize, usize), (usize, usize))> {
+        let (first, second) = split_panes(area, split)?;
+        Some(match split.direction {
+            SplitDirection::Vertical => ((first.x, first.width), (second.x, second.width)),
+            SplitDirection::Horizontal => ((first.y, first.height), (second.y, second.height)),
+        })
+    }
+
+    #[test]
+    fn side_by_side_panes_leave_a_divider_column() {
+        let vertical = |ratio| split(SplitDirection::Vertical, ratio);
+        assert_eq!(spans(area(61, 10), &vertical(50)), Some(((10, 30), (41, 30))));
+        assert_eq!(spans(area(61, 10), &vertical(5)), Some(((10, 20), (31, 40))));
+        assert_eq!(spans(area(61, 10), &vertical(95)), Some(((10, 40), (51, 20))));
+        assert_eq!(spans(area(41, 10), &vertical(80)), Some(((10, 20), (31, 20))));
+        assert_eq!(spans(area(40, 10), &vertical(50)), None);
+        assert_eq!(spans(area(0, 10), &vertical(50)), None);
+    }
+
+    #[test]
+    fn stacked_panes_keep_their_minimum_height() {
+        let horizontal = |ratio| split(SplitDirection::Horizontal, ratio);
+        assert_eq!(spans(area(80, 20), &horizontal(50)), Some(((2, 10), (12, 10))));
+        assert_eq!(spans(area(80, 20), &horizontal(90)), Some(((2, 16), (18, 4))));
+        assert_eq!(spans(area(80, 20), &horizontal(10)), Some(((2, 4), (6, 16))));
+        assert_eq!(spans(area(80, 8), &horizontal(20)), Some(((2, 4), (6, 4))));
+        assert_eq!(spans(area(80, 7), &horizontal(50)), None);
+    }
+}
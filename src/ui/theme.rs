@@ -0,0 +1,746 @@
+//! Color themes. Everything the UI colors is drawn with a named style of
+//! the active theme: chat lines by kind, markdown and code, the sidebar,
+//! status bar, banners and overlays.
+//!
+//! There are three built-in themes (dark, light and high-contrast) and any
+//! number of custom ones in `~/.companion/themes/<name>.json`. A custom
+//! theme starts from a built-in one and replaces the styles it lists:
+//!
+//! ```json
+//! { "base": "light", "styles": { "user": { "fg": "#005f87", "bold": true } } }
+//! ```
+//!
+//! Themes may use any color; `fit` brings them down to what the terminal
+//! can show when the frame is drawn.
+
+use std::path::PathBuf;
+
+use crossterm::style::Color;
+use serde::{Deserialize, Serialize};
+
+use crate::ui::highlight::ColorDepth;
+
+// ─── Styles ─────────────────────────────────────────────────────────────────
+
+/// Colors and weight of one part of the UI. Drawn over what is already
+/// set, so a style without a background keeps the one beneath it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+}
+
+const fn fg(color: Color) -> Style {
+    Style {
+        fg: Some(color),
+        bg: None,
+        bold: false,
+    }
+}
+
+const fn on(fg: Color, bg: Color) -> Style {
+    Style {
+        fg: Some(fg),
+        bg: Some(bg),
+        bold: false,
+    }
+}
+
+const fn rgb(r: u8, g: u8, b: u8) -> Color {
+    Color::Rgb { r, g, b }
+}
+
+impl Style {
+    const fn bold(self) -> Style {
+        Style { bold: true, ..self }
+    }
+}
+
+// ─── Themes ─────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Theme {
+    #[serde(skip)]
+    pub name: String,
+
+    // Chat lines
+    pub normal: Style,
+    pub user: Style,
+    pub assistant: Style,
+    pub system: Style,
+    /// Tool card headers
+    pub tool: Style,
+    pub tool_result: Style,
+    /// Claude's reply while it streams in
+    pub streaming: Style,
+    pub error: Style,
+    /// Hints, folded counts and empty states
+    pub dim: Style,
+    pub search_match: Style,
+    pub search_current: Style,
+
+    // Diffs of file edits
+    pub diff_added: Style,
+    pub diff_removed: Style,
+    pub diff_hunk: Style,
+    /// Line numbers of diffs and `Read` results
+    pub diff_gutter: Style,
+
+    // Markdown
+    pub heading: Style,
+    pub list_marker: Style,
+    /// Rules, table borders and quote bars
+    pub rule: Style,
+    /// Code blocks: the background, and the text when the language is
+    /// unknown
+    pub code: Style,
+    /// The language tag above a code block
+    pub code_tag: Style,
+    pub inline_code: Style,
+    pub link: Style,
+    /// URLs printed after links on terminals without hyperlinks
+    pub link_url: Style,
+
+    // Syntax highlighting
+    pub code_plain: Style,
+    pub code_keyword: Style,
+    /// Types, builtin constants, lifetimes
+    pub code_type: Style,
+    pub code_string: Style,
+    pub code_number: Style,
+    pub code_comment: Style,
+    pub code_function: Style,
+    /// Macros, attributes, decorators, TOML tables
+    pub code_meta: Style,
+    /// JSON and TOML keys, shell variables
+    pub code_key: Style,
+
+    // Sidebar and panes
+    pub sidebar: Style,
+    /// Sessions with requests waiting
+    pub sidebar_waiting: Style,
+    /// The git line under a session
+    pub sidebar_git: Style,
+    pub sidebar_active: Style,
+    /// Over `sidebar_active`
+    pub sidebar_active_git: Style,
+    /// Sidebar edge and split divider
+    pub border: Style,
+    pub pane_title: Style,
+    pub pane_title_focused: Style,
+
+    // Status bar; the segments are drawn over `status_bar`
+    pub status_bar: Style,
+    pub mode_normal: Style,
+    pub mode_insert: Style,
+    pub mode_command: Style,
+    pub status_running: Style,
+    pub status_search: Style,
+    /// Model, context and session count on the right
+    pub status_info: Style,
+    /// The `:` command line
+    pub command_line: Style,
+    pub flash: Style,
+
+    // Banners and overlays
+    /// Permission banner, deny prompt and input editor frame
+    pub banner: Style,
+    /// The input editor's text field
+    pub banner_input: Style,
+    pub question: Style,
+    pub menu: Style,
+    pub menu_selected: Style,
+    pub inbox_header: Style,
+    pub inbox_hint: Style,
+    pub inbox_selected: Style,
+
+    // Task panel
+    pub task_header: Style,
+    pub task_pending: Style,
+    pub task_in_progress: Style,
+    pub task_completed: Style,
+    pub task_deleted: Style,
+
+    // Input line
+    pub prompt: Style,
+    pub prompt_inactive: Style,
+}
+
+/// Names of the built-in themes
+pub const BUILTIN: [&str; 3] = ["dark", "light", "high-contrast"];
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::dark()
+    }
+}
+
+impl Theme {
+    pub fn builtin(name: &str) -> Option<Theme> {
+        match name {
+            "dark" => Some(Theme::dark()),
+            "light" => Some(Theme::light()),
+            "high-contrast" => Some(Theme::high_contrast()),
+            _ => None,
+        }
+    }
+
+    /// For dark terminal backgrounds
+    pub fn dark() -> Theme {
+        Theme {
+            name: "dark".to_string(),
+
+            normal: Style::default(),
+            user: fg(Color::Green).bold(),
+            assistant: fg(Color::White),
+            system: fg(Color::Yellow),
+            tool: fg(Color::Cyan),
+            tool_result: fg(Color::DarkGrey),
+            streaming: fg(Color::White),
+            error: fg(Color::Red),
+            dim: fg(Color::DarkGrey),
+            search_match: on(Color::Black, Color::DarkYellow),
+            search_current: on(Color::Black, Color::Yellow),
+
+            diff_added: fg(Color::Green),
+            diff_removed: fg(Color::Red),
+            diff_hunk: fg(Color::Cyan),
+            diff_gutter: fg(Color::DarkGrey),
+
+            heading: fg(Color::Cyan).bold(),
+            list_marker: fg(Color::Cyan),
+            rule: fg(Color::DarkGrey),
+            code: on(Color::DarkYellow, rgb(30, 30, 30)),
+            code_tag: fg(Color::DarkGrey),
+            inline_code: fg(Color::Yellow),
+            link: fg(Color::Blue),
+            link_url: fg(Color::DarkGrey),
+
+            code_plain: fg(rgb(171, 178, 191)),
+            code_keyword: fg(rgb(198, 120, 221)),
+            code_type: fg(rgb(229, 192, 123)),
+            code_string: fg(rgb(152, 195, 121)),
+            code_number: fg(rgb(209, 154, 102)),
+            code_comment: fg(rgb(92, 99, 112)),
+            code_function: fg(rgb(97, 175, 239)),
+            code_meta: fg(rgb(86, 182, 194)),
+            code_key: fg(rgb(224, 108, 117)),
+
+            sidebar: fg(Color::Grey),
+            sidebar_waiting: fg(Color::Yellow),
+            sidebar_git: fg(Color::DarkGrey),
+            sidebar_active: on(Color::White, Color::DarkGrey).bold(),
+            sidebar_active_git: fg(Color::Grey),
+            border: fg(Color::DarkGrey),
+            pane_title: fg(Color::Grey),
+            pane_title_focused: on(Color::White, Color::DarkGrey).bold(),
+
+            status_bar: on(Color::White, rgb(30, 30, 30)),
+            mode_normal: fg(Color::Grey).bold(),
+            mode_insert: fg(Color::Green).bold(),
+            mode_command: fg(Color::Yellow).bold(),
+            status_running: fg(Color::Yellow),
+            status_search: fg(Color::Cyan),
+            status_info: fg(Color::DarkGrey),
+            command_line: on(Color::White, Color::DarkGrey),
+            flash: on(Color::Black, Color::DarkYellow),
+
+            banner: on(Color::Black, Color::DarkYellow),
+            banner_input: on(Color::White, rgb(40, 40, 40)),
+            question: on(Color::White, rgb(30, 50, 80)),
+            menu: on(Color::Grey, rgb(40, 40, 40)),
+            menu_selected: on(Color::White, rgb(60, 60, 100)).bold(),
+            inbox_header: on(Color::White, rgb(40, 40, 60)).bold(),
+            inbox_hint: fg(Color::DarkGrey),
+            inbox_selected: on(Color::White, Color::DarkGrey),
+
+            task_header: on(Color::White, rgb(40, 40, 60)),
+            task_pending: fg(Color::Grey),
+            task_in_progress: fg(Color::Yellow),
+            task_completed: fg(Color::Green),
+            task_deleted: fg(Color::DarkGrey),
+
+            prompt: fg(Color::Cyan),
+            prompt_inactive: fg(Color::DarkGrey),
+        }
+    }
+
+    /// For light terminal backgrounds: dark text, pale bars
+    pub fn light() -> Theme {
+        let ink = rgb(88, 88, 88);
+        let faint = rgb(138, 138, 138);
+        let orange = rgb(175, 95, 0);
+        let green = rgb(0, 135, 0);
+        let red = rgb(200, 0, 0);
+        let teal = rgb(0, 135, 175);
+        let bar = rgb(228, 228, 228);
+        let highlight = rgb(208, 208, 208);
+        let amber = rgb(255, 215, 95);
+        Theme {
+            name: "light".to_string(),
+
+            user: fg(green).bold(),
+            assistant: Style::default(),
+            system: fg(orange),
+            tool: fg(teal),
+            tool_result: fg(rgb(108, 108, 108)),
+            streaming: Style::default(),
+            error: fg(red),
+            dim: fg(faint),
+            search_match: on(Color::Black, rgb(255, 235, 160)),
+            search_current: on(Color::Black, amber),
+
+            diff_added: fg(green),
+            diff_removed: fg(red),
+            diff_hunk: fg(teal),
+            diff_gutter: fg(faint),
+
+            heading: fg(rgb(0, 95, 175)).bold(),
+            list_marker: fg(teal),
+            rule: fg(rgb(178, 178, 178)),
+            code: on(rgb(135, 95, 0), rgb(242, 242, 242)),
+            code_tag: fg(faint),
+            inline_code: fg(rgb(175, 0, 95)),
+            link: fg(rgb(0, 0, 200)),
+            link_url: fg(faint),
+
+            code_plain: fg(rgb(56, 58, 66)),
+            code_keyword: fg(rgb(166, 38, 164)),
+            code_type: fg(rgb(193, 132, 1)),
+            code_string: fg(rgb(80, 161, 79)),
+            code_number: fg(rgb(152, 104, 1)),
+            code_comment: fg(rgb(160, 161, 167)),
+            code_function: fg(rgb(64, 120, 242)),
+            code_meta: fg(rgb(1, 132, 188)),
+            code_key: fg(rgb(228, 86, 73)),
+
+            sidebar: fg(ink),
+            sidebar_waiting: fg(orange),
+            sidebar_git: fg(faint),
+            sidebar_active: on(Color::Black, highlight).bold(),
+            sidebar_active_git: fg(ink),
+            border: fg(rgb(188, 188, 188)),
+            pane_title: fg(ink),
+            pane_title_focused: on(Color::Black, highlight).bold(),
+
+            status_bar: on(Color::Black, bar),
+            mode_normal: fg(ink).bold(),
+            mode_insert: fg(green).bold(),
+            mode_command: fg(orange).bold(),
+            status_running: fg(orange),
+            status_search: fg(teal),
+            status_info: fg(rgb(108, 108, 108)),
+            command_line: on(Color::Black, highlight),
+            flash: on(Color::Black, amber),
+
+            banner: on(Color::Black, amber),
+            banner_input: on(Color::Black, rgb(255, 255, 255)),
+            question: on(Color::Black, rgb(210, 225, 245)),
+            menu: on(ink, rgb(235, 235, 235)),
+            menu_selected: on(Color::Black, rgb(200, 200, 240)).bold(),
+            inbox_header: on(Color::Black, rgb(215, 215, 235)).bold(),
+            inbox_hint: fg(faint),
+            inbox_selected: on(Color::Black, highlight),
+
+            task_header: on(Color::Black, rgb(215, 215, 235)),
+            task_pending: fg(ink),
+            task_in_progress: fg(orange),
+            task_completed: fg(green),
+            task_deleted: fg(rgb(160, 160, 160)),
+
+            prompt: fg(teal),
+            prompt_inactive: fg(rgb(160, 160, 160)),
+
+            ..Theme::dark()
+        }
+    }
+
+    /// Bright colors only, no greys, for dark backgrounds
+    pub fn high_contrast() -> Theme {
+        Theme {
+            name: "high-contrast".to_string(),
+
+            assistant: fg(Color::White),
+            system: fg(Color::Yellow).bold(),
+            tool: fg(Color::Cyan),
+            tool_result: fg(Color::Grey),
+            streaming: fg(Color::White),
+            error: fg(Color::Red).bold(),
+            dim: fg(Color::Grey),
+            search_match: on(Color::Black, Color::Cyan),
+            search_current: on(Color::Black, Color::Yellow),
+
+            diff_gutter: fg(Color::Grey),
+
+            heading: fg(Color::Yellow).bold(),
+            list_marker: fg(Color::Yellow),
+            rule: fg(Color::Grey),
+            code: on(Color::Yellow, Color::Black),
+            code_tag: fg(Color::Grey),
+            link: fg(Color::Cyan),
+            link_url: fg(Color::Grey),
+
+            code_plain: fg(Color::White),
+            code_keyword: fg(Color::Magenta),
+            code_type: fg(Color::Yellow),
+            code_string: fg(Color::Green),
+            code_number: fg(Color::Yellow),
+            code_comment: fg(Color::Grey),
+            code_function: fg(Color::Cyan),
+            code_meta: fg(Color::Cyan),
+            code_key: fg(Color::Red),
+
+            sidebar: fg(Color::White),
+            sidebar_waiting: fg(Color::Yellow).bold(),
+            sidebar_git: fg(Color::Grey),
+            sidebar_active: on(Color::Black, Color::White).bold(),
+            sidebar_active_git: fg(Color::Black),
+            border: fg(Color::White),
+            pane_title: fg(Color::White),
+            pane_title_focused: on(Color::Black, Color::White).bold(),
+
+            status_bar: on(Color::Black, Color::White),
+            mode_normal: fg(Color::Black).bold(),
+            mode_insert: on(Color::Black, Color::Green).bold(),
+            mode_command: on(Color::Black, Color::Yellow).bold(),
+            status_running: fg(Color::DarkMagenta).bold(),
+            status_search: fg(Color::DarkBlue),
+            status_info: fg(Color::Black),
+            command_line: on(Color::Black, Color::White),
+            flash: on(Color::Black, Color::Yellow),
+
+            banner: on(Color::Black, Color::Yellow),
+            banner_input: on(Color::White, Color::Black),
+            question: on(Color::Black, Color::Cyan),
+            menu: on(Color::White, Color::Black),
+            menu_selected: on(Color::Black, Color::White).bold(),
+            inbox_header: on(Color::Black, Color::White).bold(),
+            inbox_hint: fg(Color::White),
+            inbox_selected: on(Color::Black, Color::Cyan),
+
+            task_header: on(Color::Black, Color::White),
+            task_pending: fg(Color::White),
+            task_deleted: fg(Color::Grey),
+
+            prompt: fg(Color::Yellow).bold(),
+            prompt_inactive: fg(Color::Grey),
+
+            ..Theme::dark()
+        }
+    }
+}
+
+// ─── Custom Themes ──────────────────────────────────────────────────────────
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThemeFile {
+    /// Built-in theme the styles are replaced in
+    #[serde(default = "default_base")]
+    base: String,
+    /// Styles by name; fields left out keep the base theme's
+    #[serde(default)]
+    styles: serde_json::Map<String, serde_json::Value>,
+}
+
+fn default_base() -> String {
+    "dark".to_string()
+}
+
+impl ThemeFile {
+    fn into_theme(self, name: String) -> anyhow::Result<Theme> {
+        let base = Theme::builtin(&self.base)
+            .ok_or_else(|| anyhow::anyhow!("unknown base theme '{}'", self.base))?;
+        let mut value = serde_json::to_value(&base)?;
+        for (style, fields) in self.styles {
+            let Some(slot) = value.get_mut(&style).and_then(|v| v.as_object_mut()) else {
+                anyhow::bail!("unknown style '{}'", style);
+            };
+            match fields {
+                serde_json::Value::Object(fields) => slot.extend(fields),
+                _ => anyhow::bail!("style '{}' is not an object", style),
+            }
+        }
+        let mut theme: Theme = serde_json::from_value(value)?;
+        theme.name = name;
+        Ok(theme)
+    }
+}
+
+/// Load custom themes from ~/.companion/themes/*.json, sorted by file name
+pub fn load_custom() -> Vec<Theme> {
+    let dir = dirs::home_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(".companion")
+        .join("themes");
+    let mut paths: Vec<PathBuf> = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.extension().map(|e| e == "json").unwrap_or(false))
+            .collect(),
+        Err(_) => return Vec::new(),
+    };
+    paths.sort();
+
+    let mut themes = Vec::new();
+    for path in paths {
+        let data = match std::fs::read_to_string(&path) {
+            Ok(d) => d,
+            Err(_) => continue,
+        };
+        let name = path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let theme = serde_json::from_str::<ThemeFile>(&data)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| file.into_theme(name));
+        match theme {
+            Ok(theme) => themes.push(theme),
+            Err(e) => {
+                tracing::warn!("Failed to parse theme {:?}: {}", path, e);
+            }
+        }
+    }
+    themes
+}
+
+// ─── Color Depth ────────────────────────────────────────────────────────────
+
+/// The 16 ANSI colors in palette order
+const ANSI16: [Color; 16] = [
+    Color::Black,
+    Color::DarkRed,
+    Color::DarkGreen,
+    Color::DarkYellow,
+    Color::DarkBlue,
+    Color::DarkMagenta,
+    Color::DarkCyan,
+    Color::Grey,
+    Color::DarkGrey,
+    Color::Red,
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::White,
+];
+
+/// Channel levels of the 256-color cube
+const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// The nearest color `depth` can show. Named colors pass through; true
+/// colors go to the 256-color cube or grey ramp, or to the ANSI color of
+/// their hue and brightness.
+pub fn fit(color: Color, depth: ColorDepth) -> Color {
+    match (color, depth) {
+        (_, ColorDepth::TrueColor) => color,
+        (Color::Rgb { r, g, b }, ColorDepth::Ansi256) => Color::AnsiValue(nearest_256(r, g, b)),
+        (Color::Rgb { r, g, b }, ColorDepth::Ansi16) => nearest_16(r, g, b),
+        (Color::AnsiValue(n), ColorDepth::Ansi16) if n < 16 => ANSI16[n as usize],
+        (Color::AnsiValue(n), ColorDepth::Ansi16) => {
+            let (r, g, b) = ansi256_rgb(n);
+            nearest_16(r, g, b)
+        }
+        _ => color,
+    }
+}
+
+fn nearest_256(r: u8, g: u8, b: u8) -> u8 {
+    let level = |c: u8| {
+        (0..CUBE.len())
+            .min_by_key(|&i| (c as i32 - CUBE[i] as i32).abs())
+            .unwrap_or(0)
+    };
+    let (ri, gi, bi) = (level(r), level(g), level(b));
+    let cube = (CUBE[ri], CUBE[gi], CUBE[bi]);
+    // Grey ramp 232-255 runs from 8 to 238 in steps of 10
+    let mean = (r as i32 + g as i32 + b as i32) / 3;
+    let step = ((mean - 3) / 10).clamp(0, 23);
+    let grey = (8 + 10 * step) as u8;
+    let distance = |(r2, g2, b2): (u8, u8, u8)| {
+        [(r, r2), (g, g2), (b, b2)]
+            .iter()
+            .map(|&(a, b)| (a as i32 - b as i32).pow(2))
+            .sum::<i32>()
+    };
+    if distance((grey, grey, grey)) < distance(cube) {
+        232 + step as u8
+    } else {
+        16 + 36 * ri as u8 + 6 * gi as u8 + bi as u8
+    }
+}
+
+fn ansi256_rgb(n: u8) -> (u8, u8, u8) {
+    match n {
+        0..=15 => (0, 0, 0),
+        16..=231 => {
+            let i = n - 16;
+            (CUBE[(i / 36) as usize], CUBE[(i / 6 % 6) as usize], CUBE[(i % 6) as usize])
+        }
+        _ => {
+            let v = 8 + 10 * (n - 232);
+            (v, v, v)
+        }
+    }
+}
+
+/// Greys by brightness; other colors by hue, the bright variant when light
+fn nearest_16(r: u8, g: u8, b: u8) -> Color {
+    let max = r.max(g).max(b) as f32;
+    let min = r.min(g).min(b) as f32;
+    let value = max / 255.0;
+    let saturation = if max == 0.0 { 0.0 } else { (max - min) / max };
+    if saturation < 0.25 {
+        return match value {
+            v if v < 0.25 => Color::Black,
+            v if v < 0.6 => Color::DarkGrey,
+            v if v < 0.85 => Color::Grey,
+            _ => Color::White,
+        };
+    }
+    let (r, g, b) = (r as f32, g as f32, b as f32);
+    let hue = if max == r {
+        60.0 * (g - b) / (max - min)
+    } else if max == g {
+        60.0 * (b - r) / (max - min) + 120.0
+    } else {
+        60.0 * (r - g) / (max - min) + 240.0
+    };
+    let (dark, bright) = match hue.rem_euclid(360.0) {
+        h if !(15.0..330.0).contains(&h) => (Color::DarkRed, Color::Red),
+        // Orange
+        h if h < 38.0 => (Color::DarkYellow, Color::DarkYellow),
+        h if h < 75.0 => (Color::DarkYellow, Color::Yellow),
+        h if h < 165.0 => (Color::DarkGreen, Color::Green),
+        h if h < 195.0 => (Color::DarkCyan, Color::Cyan),
+        h if h < 255.0 => (Color::DarkBlue, Color::Blue),
+        _ => (Color::DarkMagenta, Color::Magenta),
+    };
+    if value > 0.7 {
+        bright
+    } else {
+        dark
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn custom(json: &str) -> anyhow::Result<Theme> {
+        serde_json::from_str::<ThemeFile>(json)?.into_theme("mine".to_string())
+    }
+
+    fn error(json: &str) -> String {
+        custom(json).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn custom_themes_start_from_their_base() {
+        let theme = custom(r#"{ "base": "light" }"#).unwrap();
+        assert_eq!(theme.name, "mine");
+        assert_eq!(theme, Theme { name: "mine".to_string(), ..Theme::light() });
+        // Dark when no base is named
+        assert_eq!(custom("{}").unwrap().user, Theme::dark().user);
+        assert_eq!(error(r#"{ "base": "solarized" }"#), "unknown base theme 'solarized'");
+    }
+
+    #[test]
+    fn custom_styles_replace_only_the_fields_they_list() {
+        let theme = custom(
+            r##"{ "styles": {
+                "user": { "fg": "#005f87" },
+                "code": { "bg": null },
+                "tool": { "bold": true }
+            } }"##,
+        )
+        .unwrap();
+        let dark = Theme::dark();
+        assert_eq!(theme.user.fg, Some(rgb(0, 95, 135)));
+        assert!(theme.user.bold);
+        assert_eq!(theme.code, Style { bg: None, ..dark.code });
+        assert_eq!(theme.tool, dark.tool.bold());
+        assert_eq!(theme.assistant, dark.assistant);
+    }
+
+    #[test]
+    fn bad_custom_styles_are_rejected() {
+        assert_eq!(error(r#"{ "styles": { "usr": {} } }"#), "unknown style 'usr'");
+        assert_eq!(error(r#"{ "styles": { "name": {} } }"#), "unknown style 'name'");
+        assert_eq!(
+            error(r#"{ "styles": { "user": "green" } }"#),
+            "style 'user' is not an object"
+        );
+        assert!(custom(r#"{ "styles": { "user": { "color": "green" } } }"#).is_err());
+        assert!(custom(r##"{ "styles": { "user": { "fg": "#05f" } } }"##).is_err());
+    }
+
+    #[test]
+    fn true_colors_fit_the_cube_or_the_grey_ramp() {
+        assert_eq!(nearest_256(255, 0, 0), 196);
+        assert_eq!(nearest_256(95, 135, 175), 67);
+        assert_eq!(nearest_256(0, 0, 0), 16);
+        assert_eq!(nearest_256(255, 255, 255), 231);
+        // Greys between the cube's levels go to the ramp
+        assert_eq!(nearest_256(30, 30, 30), 234);
+        assert_eq!(nearest_256(128, 128, 128), 244);
+        assert_eq!(nearest_256(130, 126, 128), 244);
+        // A tint the ramp can't show stays in the cube
+        assert_eq!(nearest_256(135, 95, 95), 95);
+    }
+
+    #[test]
+    fn palette_colors_round_trip() {
+        assert_eq!(ansi256_rgb(196), (255, 0, 0));
+        assert_eq!(ansi256_rgb(67), (95, 135, 175));
+        assert_eq!(ansi256_rgb(232), (8, 8, 8));
+        assert_eq!(ansi256_rgb(255), (238, 238, 238));
+        for n in 16..=255 {
+            let (r, g, b) = ansi256_rgb(n);
+            assert_eq!(nearest_256(r, g, b), n);
+        }
+    }
+
+    #[test]
+    fn true_colors_fit_sixteen_by_hue_and_brightness() {
+        assert_eq!(nearest_16(0, 0, 0), Color::Black);
+        assert_eq!(nearest_16(100, 100, 100), Color::DarkGrey);
+        assert_eq!(nearest_16(180, 180, 180), Color::Grey);
+        assert_eq!(nearest_16(240, 240, 240), Color::White);
+        // Slightly tinted is still grey
+        assert_eq!(nearest_16(100, 90, 80), Color::DarkGrey);
+
+        assert_eq!(nearest_16(200, 0, 0), Color::Red);
+        assert_eq!(nearest_16(120, 0, 0), Color::DarkRed);
+        assert_eq!(nearest_16(255, 0, 40), Color::Red);
+        assert_eq!(nearest_16(255, 128, 0), Color::DarkYellow);
+        assert_eq!(nearest_16(255, 215, 0), Color::Yellow);
+        assert_eq!(nearest_16(0, 200, 0), Color::Green);
+        assert_eq!(nearest_16(0, 120, 0), Color::DarkGreen);
+        assert_eq!(nearest_16(0, 200, 200), Color::Cyan);
+        assert_eq!(nearest_16(0, 0, 255), Color::Blue);
+        assert_eq!(nearest_16(0, 0, 120), Color::DarkBlue);
+        assert_eq!(nearest_16(255, 0, 255), Color::Magenta);
+    }
+
+    #[test]
+    fn fit_only_changes_what_the_terminal_cant_show() {
+        let orange = rgb(255, 128, 0);
+        assert_eq!(fit(orange, ColorDepth::TrueColor), orange);
+        assert_eq!(fit(orange, ColorDepth::Ansi256), Color::AnsiValue(208));
+        assert_eq!(fit(orange, ColorDepth::Ansi16), Color::DarkYellow);
+        assert_eq!(fit(Color::Cyan, ColorDepth::Ansi256), Color::Cyan);
+        assert_eq!(fit(Color::Cyan, ColorDepth::Ansi16), Color::Cyan);
+        assert_eq!(fit(Color::AnsiValue(9), ColorDepth::Ansi16), Color::Red);
+        assert_eq!(fit(Color::AnsiValue(196), ColorDepth::Ansi16), Color::Red);
+        assert_eq!(fit(Color::AnsiValue(234), ColorDepth::Ansi16), Color::Black);
+        assert_eq!(fit(Color::AnsiValue(196), ColorDepth::Ansi256), Color::AnsiValue(196));
+    }
+}